@@ -0,0 +1,61 @@
+//! Arcball rotation, turning drags across the screen into rotations as if rolling a ball under the cursor.
+//!
+//! Shared by the CPU and GPU viewers, so that dragging with the mouse feels the same in both.
+
+use nalgebra::{UnitQuaternion, Vector3};
+
+/// Point on the unit ball under a point on the screen, with `z` pointing towards the viewer
+/// The screen point is measured from the centre of the ball in units of its radius, with `y` pointing up
+/// Points outside the ball are moved onto its rim, so dragging around the outside spins about the viewing axis
+pub fn ball_point(x: f32, y: f32) -> Vector3<f32> {
+    let distance_squared = x * x + y * y;
+    if distance_squared <= 1.0 {
+        Vector3::new(x, y, (1.0 - distance_squared).sqrt())
+    } else {
+        let distance = distance_squared.sqrt();
+        Vector3::new(x / distance, y / distance, 0.0)
+    }
+}
+
+/// Convert from clip space to the units of `ball_point`, where the ball fills the shorter side of the screen
+/// `aspect` is the ratio of width to height of the screen
+pub fn clip_to_ball(x_clip: f32, y_clip: f32, aspect: f32) -> (f32, f32) {
+    if aspect >= 1.0 {
+        (x_clip * aspect, y_clip)
+    } else {
+        (x_clip, y_clip / aspect)
+    }
+}
+
+/// Rotation in view space carrying the point on the ball under `from` to the point under `to`
+pub fn rotation(from: (f32, f32), to: (f32, f32)) -> UnitQuaternion<f32> {
+    UnitQuaternion::rotation_between(&ball_point(from.0, from.1), &ball_point(to.0, to.1))
+        .unwrap_or_else(UnitQuaternion::identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ball_point() {
+        assert_eq!(ball_point(0.0, 0.0), Vector3::z());
+        assert!((ball_point(0.6, 0.0) - Vector3::new(0.6, 0.0, 0.8)).norm() < 1e-6);
+        // Points outside the ball lie on the rim
+        assert!((ball_point(0.0, -3.0) - -Vector3::y()).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_rotation() {
+        assert_eq!(rotation((0.3, 0.2), (0.3, 0.2)), UnitQuaternion::identity());
+
+        // Dragging right turns the front of the ball to the right, about the vertical axis
+        let (axis, angle) = rotation((0.0, 0.0), (0.5, 0.0)).axis_angle().unwrap();
+        assert!((axis.into_inner() - Vector3::y()).norm() < 1e-6);
+        assert!((angle - 0.5f32.asin()).abs() < 1e-5);
+
+        // Dragging around the rim spins about the viewing axis
+        let (axis, _) = rotation((2.0, 0.0), (0.0, 2.0)).axis_angle().unwrap();
+        assert!((axis.into_inner() - Vector3::z()).norm() < 1e-6);
+    }
+}
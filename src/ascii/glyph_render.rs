@@ -1,19 +1,113 @@
 //! Rendering fonts such that we can later learn the mappings
-use ab_glyph::{point, Font, FontRef, Glyph, OutlinedGlyph};
+use ab_glyph::{point, Font, FontArc, FontRef, Glyph, InvalidFont, OutlinedGlyph};
 use core::f32;
 use image::{ImageBuffer, Rgba};
 use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
 
-// TODO See if this can be made a structure constant even when AsciiMatrices is generic
-// TODO Check if it is actually this number
+/// Number of printable ASCII characters, making up the default glyph set
 pub const NUM_ASCII_MATRICES: usize = 95;
 
-// TODO Make this into a sensible function
+/// Maximum number of glyphs in a glyph set
+/// Limited because compute shaders write the glyph index into a single `u8` channel
+pub const MAX_GLYPHS: usize = 256;
+
+#[derive(Error, Debug)]
+pub enum GlyphError {
+    #[error("Could not read font file.")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse font file.")]
+    InvalidFont(#[from] InvalidFont),
+    #[error("Glyph set must contain at least one visible character.")]
+    EmptyGlyphSet,
+    #[error("Glyph set has {0} characters but at most {MAX_GLYPHS} are supported.")]
+    TooManyGlyphs(usize),
+}
+
 // TODO Also put this in the resources of the package
+/// Get the font bundled with the package
 pub fn get_font() -> impl Font {
     FontRef::try_from_slice(include_bytes!("../../data/FiraCode-Regular.ttf")).unwrap()
 }
 
+/// Load a TTF or OTF font from a path at runtime
+/// Useful for matching glyph shapes to the font used by the terminal emulator
+pub fn load_font<Q: AsRef<Path>>(path: Q) -> Result<FontArc, GlyphError> {
+    let data = std::fs::read(path)?;
+    Ok(FontArc::try_from_vec(data)?)
+}
+
+/// Set of characters that a rasterizer is allowed to output
+/// Always contains a space, which is used when no other glyph matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlyphSet {
+    symbols: Vec<char>,
+}
+
+impl GlyphSet {
+    /// Create a glyph set from arbitrary characters, ignoring duplicates and control characters
+    pub fn new<I: IntoIterator<Item = char>>(symbols: I) -> Result<Self, GlyphError> {
+        let mut symbols: Vec<char> = symbols
+            .into_iter()
+            .filter(|c| !c.is_control())
+            .chain(std::iter::once(' '))
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+        if symbols.len() < 2 {
+            return Err(GlyphError::EmptyGlyphSet);
+        }
+        if symbols.len() > MAX_GLYPHS {
+            return Err(GlyphError::TooManyGlyphs(symbols.len()));
+        }
+        Ok(Self { symbols })
+    }
+    /// All printable ASCII characters
+    /// !"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\]^_`abcdefghijklmnopqrstuvwxyz{|}~
+    pub fn ascii() -> Self {
+        Self::new((32..=126u8).map(|i| i as char)).unwrap()
+    }
+    /// Unicode block elements, e.g. ▀▄█▌▐░▒▓
+    pub fn blocks() -> Self {
+        Self::new('\u{2580}'..='\u{259F}').unwrap()
+    }
+    /// Unicode box-drawing characters, e.g. ─│┌┐└┘├┤┬┴┼
+    pub fn box_drawing() -> Self {
+        Self::new('\u{2500}'..='\u{257F}').unwrap()
+    }
+    pub fn symbols(&self) -> &[char] {
+        &self.symbols[..]
+    }
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+impl Default for GlyphSet {
+    fn default() -> Self {
+        Self::ascii()
+    }
+}
+
+/// Parse either the name of a preset (`ascii`, `blocks`, `box`) or a literal string of characters
+impl FromStr for GlyphSet {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Self::ascii()),
+            "blocks" => Ok(Self::blocks()),
+            "box" => Ok(Self::box_drawing()),
+            _ => Self::new(s.chars()),
+        }
+    }
+}
+
 /// Get ASCII characters to debug
 /// !"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\]^_`abcdefghijklmnopqrstuvwxyz{|}~
 pub fn get_ascii_from_font<F: Font>(font: &F, grid_size: u32) -> Vec<Glyph> {
@@ -59,7 +153,7 @@ impl From<f32> for AsciiPixelPadded {
     }
 }
 
-/// Holds all the intensity matrices for all the printable ASCII characters
+/// Holds the intensity matrix for a single glyph
 /// `W` and `H` are the number of horizontal and vertical pixels assigned to one glyph
 #[derive(Debug)]
 pub struct GlyphMatrix<const W: usize, const H: usize> {
@@ -88,8 +182,13 @@ impl<const W: usize, const H: usize> GlyphMatrix<W, H> {
             v_offset = Some(0usize);
             h_offset = Some(0usize);
             go.draw(|x, y, c| {
-                // FIXME Be careful of out of bounds errors here
-                default_matrix[y as usize][x as usize] = c;
+                // Wide glyphs (e.g. box-drawing characters) may spill outside of the cell
+                if let Some(p) = default_matrix
+                    .get_mut(y as usize)
+                    .and_then(|row| row.get_mut(x as usize))
+                {
+                    *p = c;
+                }
             });
         }
         Self {
@@ -118,8 +217,14 @@ impl<const W: usize, const H: usize> GlyphMatrix<W, H> {
         self.matrix = [[0f32; W]; H];
         if let Some(go) = self.glyph_outline.as_ref() {
             go.draw(|x, y, c| {
-                // FIXME Be careful about out of bounds errors here
-                self.matrix[y as usize][x as usize] = 1.0 - c;
+                // Wide glyphs (e.g. box-drawing characters) may spill outside of the cell
+                if let Some(p) = self
+                    .matrix
+                    .get_mut(y as usize)
+                    .and_then(|row| row.get_mut(x as usize))
+                {
+                    *p = 1.0 - c;
+                }
             });
         }
     }
@@ -209,13 +314,19 @@ pub struct AsciiMatrices<const W: usize, const H: usize> {
 }
 
 impl<const W: usize, const H: usize> AsciiMatrices<W, H> {
-    /// Bare constructor for glyph matrices
+    /// Bare constructor for glyph matrices of the printable ASCII characters
     /// Will do horizontal and vertical centering
     pub fn new<F: Font>(font: &F) -> Self {
-        // TODO Define this range near the constants
-        let ascii_symbols: Vec<char> = (32..=126u8).map(|i| i as char).collect();
+        Self::with_glyphs(font, &GlyphSet::ascii())
+    }
+    /// Constructor for glyph matrices of an arbitrary glyph set
+    /// Characters missing from the font are skipped rather than drawn as a placeholder box
+    pub fn with_glyphs<F: Font>(font: &F, glyph_set: &GlyphSet) -> Self {
         let mut glyph_matrices = BTreeMap::new();
-        for symbol in ascii_symbols.into_iter() {
+        for &symbol in glyph_set.symbols() {
+            if symbol != ' ' && font.glyph_id(symbol).0 == 0 {
+                continue;
+            }
             let glyph_matrix = GlyphMatrix::<W, H>::new(font, symbol);
 
             glyph_matrices.insert(symbol, glyph_matrix);
@@ -225,15 +336,28 @@ impl<const W: usize, const H: usize> AsciiMatrices<W, H> {
         out.h_center();
         out
     }
+    /// Characters in the same order as the exported matrices and statistics
+    pub fn symbols(&self) -> Vec<char> {
+        self.glyph_matrices.keys().copied().collect()
+    }
+    /// Number of glyphs held
+    pub fn len(&self) -> usize {
+        self.glyph_matrices.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.glyph_matrices.is_empty()
+    }
     /// Center the glyphs vertically, so that they are consistent and lie in the middle
     /// Needed because by default, each glyph is drawn with its highest point up against the top of the cell
     pub fn v_center(&mut self) {
-        let top = self
+        let Some(top) = self
             .glyph_matrices
             .iter()
             .filter_map(|(_, gm)| gm.internal_min_y())
             .reduce(f32::min)
-            .unwrap();
+        else {
+            return;
+        };
         for (_, gm) in self.glyph_matrices.iter_mut() {
             if !gm.is_blank() {
                 let offset = (gm.internal_min_y().unwrap() - top).round() as usize;
@@ -258,12 +382,14 @@ impl<const W: usize, const H: usize> AsciiMatrices<W, H> {
     }
     /// Center the glyphs horizontally, so that kerning is respected and lie in the middle of a cell
     pub fn h_center(&mut self) {
-        let left = self
+        let Some(left) = self
             .glyph_matrices
             .iter()
             .filter_map(|(_, gm)| gm.internal_min_x())
             .reduce(f32::min)
-            .unwrap();
+        else {
+            return;
+        };
         for (_, gm) in self.glyph_matrices.iter_mut() {
             if !gm.is_blank() {
                 let offset = (gm.internal_min_x().unwrap() - left).round() as usize;
@@ -279,23 +405,16 @@ impl<const W: usize, const H: usize> AsciiMatrices<W, H> {
         }
     }
     /// Export the ASCII matrices as a 3D texture
-    pub fn padded_matrix_list(&self) -> [[[AsciiPixelPadded; W]; H]; NUM_ASCII_MATRICES] {
+    pub fn padded_matrix_list(&self) -> Vec<[[AsciiPixelPadded; W]; H]> {
         self.glyph_matrices
             .values()
             .map(|gm| gm.padded_matrix())
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap()
+            .collect()
     }
     /// Calculate the mean and standard deviation of every ASCII matrix
     /// Useful for the rasterizer which uses SSIM
-    pub fn matrix_stats(&self) -> [AsciiStats; NUM_ASCII_MATRICES] {
-        self.glyph_matrices
-            .values()
-            .map(|gm| gm.stats())
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap()
+    pub fn matrix_stats(&self) -> Vec<AsciiStats> {
+        self.glyph_matrices.values().map(|gm| gm.stats()).collect()
     }
 }
 
@@ -317,4 +436,37 @@ mod tests {
         // let rand = ascii_matrices.glyph_matrices.get(&'a');
         // TODO Write some check using this
     }
+
+    #[test]
+    fn test_unicode_glyph_set() {
+        let font = get_font();
+        let glyph_set: GlyphSet = "blocks".parse().unwrap();
+        assert_eq!(glyph_set.symbols()[0], ' ');
+
+        let ascii_matrices = AsciiMatrices::<8, 16>::with_glyphs(&font, &glyph_set);
+        assert!(ascii_matrices.len() > 1);
+        assert!(ascii_matrices.len() <= glyph_set.len());
+        assert_eq!(ascii_matrices.matrix_stats().len(), ascii_matrices.len());
+        assert_eq!(ascii_matrices.symbols()[0], ' ');
+    }
+
+    #[test]
+    fn test_glyph_set_errors() {
+        assert!(matches!(
+            GlyphSet::new("   ".chars()),
+            Err(GlyphError::EmptyGlyphSet)
+        ));
+        assert!(matches!(
+            GlyphSet::new('\u{4E00}'..='\u{4FFF}'),
+            Err(GlyphError::TooManyGlyphs(_))
+        ));
+        assert!(matches!(
+            load_font("./data/does_not_exist.ttf"),
+            Err(GlyphError::Io(_))
+        ));
+        assert!(matches!(
+            load_font("./data/rbd.pdb"),
+            Err(GlyphError::InvalidFont(_))
+        ));
+    }
 }
@@ -1,3 +1,4 @@
+use crate::dither::{dither_intensities, Dithering};
 use crate::rasterizer::{ColoredChar, ColoredPixel, Rasterizer, RasterizerError};
 use ratatui::style::Color;
 
@@ -14,6 +15,7 @@ pub struct BasicAsciiRasterizer {
     gradient: Vec<char>,
     ranges: Vec<(f32, f32)>,
     background: char,
+    dithering: Dithering,
 }
 
 impl BasicAsciiRasterizer {
@@ -41,6 +43,7 @@ impl BasicAsciiRasterizer {
                 gradient,
                 ranges,
                 background,
+                dithering: Dithering::default(),
             }),
             Err(e) => Err(e),
         }
@@ -106,10 +109,21 @@ impl Rasterizer for BasicAsciiRasterizer {
         // Add one per row to account for newline character
         let total_chars = pixels.len() + (pixels.len() / output_width);
         let mut out: Vec<ColoredChar> = Vec::with_capacity(total_chars);
+
+        let mut intensities: Vec<f32> = pixels.iter().map(|chunk| chunk[0].intensity).collect();
+        dither_intensities(&mut intensities, output_width, &self.ranges, self.dithering);
+
         // Reverse because small coord means small index, but the top of the screen should have large y
-        for row in pixels.chunks(output_width).rev() {
-            for chunk in row.iter() {
-                let pixel = chunk[0];
+        for (row, row_intensities) in pixels
+            .chunks(output_width)
+            .zip(intensities.chunks(output_width))
+            .rev()
+        {
+            for (chunk, intensity) in row.iter().zip(row_intensities.iter()) {
+                let pixel = ColoredPixel {
+                    intensity: *intensity,
+                    color: chunk[0].color,
+                };
                 let ascii = self.pixel_to_char(pixel);
                 out.push(ascii);
             }
@@ -127,6 +141,12 @@ impl Rasterizer for BasicAsciiRasterizer {
     fn grid_width(&self) -> usize {
         1
     }
+    fn dithering(&self) -> Dithering {
+        self.dithering
+    }
+    fn set_dithering(&mut self, dithering: Dithering) {
+        self.dithering = dithering;
+    }
 }
 
 #[cfg(test)]
@@ -168,4 +188,20 @@ mod tests {
         let rasterizer = BasicAsciiRasterizer::new(gradient, thresholds, ' ');
         assert!(rasterizer.is_err_and(|x| x == RasterizerError::GradientNotMatchingThresholds));
     }
+
+    #[test]
+    fn test_dithering_keeps_background() {
+        let mut rasterizer = BasicAsciiRasterizer::default();
+        rasterizer.set_dithering(Dithering::FloydSteinberg);
+        let pixels = [0.45, 1.1, 0.45, 0.45].map(ColoredPixel::from);
+        let chunks: Vec<&[ColoredPixel]> = pixels.chunks(1).collect();
+        let chars = rasterizer.pixels_to_stdout(chunks, 2);
+
+        // Rows are reversed, so the background pixel lands on the second line
+        let symbols: Vec<char> = chars.iter().map(|cc| cc.symbol).collect();
+        assert_eq!(symbols[4], rasterizer.background);
+        for i in [0, 1, 3] {
+            assert_ne!(symbols[i], rasterizer.background);
+        }
+    }
 }
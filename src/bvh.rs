@@ -0,0 +1,230 @@
+//! Bounding volume hierarchy over the shapes of a scene, so that each ray is only tested against shapes it might hit.
+
+use crate::{
+    scene::{ColoredShape, ObjectId, Visibility},
+    surface::ValidShape,
+};
+use parry3d::{
+    bounding_volume::SimdAabb,
+    math::{SimdBool, SimdReal, SIMD_WIDTH},
+    partitioning::{Qbvh, QbvhUpdateWorkspace, SimdBestFirstVisitStatus, SimdBestFirstVisitor},
+    query::{Ray, RayCast, RayIntersection, SimdRay},
+    simba::simd::{SimdBool as _, SimdPartialOrd, SimdValue},
+};
+
+/// Hierarchy of the world-space bounding boxes of every shape in a scene, where each leaf is the index of a shape
+#[derive(Default)]
+pub struct SceneBvh {
+    qbvh: Qbvh<usize>,
+    workspace: QbvhUpdateWorkspace,
+}
+
+impl std::fmt::Debug for SceneBvh {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SceneBvh")
+            .field("qbvh", &self.qbvh)
+            .finish()
+    }
+}
+
+impl SceneBvh {
+    /// Build a hierarchy from scratch
+    /// Needed whenever shapes are added or removed
+    pub fn rebuild<S: ValidShape>(&mut self, shapes: &[ColoredShape<S>]) {
+        let leaves = shapes
+            .iter()
+            .enumerate()
+            .map(|(i, cs)| (i, cs.shape.world_aabb(&cs.world_transform)));
+        self.qbvh.clear_and_rebuild(leaves, 0.0);
+    }
+    /// Update the bounding boxes after the shapes have moved, keeping the same tree structure
+    /// Cheaper than rebuilding, but the tree may become less efficient if shapes move far apart
+    pub fn refit<S: ValidShape>(&mut self, shapes: &[ColoredShape<S>]) {
+        for i in 0..shapes.len() {
+            self.qbvh.pre_update_or_insert(i);
+        }
+        self.qbvh.refit(0.0, &mut self.workspace, |i| {
+            let cs = &shapes[*i];
+            cs.shape.world_aabb(&cs.world_transform)
+        });
+    }
+    /// Find the nearest intersection of a ray with the visible shapes, returning the index of the shape that was hit
+    /// Branches of the tree further away than the nearest hit so far are skipped
+    pub fn cast_ray<S: RayCast + ValidShape>(
+        &self,
+        shapes: &[ColoredShape<S>],
+        ray: &Ray,
+        max_toi: f32,
+        solid: bool,
+        visibility: &Visibility,
+    ) -> Option<(usize, RayIntersection)> {
+        if shapes.is_empty() {
+            return None;
+        }
+        let mut visitor = NearestHitVisitor {
+            shapes,
+            ray,
+            simd_ray: SimdRay::splat(*ray),
+            max_toi,
+            solid,
+            visibility,
+        };
+        self.qbvh
+            .traverse_best_first(&mut visitor)
+            .map(|(_, result)| result)
+    }
+}
+
+/// Best-first search for the nearest hit, where the cost of each node is the time-of-impact of its bounding box
+struct NearestHitVisitor<'a, S> {
+    shapes: &'a [ColoredShape<S>],
+    ray: &'a Ray,
+    simd_ray: SimdRay,
+    max_toi: f32,
+    solid: bool,
+    visibility: &'a Visibility,
+}
+
+impl<S: RayCast + ValidShape> SimdBestFirstVisitor<usize, SimdAabb> for NearestHitVisitor<'_, S> {
+    type Result = (usize, RayIntersection);
+
+    fn visit(
+        &mut self,
+        best_cost_so_far: f32,
+        bv: &SimdAabb,
+        data: Option<[Option<&usize>; SIMD_WIDTH]>,
+    ) -> SimdBestFirstVisitStatus<Self::Result> {
+        let (hit, toi) = bv.cast_local_ray(&self.simd_ray, SimdReal::splat(self.max_toi));
+        let Some(data) = data else {
+            // Internal node, so carry on into any children whose boxes are hit
+            return SimdBestFirstVisitStatus::MaybeContinue {
+                weights: toi,
+                mask: hit,
+                results: [None; SIMD_WIDTH],
+            };
+        };
+
+        let mut weights = [0.0; SIMD_WIDTH];
+        let mut mask = [false; SIMD_WIDTH];
+        let mut results = [None; SIMD_WIDTH];
+        let bitmask = (hit & toi.simd_lt(SimdReal::splat(best_cost_so_far))).bitmask();
+        for (lane, index) in data.iter().enumerate() {
+            let Some(&index) = index else {
+                continue;
+            };
+            if bitmask & (1 << lane) == 0 || self.visibility.is_shape_hidden(index) {
+                continue;
+            }
+            let cs = &self.shapes[index];
+            let hit = if self.visibility.has_hidden_parts(index) {
+                let visible = |part| {
+                    self.visibility.is_visible(ObjectId {
+                        shape: index,
+                        sub_shape: Some(part),
+                    })
+                };
+                cs.shape
+                    .cast_ray_to_visible_part(
+                        &cs.world_transform,
+                        self.ray,
+                        self.max_toi,
+                        self.solid,
+                        &visible,
+                    )
+                    .map(|(intersection, _)| intersection)
+            } else {
+                cs.shape.cast_ray_and_get_normal(
+                    &cs.world_transform,
+                    self.ray,
+                    self.max_toi,
+                    self.solid,
+                )
+            };
+            if let Some(intersection) = hit {
+                weights[lane] = intersection.toi;
+                mask[lane] = true;
+                results[lane] = Some((index, intersection));
+            }
+        }
+        SimdBestFirstVisitStatus::MaybeContinue {
+            weights: SimdReal::from(weights),
+            mask: SimdBool::from(mask),
+            results,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{Isometry3, Point3, Vector3};
+    use parry3d::shape::Ball;
+    use ratatui::style::Color;
+
+    fn balls(positions: &[f32]) -> Vec<ColoredShape<Ball>> {
+        positions
+            .iter()
+            .map(|x| ColoredShape {
+                shape: Ball::new(1.0),
+                world_transform: Isometry3::translation(*x, 0.0, 0.0),
+                color: Color::Red,
+            })
+            .collect()
+    }
+
+    #[test]
+    /// The hierarchy should give the same nearest hit as testing every shape
+    fn test_nearest_hit() {
+        let shapes = balls(&[0.0, 5.0, 10.0, 15.0, 20.0, 25.0, 30.0]);
+        let mut bvh = SceneBvh::default();
+        bvh.rebuild(&shapes);
+        let mut visibility = Visibility::default();
+
+        let ray = Ray::new(Point3::new(100.0, 0.0, 0.0), -Vector3::x());
+        let (index, intersection) = bvh
+            .cast_ray(&shapes, &ray, f32::MAX, true, &visibility)
+            .unwrap();
+        assert_eq!(index, 6);
+        assert!((intersection.toi - 69.0).abs() < 1e-4);
+
+        let miss = Ray::new(Point3::new(100.0, 5.0, 0.0), -Vector3::x());
+        assert!(bvh
+            .cast_ray(&shapes, &miss, f32::MAX, true, &visibility)
+            .is_none());
+
+        // Hidden shapes are passed through
+        let hidden = ObjectId {
+            shape: 6,
+            sub_shape: None,
+        };
+        visibility.set_visible(hidden, false);
+        let (index, _) = bvh
+            .cast_ray(&shapes, &ray, f32::MAX, true, &visibility)
+            .unwrap();
+        assert_eq!(index, 5);
+    }
+
+    #[test]
+    fn test_refit() {
+        let mut shapes = balls(&[0.0, 5.0, 10.0, 15.0, 20.0]);
+        let mut bvh = SceneBvh::default();
+        bvh.rebuild(&shapes);
+        let visibility = Visibility::default();
+
+        // Move everything upwards so the original ray misses, but a raised one hits
+        for cs in shapes.iter_mut() {
+            cs.world_transform = Isometry3::translation(0.0, 10.0, 0.0) * cs.world_transform;
+        }
+        bvh.refit(&shapes);
+
+        let ray = Ray::new(Point3::new(-100.0, 0.0, 0.0), Vector3::x());
+        assert!(bvh
+            .cast_ray(&shapes, &ray, f32::MAX, true, &visibility)
+            .is_none());
+        let ray = Ray::new(Point3::new(-100.0, 10.0, 0.0), Vector3::x());
+        let (index, _) = bvh
+            .cast_ray(&shapes, &ray, f32::MAX, true, &visibility)
+            .unwrap();
+        assert_eq!(index, 0);
+    }
+}
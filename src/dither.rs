@@ -0,0 +1,223 @@
+//! Dithering of pixel intensities before they are quantised to gradient characters.
+//!
+//! Hard thresholding causes visible banding on smoothly curving surfaces, so these spread the quantisation error around.
+
+/// Dithering applied before quantising intensities
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dithering {
+    /// Hard thresholding
+    #[default]
+    None,
+    /// Error diffusion to four neighbours, conserving all of the error
+    FloydSteinberg,
+    /// Error diffusion to six neighbours, only passing on three quarters of the error
+    Atkinson,
+    /// Ordered dithering with a 4x4 Bayer matrix
+    Bayer,
+}
+
+impl Dithering {
+    /// Get the next dithering mode, useful for cycling through modes at runtime
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::FloydSteinberg,
+            Self::FloydSteinberg => Self::Atkinson,
+            Self::Atkinson => Self::Bayer,
+            Self::Bayer => Self::None,
+        }
+    }
+    /// Code prepended to compute shaders
+    /// Error diffusion is inherently sequential, so the compute shaders fall back to ordered dithering
+    pub fn shader_code(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::FloydSteinberg | Self::Atkinson | Self::Bayer => 1,
+        }
+    }
+}
+
+/// 4x4 Bayer matrix, indexed by `[y][x]`
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Error diffusion weights as `(dx, dy, weight)`
+const FLOYD_STEINBERG: [(isize, usize, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+const ATKINSON: [(isize, usize, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Threshold from the Bayer matrix, lying in the range `0.0..1.0`
+pub fn bayer_threshold(x: usize, y: usize) -> f32 {
+    (BAYER_4X4[y % 4][x % 4] as f32 + 0.5) / 16.0
+}
+
+/// Find the midpoint and width of the range that an intensity falls into
+/// Intensities outside of all ranges get clamped to the first or last range
+fn quantise(intensity: f32, ranges: &[(f32, f32)]) -> (f32, f32) {
+    let (min, max) = ranges
+        .iter()
+        .find(|(l, u)| intensity > *l && intensity <= *u)
+        .or_else(|| {
+            if intensity <= ranges[0].0 {
+                ranges.first()
+            } else {
+                ranges.last()
+            }
+        })
+        .copied()
+        .unwrap();
+    ((min + max) / 2.0, max - min)
+}
+
+/// Dither a row-major buffer of intensities in place, given the ranges used for quantisation.
+/// Every pixel inside the ranges is replaced by the midpoint of the range that it should be quantised to.
+/// Pixels outside of all ranges (e.g. the background) are left untouched and do not receive any error.
+pub fn dither_intensities(
+    intensities: &mut [f32],
+    width: usize,
+    ranges: &[(f32, f32)],
+    dithering: Dithering,
+) {
+    if ranges.is_empty() || width == 0 {
+        return;
+    }
+    let (lowest, highest) = (ranges[0].0, ranges[ranges.len() - 1].1);
+    let foreground: Vec<bool> = intensities
+        .iter()
+        .map(|&i| i > lowest && i <= highest)
+        .collect();
+    let height = intensities.len() / width;
+
+    let kernel: &[(isize, usize, f32)] = match dithering {
+        Dithering::FloydSteinberg => &FLOYD_STEINBERG,
+        Dithering::Atkinson => &ATKINSON,
+        _ => &[],
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+            if !foreground[idx] {
+                continue;
+            }
+            let value = intensities[idx];
+            let (level, _) = match dithering {
+                Dithering::Bayer => {
+                    let (_, range_width) = quantise(value, ranges);
+                    quantise(value + (bayer_threshold(x, y) - 0.5) * range_width, ranges)
+                }
+                _ => quantise(value, ranges),
+            };
+            intensities[idx] = level;
+
+            let error = value - level;
+            for &(dx, dy, weight) in kernel.iter() {
+                let (nx, ny) = (x as isize + dx, y + dy);
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    continue;
+                }
+                let n_idx = ny * width + nx as usize;
+                if foreground[n_idx] {
+                    intensities[n_idx] += error * weight;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_ranges() -> Vec<(f32, f32)> {
+        vec![(0.0, 0.25), (0.25, 0.5), (0.5, 0.75), (0.75, 1.0)]
+    }
+
+    #[test]
+    fn test_bayer_thresholds_unique() {
+        let mut thresholds: Vec<f32> = (0..4)
+            .flat_map(|y| (0..4).map(move |x| bayer_threshold(x, y)))
+            .collect();
+        thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        thresholds.dedup();
+        assert_eq!(thresholds.len(), 16);
+        assert!(thresholds.iter().all(|t| *t > 0.0 && *t < 1.0));
+    }
+
+    #[test]
+    fn test_background_untouched() {
+        let mut intensities = vec![0.4, 1.1, 0.4, 1.1];
+        dither_intensities(
+            &mut intensities,
+            2,
+            &test_ranges(),
+            Dithering::FloydSteinberg,
+        );
+        assert_eq!(intensities[1], 1.1);
+        assert_eq!(intensities[3], 1.1);
+    }
+
+    #[test]
+    /// A flat field lying between two levels should be spread across both of them, preserving the mean
+    /// Atkinson only passes on three quarters of the error, so it doesn't keep the mean
+    fn test_dithering_preserves_mean() {
+        let (width, height) = (16, 16);
+        let flat = 0.5;
+        for dithering in [Dithering::FloydSteinberg, Dithering::Bayer] {
+            let mut intensities = vec![flat; width * height];
+            dither_intensities(&mut intensities, width, &test_ranges(), dithering);
+
+            let mean = intensities.iter().sum::<f32>() / intensities.len() as f32;
+            assert!(
+                (mean - flat).abs() < 0.02,
+                "{:?} gave mean {}",
+                dithering,
+                mean
+            );
+            assert!(intensities.iter().any(|i| *i < flat));
+            assert!(intensities.iter().any(|i| *i > flat));
+        }
+    }
+
+    #[test]
+    /// Atkinson passes error two pixels along and two rows down, where Floyd-Steinberg never reaches
+    fn test_atkinson_diffusion() {
+        // Only the first pixel can pass error to the others, which lie just below the top range
+        let background = 2.0;
+        let start = [
+            [0.74, background, 0.745],
+            [background, background, background],
+            [0.745, background, background],
+        ]
+        .concat();
+        let dither = |dithering| {
+            let mut intensities = start.clone();
+            dither_intensities(&mut intensities, 3, &test_ranges(), dithering);
+            intensities
+        };
+        // An eighth of the error is enough to push them up a level
+        let atkinson = dither(Dithering::Atkinson);
+        assert_eq!(
+            (atkinson[0], atkinson[2], atkinson[6]),
+            (0.625, 0.875, 0.875)
+        );
+        let floyd_steinberg = dither(Dithering::FloydSteinberg);
+        assert_eq!((floyd_steinberg[2], floyd_steinberg[6]), (0.625, 0.625));
+    }
+
+    #[test]
+    fn test_no_dithering_is_thresholding() {
+        let mut intensities = vec![0.1, 0.3, 0.6, 0.9];
+        dither_intensities(&mut intensities, 4, &test_ranges(), Dithering::None);
+        assert_eq!(intensities, vec![0.125, 0.375, 0.625, 0.875]);
+    }
+}
@@ -1,5 +1,13 @@
 // Compute shader for turning rendered pixels into ASCII characters
 
+// Following constants should be prepended during `wgpu::ShaderSource::Wgsl`
+// const grid_width: u32 = 1u;
+// const grid_height: u32 = 1u;
+// const dithering: u32 = 0u;
+
+// Spacing between thresholds, used to scale the ordered dithering
+const dither_spread: f32 = 0.1;
+
 // ASCII codes used for rasterizer 
 const codes = array<u32, 10>(
 64u, // '@'
@@ -60,6 +68,16 @@ fn find_best_code(intensity: f32) -> u32 {
     return code;
 }
 
+/// Threshold from a 4x4 Bayer matrix, lying in the range 0.0..1.0
+/// Calculated by interleaving bits rather than indexing, but matches `dither::bayer_threshold`
+fn bayer_threshold(coord: vec2<u32>) -> f32 {
+    let x = coord.x % 4u;
+    let y = coord.y % 4u;
+    let xy = x ^ y;
+    let index = ((xy & 1u) << 3u) | ((y & 1u) << 2u) | (((xy >> 1u) & 1u) << 1u) | ((y >> 1u) & 1u);
+    return (f32(index) + 0.5) / 16.0;
+}
+
 @compute @workgroup_size(1, 1)
 fn rasterize(
     @builtin(global_invocation_id) global_id: vec3<u32>,
@@ -68,7 +86,13 @@ fn rasterize(
 ) {
     let coord = global_id.xy;
     let in_texel = textureLoad(input_texture, vec2<i32>(coord));
-    let code = find_best_code(in_texel.w);
+
+    var intensity = in_texel.w;
+    // Leave the background alone so that it doesn't get speckled
+    if (dithering == 1u) && (intensity <= thresholds[9]) {
+        intensity = min(intensity + (bayer_threshold(coord) - 0.5) * dither_spread, thresholds[9]);
+    }
+    let code = find_best_code(intensity);
 
     let out_texel = vec4<u32>(u32(255.0 * in_texel.x), u32(255.0 * in_texel.y), u32(255.0 * in_texel.z), code);
     textureStore(output_texture, vec2<i32>(workgroup_id.xy), out_texel);
@@ -1,5 +1,6 @@
 //! Rasterizer for converting compute shader characters
 
+use crate::dither::Dithering;
 use crate::gpu::state_windowless::ValidGridSize;
 use wgpu::TextureView;
 use winit::dpi::PhysicalSize;
@@ -9,6 +10,7 @@ use winit::dpi::PhysicalSize;
 #[derive(Debug)]
 pub struct BasicGPURasterizer {
     pub grid_size: ValidGridSize,
+    pub dithering: Dithering,
     pub compute_pipeline: wgpu::ComputePipeline,
     pub compute_pipeline_layout: wgpu::PipelineLayout,
     pub compute_bind_group: wgpu::BindGroup,
@@ -21,6 +23,7 @@ impl BasicGPURasterizer {
 
     pub fn new(
         grid_size: ValidGridSize,
+        dithering: Dithering,
         device: &wgpu::Device,
         input_view: &TextureView,
         output_view: &TextureView,
@@ -74,16 +77,37 @@ impl BasicGPURasterizer {
                 push_constant_ranges: &[],
             });
 
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        let compute_pipeline =
+            Self::create_compute_pipeline(device, &compute_pipeline_layout, grid_size, dithering);
+
+        Self {
+            grid_size,
+            dithering,
+            compute_pipeline,
+            compute_pipeline_layout,
+            compute_bind_group,
+            compute_bind_group_layout,
+        }
+    }
+
+    /// Compile the compute shader with the grid size and dithering baked in as constants
+    fn create_compute_pipeline(
+        device: &wgpu::Device,
+        compute_pipeline_layout: &wgpu::PipelineLayout,
+        grid_size: ValidGridSize,
+        dithering: Dithering,
+    ) -> wgpu::ComputePipeline {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some("Compute Pipeline Descriptor"),
-            layout: Some(&compute_pipeline_layout),
+            layout: Some(compute_pipeline_layout),
             module: &device.create_shader_module(wgpu::ShaderModuleDescriptor {
                 label: Some("Compute Shader Source"),
                 source: wgpu::ShaderSource::Wgsl(
                     format!(
-                        "const grid_width: u32 = {}u;\nconst grid_height: u32 = {}u;\n{}",
+                        "const grid_width: u32 = {}u;\nconst grid_height: u32 = {}u;\nconst dithering: u32 = {}u;\n{}",
                         grid_size.width(),
                         grid_size.height(),
+                        dithering.shader_code(),
                         include_str!("basic_ascii.wgsl")
                     )
                     .into(),
@@ -91,15 +115,24 @@ impl BasicGPURasterizer {
                 ),
             }),
             entry_point: "rasterize",
-        });
+        })
+    }
 
-        Self {
-            grid_size,
-            compute_pipeline,
-            compute_pipeline_layout,
-            compute_bind_group,
-            compute_bind_group_layout,
-        }
+    /// Change the dithering, which requires recompiling the compute shader
+    pub fn set_dithering(&mut self, dithering: Dithering, device: &wgpu::Device) {
+        self.dithering = dithering;
+        self.compute_pipeline = Self::create_compute_pipeline(
+            device,
+            &self.compute_pipeline_layout,
+            self.grid_size,
+            dithering,
+        );
+    }
+
+    /// Convert a code written by the compute shader back into its character
+    /// This rasterizer writes ASCII codes directly
+    pub fn code_to_symbol(&self, code: u8) -> char {
+        code as char
     }
 
     pub fn resize(
@@ -1,4 +1,15 @@
-use crate::gpu::input::{UnifiedEvent, UnifiedKeyCode, UnifiedKeyKind};
+use nalgebra::{Matrix3, Rotation3, UnitQuaternion};
+use winit::dpi::PhysicalSize;
+
+use crate::arcball;
+use crate::gpu::input::{
+    UnifiedEvent, UnifiedKeyCode, UnifiedKeyKind, UnifiedMouseButton, UnifiedMouseKind,
+};
+use crate::gpu::OPENGL_TO_WGPU_MATRIX;
+use crate::tui::keymap::{KeyAction, Keymap};
+
+/// Fraction of the distance to the target moved by each step of the scroll wheel
+const ZOOM_FRACTION: f32 = 0.1;
 
 #[derive(Debug)]
 pub struct Camera {
@@ -12,12 +23,13 @@ pub struct Camera {
 }
 
 impl Camera {
+    /// Depth ends up in the range 0 to 1 used by `wgpu`, rather than -1 to 1 as in OpenGL
     pub fn build_view_projection_matrix(&self) -> nalgebra::Matrix4<f32> {
         let view =
             nalgebra::Isometry3::look_at_rh(&self.eye, &self.target, &self.up).to_homogeneous();
         let proj = nalgebra::Perspective3::new(self.aspect, self.fovy, self.znear, self.zfar)
             .to_homogeneous();
-        proj * view
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 }
 
@@ -62,6 +74,21 @@ pub struct CameraController {
     pub is_backward_pressed: bool,
     pub is_left_pressed: bool,
     pub is_right_pressed: bool,
+    pub is_shift_pressed: bool,
+    /// Button held down to start the current drag
+    pub mouse_button: Option<UnifiedMouseButton>,
+    /// Whether the current drag pans rather than rotates, decided when it starts
+    pub panning: bool,
+    /// Last position of the mouse in clip space
+    pub mouse_position: Option<(f32, f32)>,
+    /// Arcball rotation of the scene in view space, waiting to be applied to the camera
+    pub pending_rotation: UnitQuaternion<f32>,
+    /// Movement of the scene across the screen in clip space, waiting to be applied to the camera
+    pub pending_pan: (f32, f32),
+    /// Steps of the scroll wheel, waiting to be applied to the camera
+    pub pending_zoom: f32,
+    /// Keys for moving the camera, shared with the terminal interface
+    pub keymap: Keymap,
 }
 
 impl CameraController {
@@ -75,38 +102,91 @@ impl CameraController {
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
+            is_shift_pressed: false,
+            mouse_button: None,
+            panning: false,
+            mouse_position: None,
+            pending_rotation: UnitQuaternion::identity(),
+            pending_pan: (0.0, 0.0),
+            pending_zoom: 0.0,
+            keymap: Keymap::default(),
         }
     }
 
-    pub fn process_events(&mut self, event: UnifiedEvent) -> bool {
-        let is_pressed = event.kind == UnifiedKeyKind::Press;
-        match event.keycode {
-            UnifiedKeyCode::K | UnifiedKeyCode::Up => {
-                self.is_up_pressed = is_pressed;
-                true
-            }
-            UnifiedKeyCode::H | UnifiedKeyCode::Left => {
-                self.is_left_pressed = is_pressed;
-                true
-            }
-            UnifiedKeyCode::J | UnifiedKeyCode::Down => {
-                self.is_down_pressed = is_pressed;
-                true
-            }
-            UnifiedKeyCode::L | UnifiedKeyCode::Right => {
-                self.is_right_pressed = is_pressed;
-                true
-            }
-            UnifiedKeyCode::U => {
-                self.is_forward_pressed = is_pressed;
-                true
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    /// The output size is in the same units as mouse positions, which is cells for terminals and pixels for windows
+    pub fn process_events(
+        &mut self,
+        event: UnifiedEvent,
+        output_size: PhysicalSize<u32>,
+        aspect: f32,
+    ) -> bool {
+        if let Some(mouse) = event.mouse {
+            let position = mouse.position.map(|(x, y)| {
+                let width = output_size.width.max(1) as f32;
+                let height = output_size.height.max(1) as f32;
+                (
+                    2.0 * (x + 0.5) / width - 1.0,
+                    1.0 - 2.0 * (y + 0.5) / height,
+                )
+            });
+            match mouse.kind {
+                UnifiedMouseKind::Press(button) => {
+                    // Terminals never report shift being released, so it is only read here and not remembered
+                    let shift = mouse.shift || self.is_shift_pressed;
+                    self.mouse_button = Some(button);
+                    self.panning = button == UnifiedMouseButton::Middle
+                        || (button == UnifiedMouseButton::Left && shift);
+                }
+                UnifiedMouseKind::Release(_) => {
+                    self.mouse_button = None;
+                    self.panning = false;
+                }
+                UnifiedMouseKind::Move => {
+                    if let (Some(button), Some(from), Some(to)) =
+                        (self.mouse_button, self.mouse_position, position)
+                    {
+                        if self.panning {
+                            self.pending_pan.0 += to.0 - from.0;
+                            self.pending_pan.1 += to.1 - from.1;
+                        } else if button == UnifiedMouseButton::Left {
+                            let rotation = arcball::rotation(
+                                arcball::clip_to_ball(from.0, from.1, aspect),
+                                arcball::clip_to_ball(to.0, to.1, aspect),
+                            );
+                            self.pending_rotation = rotation * self.pending_rotation;
+                        }
+                    }
+                }
+                UnifiedMouseKind::Scroll(steps) => self.pending_zoom += steps,
             }
-            UnifiedKeyCode::D => {
-                self.is_backward_pressed = is_pressed;
-                true
+            // Windows report where the cursor is separately from button presses
+            if position.is_some() {
+                self.mouse_position = position;
             }
-            _ => false,
+            return true;
         }
+
+        let is_pressed = event.kind == UnifiedKeyKind::Press;
+        if event.keycode == UnifiedKeyCode::Shift {
+            self.is_shift_pressed = is_pressed;
+            return true;
+        }
+        let pressed = match event.key.and_then(|key| self.keymap.action(key)) {
+            Some(KeyAction::MoveUp) => &mut self.is_up_pressed,
+            Some(KeyAction::MoveLeft) => &mut self.is_left_pressed,
+            Some(KeyAction::MoveDown) => &mut self.is_down_pressed,
+            Some(KeyAction::MoveRight) => &mut self.is_right_pressed,
+            Some(KeyAction::ZoomIn) => &mut self.is_forward_pressed,
+            Some(KeyAction::ZoomOut) => &mut self.is_backward_pressed,
+            _ => return false,
+        };
+        *pressed = is_pressed;
+        true
     }
 
     /// Reset the camera controller so that nothing is pressed.
@@ -120,7 +200,40 @@ impl CameraController {
         self.is_right_pressed = false;
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
+    /// Orbit, pan and zoom the camera about its target according to the mouse input since the last update
+    /// The scene should appear to follow the mouse, so the camera moves the opposite way
+    fn apply_mouse(&mut self, camera: &mut Camera) {
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        let forward = -offset / distance;
+        let right = forward.cross(&camera.up).normalize();
+        let up = right.cross(&forward);
+        // Columns are the axes of view space in world space
+        let view_to_world =
+            Rotation3::from_matrix_unchecked(Matrix3::from_columns(&[right, up, -forward]));
+        let view_to_world = UnitQuaternion::from_rotation_matrix(&view_to_world);
+
+        let rotation = view_to_world * self.pending_rotation.inverse() * view_to_world.inverse();
+        camera.eye = camera.target + rotation * offset;
+        camera.up = rotation * up;
+
+        let half_height = distance * (camera.fovy / 2.0).tan();
+        let pan =
+            -(right * self.pending_pan.0 * camera.aspect + up * self.pending_pan.1) * half_height;
+        camera.eye += pan;
+        camera.target += pan;
+
+        let zoom = (1.0 - ZOOM_FRACTION).powf(self.pending_zoom);
+        camera.eye = camera.target + (camera.eye - camera.target) * zoom;
+
+        self.pending_rotation = UnitQuaternion::identity();
+        self.pending_pan = (0.0, 0.0);
+        self.pending_zoom = 0.0;
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera) {
+        self.apply_mouse(camera);
+
         let forward = camera.target - camera.eye;
         let forward_norm = forward.normalize();
         let forward_mag = forward.magnitude();
@@ -164,3 +277,115 @@ impl CameraController {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::input::UnifiedMouseEvent;
+
+    fn camera() -> Camera {
+        Camera {
+            eye: nalgebra::Point3::new(0.0, 0.0, 10.0),
+            target: nalgebra::Point3::origin(),
+            up: nalgebra::Vector3::y(),
+            aspect: 1.0,
+            fovy: std::f32::consts::FRAC_PI_4,
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+
+    fn mouse(kind: UnifiedMouseKind, position: Option<(f32, f32)>) -> UnifiedEvent {
+        UnifiedEvent {
+            keycode: UnifiedKeyCode::Unknown,
+            key: None,
+            kind: UnifiedKeyKind::Unknown,
+            mouse: Some(UnifiedMouseEvent {
+                kind,
+                position,
+                shift: false,
+            }),
+        }
+    }
+
+    fn drag(controller: &mut CameraController, shift: bool, to: (f32, f32)) {
+        let size = PhysicalSize::new(100, 100);
+        let left = UnifiedMouseButton::Left;
+        let mut press = mouse(UnifiedMouseKind::Press(left), Some((50.0, 50.0)));
+        if let Some(mouse) = press.mouse.as_mut() {
+            mouse.shift = shift;
+        }
+        controller.process_events(press, size, 1.0);
+        controller.process_events(mouse(UnifiedMouseKind::Move, Some(to)), size, 1.0);
+        controller.process_events(mouse(UnifiedMouseKind::Release(left), Some(to)), size, 1.0);
+    }
+
+    #[test]
+    fn test_shift_drag_only_pans_once() {
+        let mut controller = CameraController::new(1.0, 0.1);
+        let mut camera = camera();
+
+        drag(&mut controller, true, (70.0, 50.0));
+        controller.update_camera(&mut camera);
+        assert!(camera.target != nalgebra::Point3::origin());
+
+        // Shift being released is never reported by terminals, so a plain drag afterwards must still rotate
+        let (eye, target) = (camera.eye, camera.target);
+        drag(&mut controller, false, (70.0, 50.0));
+        controller.update_camera(&mut camera);
+        assert!((camera.target - target).norm() < 1e-6);
+        assert!((camera.eye - eye).norm() > 1e-3);
+        assert!(!controller.is_shift_pressed);
+    }
+
+    #[test]
+    fn test_mouse_controls() {
+        let size = PhysicalSize::new(100, 100);
+        let mut controller = CameraController::new(1.0, 0.1);
+        let mut camera = camera();
+
+        // Scrolling up moves towards the target without turning
+        controller.process_events(mouse(UnifiedMouseKind::Scroll(1.0), None), size, 1.0);
+        controller.update_camera(&mut camera);
+        assert!((camera.eye - nalgebra::Point3::new(0.0, 0.0, 9.0)).norm() < 1e-4);
+
+        // Dragging right swings the camera to the left, keeping its distance from the target
+        let left = UnifiedMouseButton::Left;
+        controller.process_events(mouse(UnifiedMouseKind::Move, Some((50.0, 50.0))), size, 1.0);
+        controller.process_events(mouse(UnifiedMouseKind::Press(left), None), size, 1.0);
+        controller.process_events(mouse(UnifiedMouseKind::Move, Some((70.0, 50.0))), size, 1.0);
+        controller.update_camera(&mut camera);
+        assert!(camera.eye.x < 0.0);
+        assert!(((camera.eye - camera.target).norm() - 9.0).abs() < 1e-3);
+
+        // Middle-dragging moves the target along with the camera
+        controller.process_events(mouse(UnifiedMouseKind::Release(left), None), size, 1.0);
+        let middle = UnifiedMouseButton::Middle;
+        controller.process_events(mouse(UnifiedMouseKind::Press(middle), None), size, 1.0);
+        controller.process_events(mouse(UnifiedMouseKind::Move, Some((70.0, 30.0))), size, 1.0);
+        let offset = camera.eye - camera.target;
+        controller.update_camera(&mut camera);
+        assert!(camera.target != nalgebra::Point3::origin());
+        assert!((camera.eye - camera.target - offset).norm() < 1e-3);
+    }
+
+    #[test]
+    fn test_depth_inversion() {
+        let camera = camera();
+        let (n, f) = (camera.znear, camera.zfar);
+        for distance in [n, 1.0, 10.0, 50.0, f] {
+            let point = camera.eye - nalgebra::Vector3::z() * distance;
+            let clip = camera.build_view_projection_matrix() * point.to_homogeneous();
+            let depth = clip.z / clip.w;
+            assert!((-1e-6..=1.0 + 1e-6).contains(&depth));
+            // Same as `linear_depth` in `post_process.wgsl`
+            let linear = n * f / (f - depth * (f - n));
+            assert!(
+                (linear - distance).abs() < 1e-3 * distance,
+                "{} became {}",
+                distance,
+                linear
+            );
+        }
+    }
+}
@@ -3,11 +3,10 @@
 // Following constants should be prepended during `wgpu::ShaderSource::Wgsl`
 // const grid_width: u32 = 1u;
 // const grid_height: u32 = 2u;
+// const num_glyphs: u32 = 95u;
 // TODO Look into pipeline overridable constants like `@id override grid_width`, once this gets added to `wgpu`
 
-const ASCII_START: u32 = 32u;
-const ASCII_STOP: u32 = 127u;
-const NUM_ASCII: u32 = ASCII_STOP - ASCII_START;
+const NUM_ASCII: u32 = num_glyphs;
 
 const grid_size: u32 = grid_width * grid_height;
 
@@ -98,7 +97,7 @@ fn ascii_from_ssim(
     @builtin(workgroup_id) workgroup_id: vec3<u32>,
 ) {
     var best_ssim: f32 = 0.0;
-    var best_index: u32 = 0u; // Will correspond to ' ' later
+    var best_index: u32 = 0u; // Glyph sets are sorted, so this corresponds to ' '
     // var best_index: u32 = 1u; // Will correspond to '!' later
     for (var ascii_index: u32 = 0u; ascii_index < NUM_ASCII; ascii_index++) {
         let ssim = textureLoad(ssim_texture, vec3<u32>(workgroup_id.x, workgroup_id.y, ascii_index)).w;
@@ -107,9 +106,8 @@ fn ascii_from_ssim(
             best_index = ascii_index;
         }
     }
-    let ascii_char = best_index + ASCII_START;
-
+    // Write the index into the glyph set rather than the character, which may not fit into a `u8`
     let ssim_texel = textureLoad(ssim_texture, vec3<u32>(workgroup_id.x, workgroup_id.y, best_index));
-    let out_texel = vec4<u32>(u32(255.0 * ssim_texel.x), u32(255.0 * ssim_texel.y), u32(255.0 * ssim_texel.z), ascii_char);
+    let out_texel = vec4<u32>(u32(255.0 * ssim_texel.x), u32(255.0 * ssim_texel.y), u32(255.0 * ssim_texel.z), best_index);
     textureStore(output_texture, vec2<u32>(workgroup_id.xy), out_texel);
 }
@@ -1,15 +1,110 @@
 //! Processing the inputs from both windowed and terminal applications
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use crate::tui::keymap::{KeyAction, Keymap};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton as WinitMouseButton, MouseScrollDelta,
+    VirtualKeyCode, WindowEvent,
+};
 // Want to define a from method for KeyEventKind
 
 // TODO Also needs to work with modifiers
 
-#[derive(Debug, Clone, Copy, Hash)]
+/// Number of pixels scrolled that count as a single step of a scroll wheel
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy)]
 pub struct UnifiedEvent {
     pub keycode: UnifiedKeyCode,
+    /// Key as bound in the keymap, covering every character rather than just those with a `UnifiedKeyCode`
+    pub key: Option<KeyCode>,
     pub kind: UnifiedKeyKind,
+    /// Set for mouse events, in which case the key code is `Unknown`
+    pub mouse: Option<UnifiedMouseEvent>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum UnifiedMouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnifiedMouseKind {
+    Press(UnifiedMouseButton),
+    Release(UnifiedMouseButton),
+    /// Covers both moving and dragging, since windows report buttons separately from movement
+    Move,
+    /// Steps of the scroll wheel, where positive scrolls up
+    Scroll(f32),
+}
+
+/// Mouse input, where positions are in terminal cells or window pixels from the top left
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct UnifiedMouseEvent {
+    pub kind: UnifiedMouseKind,
+    /// Only known for some events, since windows report where the cursor is separately
+    pub position: Option<(f32, f32)>,
+    /// Whether shift was held, if known
+    pub shift: bool,
+}
+
+impl UnifiedEvent {
+    fn unknown() -> Self {
+        Self {
+            keycode: UnifiedKeyCode::Unknown,
+            key: None,
+            kind: UnifiedKeyKind::Unknown,
+            mouse: None,
+        }
+    }
+    fn from_mouse(mouse: UnifiedMouseEvent) -> Self {
+        Self {
+            mouse: Some(mouse),
+            ..Self::unknown()
+        }
+    }
+    /// Whether the event presses a key bound to quitting or going back
+    pub fn quits(&self, keymap: &Keymap) -> bool {
+        self.kind == UnifiedKeyKind::Press
+            && matches!(
+                self.key.and_then(|key| keymap.action(key)),
+                Some(KeyAction::Quit | KeyAction::Back)
+            )
+    }
+}
+
+impl From<MouseButton> for UnifiedMouseButton {
+    fn from(button: MouseButton) -> Self {
+        match button {
+            MouseButton::Left => Self::Left,
+            MouseButton::Middle => Self::Middle,
+            MouseButton::Right => Self::Right,
+        }
+    }
+}
+
+impl From<&MouseEvent> for UnifiedMouseEvent {
+    fn from(event: &MouseEvent) -> Self {
+        let kind = match event.kind {
+            MouseEventKind::Down(button) => UnifiedMouseKind::Press(button.into()),
+            MouseEventKind::Up(button) => UnifiedMouseKind::Release(button.into()),
+            MouseEventKind::Drag(_) | MouseEventKind::Moved => UnifiedMouseKind::Move,
+            MouseEventKind::ScrollUp => UnifiedMouseKind::Scroll(1.0),
+            MouseEventKind::ScrollDown => UnifiedMouseKind::Scroll(-1.0),
+            MouseEventKind::ScrollLeft | MouseEventKind::ScrollRight => {
+                UnifiedMouseKind::Scroll(0.0)
+            }
+        };
+        Self {
+            kind,
+            position: Some((event.column as f32, event.row as f32)),
+            shift: event.modifiers.contains(KeyModifiers::SHIFT),
+        }
+    }
 }
 
 // TODO Consider changing `kind` to an `option` instead
@@ -30,6 +125,9 @@ pub enum UnifiedKeyCode {
     L,
     U,
     D,
+    G,
+    O,
+    T,
     Shift,
     Esc,
     Left,
@@ -56,6 +154,9 @@ impl From<&Event> for UnifiedEvent {
                     KeyCode::Char('l') => UnifiedKeyCode::L,
                     KeyCode::Char('u') => UnifiedKeyCode::U,
                     KeyCode::Char('d') => UnifiedKeyCode::D,
+                    KeyCode::Char('g') => UnifiedKeyCode::G,
+                    KeyCode::Char('o') => UnifiedKeyCode::O,
+                    KeyCode::Char('t') => UnifiedKeyCode::T,
                     KeyCode::Char(' ') => UnifiedKeyCode::Space,
                     KeyCode::Esc => UnifiedKeyCode::Esc,
                     KeyCode::Up => UnifiedKeyCode::Up,
@@ -71,19 +172,21 @@ impl From<&Event> for UnifiedEvent {
                 };
                 UnifiedEvent {
                     keycode: new_code,
+                    key: Some(*code),
                     kind: new_kind,
+                    mouse: None,
                 }
             }
-            _ => UnifiedEvent {
-                keycode: UnifiedKeyCode::Unknown,
-                kind: UnifiedKeyKind::Unknown,
-            },
+            Event::Mouse(mouse) => UnifiedEvent::from_mouse(mouse.into()),
+            _ => UnifiedEvent::unknown(),
         }
     }
 }
 
-impl<'a> From<&WindowEvent<'a>> for UnifiedEvent {
-    fn from(event: &WindowEvent) -> Self {
+impl UnifiedEvent {
+    /// Windows report modifiers separately from key presses, so the latest state of them is needed to tell which
+    /// character a key types
+    pub fn from_window_event(event: &WindowEvent, modifiers: ModifiersState) -> Self {
         match event {
             WindowEvent::KeyboardInput {
                 input:
@@ -102,6 +205,9 @@ impl<'a> From<&WindowEvent<'a>> for UnifiedEvent {
                     VirtualKeyCode::L => UnifiedKeyCode::L,
                     VirtualKeyCode::U => UnifiedKeyCode::U,
                     VirtualKeyCode::D => UnifiedKeyCode::D,
+                    VirtualKeyCode::G => UnifiedKeyCode::G,
+                    VirtualKeyCode::O => UnifiedKeyCode::O,
+                    VirtualKeyCode::T => UnifiedKeyCode::T,
                     VirtualKeyCode::Space => UnifiedKeyCode::Space,
                     VirtualKeyCode::Up => UnifiedKeyCode::Up,
                     VirtualKeyCode::Down => UnifiedKeyCode::Down,
@@ -117,22 +223,144 @@ impl<'a> From<&WindowEvent<'a>> for UnifiedEvent {
                 };
                 UnifiedEvent {
                     keycode: new_code,
+                    key: winit_key(*keycode, modifiers.shift()),
                     kind: new_kind,
+                    mouse: None,
                 }
             }
-            _ => UnifiedEvent {
-                keycode: UnifiedKeyCode::Unknown,
-                kind: UnifiedKeyKind::Unknown,
-            },
+            WindowEvent::CursorMoved { position, .. } => {
+                UnifiedEvent::from_mouse(UnifiedMouseEvent {
+                    kind: UnifiedMouseKind::Move,
+                    position: Some((position.x as f32, position.y as f32)),
+                    shift: modifiers.shift(),
+                })
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    WinitMouseButton::Left => UnifiedMouseButton::Left,
+                    WinitMouseButton::Middle => UnifiedMouseButton::Middle,
+                    WinitMouseButton::Right => UnifiedMouseButton::Right,
+                    WinitMouseButton::Other(_) => return UnifiedEvent::unknown(),
+                };
+                let kind = match state {
+                    ElementState::Pressed => UnifiedMouseKind::Press(button),
+                    ElementState::Released => UnifiedMouseKind::Release(button),
+                };
+                UnifiedEvent::from_mouse(UnifiedMouseEvent {
+                    kind,
+                    position: None,
+                    shift: modifiers.shift(),
+                })
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        position.y as f32 / PIXELS_PER_SCROLL_LINE
+                    }
+                };
+                UnifiedEvent::from_mouse(UnifiedMouseEvent {
+                    kind: UnifiedMouseKind::Scroll(lines),
+                    position: None,
+                    shift: modifiers.shift(),
+                })
+            }
+            _ => UnifiedEvent::unknown(),
         }
     }
 }
 
+/// Letter keys of windows, which are read as lower case unless shift is held
+const WINIT_LETTERS: [(VirtualKeyCode, char); 26] = [
+    (VirtualKeyCode::A, 'a'),
+    (VirtualKeyCode::B, 'b'),
+    (VirtualKeyCode::C, 'c'),
+    (VirtualKeyCode::D, 'd'),
+    (VirtualKeyCode::E, 'e'),
+    (VirtualKeyCode::F, 'f'),
+    (VirtualKeyCode::G, 'g'),
+    (VirtualKeyCode::H, 'h'),
+    (VirtualKeyCode::I, 'i'),
+    (VirtualKeyCode::J, 'j'),
+    (VirtualKeyCode::K, 'k'),
+    (VirtualKeyCode::L, 'l'),
+    (VirtualKeyCode::M, 'm'),
+    (VirtualKeyCode::N, 'n'),
+    (VirtualKeyCode::O, 'o'),
+    (VirtualKeyCode::P, 'p'),
+    (VirtualKeyCode::Q, 'q'),
+    (VirtualKeyCode::R, 'r'),
+    (VirtualKeyCode::S, 's'),
+    (VirtualKeyCode::T, 't'),
+    (VirtualKeyCode::U, 'u'),
+    (VirtualKeyCode::V, 'v'),
+    (VirtualKeyCode::W, 'w'),
+    (VirtualKeyCode::X, 'x'),
+    (VirtualKeyCode::Y, 'y'),
+    (VirtualKeyCode::Z, 'z'),
+];
+
+/// Other keys that type characters, as `(key, character, character with shift)`
+/// Assumes a US layout, since windows only report which key was pressed along with the state of shift
+const WINIT_SYMBOLS: [(VirtualKeyCode, char, char); 21] = [
+    (VirtualKeyCode::Key1, '1', '!'),
+    (VirtualKeyCode::Key2, '2', '@'),
+    (VirtualKeyCode::Key3, '3', '#'),
+    (VirtualKeyCode::Key4, '4', '$'),
+    (VirtualKeyCode::Key5, '5', '%'),
+    (VirtualKeyCode::Key6, '6', '^'),
+    (VirtualKeyCode::Key7, '7', '&'),
+    (VirtualKeyCode::Key8, '8', '*'),
+    (VirtualKeyCode::Key9, '9', '('),
+    (VirtualKeyCode::Key0, '0', ')'),
+    (VirtualKeyCode::Minus, '-', '_'),
+    (VirtualKeyCode::Equals, '=', '+'),
+    (VirtualKeyCode::LBracket, '[', '{'),
+    (VirtualKeyCode::RBracket, ']', '}'),
+    (VirtualKeyCode::Backslash, '\\', '|'),
+    (VirtualKeyCode::Semicolon, ';', ':'),
+    (VirtualKeyCode::Apostrophe, '\'', '"'),
+    (VirtualKeyCode::Grave, '`', '~'),
+    (VirtualKeyCode::Comma, ',', '<'),
+    (VirtualKeyCode::Period, '.', '>'),
+    (VirtualKeyCode::Slash, '/', '?'),
+];
+
+/// Key of a window event as it would be read from a terminal, where shift changes the character typed
+fn winit_key(keycode: VirtualKeyCode, shift: bool) -> Option<KeyCode> {
+    let key = match keycode {
+        VirtualKeyCode::Space => KeyCode::Char(' '),
+        VirtualKeyCode::Escape => KeyCode::Esc,
+        VirtualKeyCode::Tab => KeyCode::Tab,
+        VirtualKeyCode::Return => KeyCode::Enter,
+        VirtualKeyCode::Back => KeyCode::Backspace,
+        VirtualKeyCode::Delete => KeyCode::Delete,
+        VirtualKeyCode::Up => KeyCode::Up,
+        VirtualKeyCode::Down => KeyCode::Down,
+        VirtualKeyCode::Left => KeyCode::Left,
+        VirtualKeyCode::Right => KeyCode::Right,
+        _ => {
+            if let Some((_, letter)) = WINIT_LETTERS.iter().find(|(code, _)| *code == keycode) {
+                KeyCode::Char(if shift {
+                    letter.to_ascii_uppercase()
+                } else {
+                    *letter
+                })
+            } else {
+                let (_, plain, shifted) =
+                    WINIT_SYMBOLS.iter().find(|(code, ..)| *code == keycode)?;
+                KeyCode::Char(if shift { *shifted } else { *plain })
+            }
+        }
+    };
+    Some(key)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crossterm::event::{KeyEventState, KeyModifiers};
-    use winit::event::{DeviceId, ModifiersState};
+    use crossterm::event::KeyEventState;
+    use winit::event::DeviceId;
 
     fn is_space(event: UnifiedEvent) -> bool {
         matches!(event.keycode, UnifiedKeyCode::Space)
@@ -153,7 +381,43 @@ mod tests {
                 is_synthetic: false,
             }
         };
-        assert!(is_space((&space_event).into()));
+        assert!(is_space(UnifiedEvent::from_window_event(
+            &space_event,
+            ModifiersState::empty()
+        )));
+        assert_eq!(
+            winit_key(VirtualKeyCode::N, false),
+            Some(KeyCode::Char('n'))
+        );
+        assert_eq!(winit_key(VirtualKeyCode::F1, false), None);
+
+        // Shift changes the character typed, just like in a terminal
+        let shifted = |keycode| {
+            #[allow(deprecated)]
+            let event = unsafe {
+                WindowEvent::KeyboardInput {
+                    device_id: DeviceId::dummy(),
+                    input: KeyboardInput {
+                        scancode: 0u32,
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        modifiers: ModifiersState::SHIFT,
+                    },
+                    is_synthetic: false,
+                }
+            };
+            UnifiedEvent::from_window_event(&event, ModifiersState::SHIFT).key
+        };
+        assert_eq!(shifted(VirtualKeyCode::C), Some(KeyCode::Char('C')));
+        assert_eq!(shifted(VirtualKeyCode::LBracket), Some(KeyCode::Char('{')));
+        assert_eq!(
+            winit_key(VirtualKeyCode::RBracket, false),
+            Some(KeyCode::Char(']'))
+        );
+        assert_eq!(
+            winit_key(VirtualKeyCode::Key1, false),
+            Some(KeyCode::Char('1'))
+        );
     }
 
     #[test]
@@ -175,4 +439,42 @@ mod tests {
         });
         assert!(!is_space((&random_event).into()));
     }
+
+    #[test]
+    pub fn test_quit_keys() {
+        let keymap = Keymap::default();
+        let key_event = |code, kind| -> UnifiedEvent {
+            (&Event::Key(KeyEvent {
+                code,
+                modifiers: KeyModifiers::empty(),
+                kind,
+                state: KeyEventState::empty(),
+            }))
+                .into()
+        };
+        assert!(key_event(KeyCode::Char('q'), KeyEventKind::Press).quits(&keymap));
+        assert!(key_event(KeyCode::Esc, KeyEventKind::Press).quits(&keymap));
+        assert!(!key_event(KeyCode::Char('h'), KeyEventKind::Press).quits(&keymap));
+        assert!(!key_event(KeyCode::Char('q'), KeyEventKind::Release).quits(&keymap));
+    }
+
+    #[test]
+    pub fn test_tui_mouse_conversion() {
+        let drag_event = Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Drag(MouseButton::Left),
+            column: 3,
+            row: 4,
+            modifiers: KeyModifiers::SHIFT,
+        });
+        let unified: UnifiedEvent = (&drag_event).into();
+        assert_eq!(unified.keycode, UnifiedKeyCode::Unknown);
+        assert_eq!(
+            unified.mouse,
+            Some(UnifiedMouseEvent {
+                kind: UnifiedMouseKind::Move,
+                position: Some((3.0, 4.0)),
+                shift: true,
+            })
+        );
+    }
 }
@@ -0,0 +1,63 @@
+//! Packing the lighting model into a uniform for the shaders.
+
+use crate::lighting::{Light, Lighting, MAX_LIGHTS};
+
+/// Values of `LightRaw::kind`, which must match `shader.wgsl`
+const DIRECTIONAL: u32 = 0;
+const POINT: u32 = 1;
+const HEADLIGHT: u32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    /// Direction towards directional lights, or position of point lights
+    pub position: [f32; 3],
+    // Fills the padding after `position`, since uniforms require 16 byte (4 float) spacing
+    pub kind: u32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl From<&Light> for LightRaw {
+    fn from(light: &Light) -> Self {
+        let kind = match light {
+            Light::Directional { .. } => DIRECTIONAL,
+            Light::Point { .. } => POINT,
+            Light::Headlight { .. } => HEADLIGHT,
+        };
+        Self {
+            position: light.direction_or_position().into(),
+            kind,
+            color: light.color(),
+            intensity: light.intensity(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingUniform {
+    pub lights: [LightRaw; MAX_LIGHTS],
+    /// Number of lights in use, with the rest ignored
+    pub count: u32,
+    pub ambient: f32,
+    pub specular: f32,
+    pub shininess: f32,
+}
+
+impl From<&Lighting> for LightingUniform {
+    /// Any lights beyond `MAX_LIGHTS` are dropped
+    fn from(lighting: &Lighting) -> Self {
+        let mut lights = [LightRaw::default(); MAX_LIGHTS];
+        for (raw, light) in lights.iter_mut().zip(lighting.lights.iter()) {
+            *raw = light.into();
+        }
+        Self {
+            lights,
+            count: lighting.lights.len().min(MAX_LIGHTS) as u32,
+            ambient: lighting.ambient,
+            specular: lighting.specular,
+            shininess: lighting.shininess,
+        }
+    }
+}
@@ -9,11 +9,22 @@ struct Camera {
 var<uniform> camera: Camera;
 
 struct Light {
+    // Direction towards directional lights, or position of point lights
     position: vec3<f32>,
+    // 0 for directional, 1 for point and 2 for headlight, matching `gpu::light`
+    kind: u32,
     color: vec3<f32>,
+    intensity: f32,
+}
+struct Lighting {
+    lights: array<Light, 4>,
+    count: u32,
+    ambient: f32,
+    specular: f32,
+    shininess: f32,
 }
 @group(1) @binding(0)
-var<uniform> light: Light;
+var<uniform> lighting: Lighting;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -27,10 +38,23 @@ struct VertexOutput {
 @vertex
 fn vs_main(
     model: VertexInput,
+    @builtin(instance_index) index: u32,
 ) -> VertexOutput {
     let scale = 0.1;
+    // Directional lights are infinitely far away, so are shown this far from the origin in their direction
+    let directional_distance = 50.0;
     var out: VertexOutput;
-    out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position, 1.0);
+    // Each instance is one of the lights
+    let light = lighting.lights[index];
+    var position = light.position;
+    if light.kind == 0u {
+        position = normalize(light.position) * directional_distance;
+    }
+    out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + position, 1.0);
+    // Headlights are at the camera, so are pushed beyond the far plane to hide them
+    if light.kind == 2u {
+        out.clip_position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+    }
     out.color = light.color;
     return out;
 }
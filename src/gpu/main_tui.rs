@@ -1,14 +1,37 @@
-#[allow(unused_imports)]
-use pdb_tui::gpu::run_tui::{run_new, shutdown, startup};
+use clap::Parser;
+use pdb_tui::ascii::glyph_render::GlyphSet;
+use pdb_tui::gpu::run_tui::{load_glyphs, run_new, shutdown, startup};
+use pdb_tui::tui::keymap::Keymap;
 use std::io::Result;
+use std::path::PathBuf;
 
 // fn main() {
 //     pollster::block_on(run());
 // }
 
+/// Program to render models within a terminal user interface using the GPU
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// TTF or OTF font to match glyph shapes against, ideally the one used by the terminal
+    /// Choosing a font or glyph set switches to the SSIM rasterizer
+    #[arg(long)]
+    font: Option<PathBuf>,
+    /// Characters that may be drawn: "ascii", "blocks", "box" or any string of characters
+    #[arg(long)]
+    glyphs: Option<GlyphSet>,
+    /// Ratio of height to width of terminal characters, queried from the terminal if not given
+    #[arg(long)]
+    cell_aspect: Option<f32>,
+}
+
 fn main() -> Result<()> {
+    let args = Args::parse();
+    // Read the config and font before taking over the terminal, so that any mistakes in them are readable
+    let keymap = Keymap::from_config().map_err(std::io::Error::other)?;
+    let glyphs = load_glyphs(args.font.as_deref(), args.glyphs).map_err(std::io::Error::other)?;
     startup()?;
-    let result = pollster::block_on(run_new());
+    let result = pollster::block_on(run_new(keymap, glyphs, args.cell_aspect));
     shutdown()?;
     result?;
     Ok(())
@@ -1,6 +1,10 @@
 #[allow(unused_imports)]
 use pdb_tui::gpu::run_windowed::run;
+use pdb_tui::tui::keymap::Keymap;
+use std::io::Result;
 
-fn main() {
-    pollster::block_on(run());
+fn main() -> Result<()> {
+    let keymap = Keymap::from_config().map_err(std::io::Error::other)?;
+    pollster::block_on(run(keymap));
+    Ok(())
 }
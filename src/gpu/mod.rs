@@ -5,16 +5,21 @@ use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 
 use camera::{Camera, CameraController, CameraUniform};
-use instance::{Instance, InstanceRaw, LightUniform};
+use instance::{Instance, InstanceRaw};
+use light::LightingUniform;
 use model::Vertex;
 
-use crate::gpu::input::UnifiedEvent;
+use crate::gpu::input::{UnifiedEvent, UnifiedKeyKind};
+use crate::lighting::{Lighting, MAX_LIGHTS};
+use crate::tui::keymap::Keymap;
 
 pub mod basic_rasterizer;
 pub mod camera;
 pub mod input;
 pub mod instance;
+pub mod light;
 pub mod model;
+pub mod post_process;
 pub mod resources;
 pub mod run_tui;
 pub mod run_windowed;
@@ -99,6 +104,16 @@ pub trait InnerState {
     fn output_size(&self) -> PhysicalSize<u32>;
     fn format(&self) -> wgpu::TextureFormat;
     fn resize(&mut self, new_size: PhysicalSize<u32>, device: &wgpu::Device);
+    /// Ratio of height to width of each rendered pixel when it is shown, e.g. in a terminal
+    fn pixel_aspect_ratio(&self) -> f32 {
+        1.0
+    }
+    /// Aspect ratio that the camera needs for the output not to be stretched
+    fn camera_aspect(&self) -> f32 {
+        self.render_size().width as f32
+            / self.render_size().height as f32
+            / self.pixel_aspect_ratio()
+    }
 }
 
 #[derive(Debug)]
@@ -117,10 +132,12 @@ struct State<IS: InnerState> {
     #[allow(dead_code)]
     instance_buffer: wgpu::Buffer,
     depth_texture: texture::Texture,
-    light_uniform: LightUniform,
     light_buffer: wgpu::Buffer,
     light_bind_group: wgpu::BindGroup,
     light_render_pipeline: wgpu::RenderPipeline,
+    lighting: Lighting,
+    /// Light changed by the lighting keys
+    selected_light: usize,
 }
 
 impl<IS: InnerState> State<IS> {
@@ -128,19 +145,19 @@ impl<IS: InnerState> State<IS> {
         inner_state: IS,
         device: wgpu::Device,
         queue: wgpu::Queue,
+        keymap: Keymap,
     ) -> Self {
         let camera = Camera {
             eye: nalgebra::Point3::new(50.0, 5.0, -10.0),
             target: nalgebra::Point3::origin(),
             up: nalgebra::Vector3::y(),
-            aspect: inner_state.render_size().width as f32
-                / inner_state.render_size().height as f32,
+            aspect: inner_state.camera_aspect(),
             fovy: std::f32::consts::FRAC_PI_4,
             znear: 0.1,
             zfar: 1000.0,
         };
 
-        let camera_controller = CameraController::new(2.0, 0.15);
+        let camera_controller = CameraController::new(2.0, 0.15).with_keymap(keymap);
 
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
@@ -209,16 +226,12 @@ impl<IS: InnerState> State<IS> {
             .await
             .unwrap();
 
-        let light_uniform = LightUniform {
-            position: [20.0, 20.0, 20.0],
-            _padding: 0,
-            color: [1.0, 1.0, 1.0],
-            _padding2: 0,
-        };
+        let lighting = Lighting::default();
+        let lighting_uniform = LightingUniform::from(&lighting);
 
         let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Light VB"),
-            contents: bytemuck::cast_slice(&[light_uniform]),
+            contents: bytemuck::cast_slice(&[lighting_uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -308,18 +321,18 @@ impl<IS: InnerState> State<IS> {
             instances,
             instance_buffer,
             depth_texture,
-            light_uniform,
             light_buffer,
             light_bind_group,
             light_render_pipeline,
+            lighting,
+            selected_light: 0,
         }
     }
     /// Resize the canvas
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.inner_state.resize(new_size, &self.device);
-            self.camera.aspect = self.inner_state.render_size().width as f32
-                / self.inner_state.render_size().height as f32;
+            self.camera.aspect = self.inner_state.camera_aspect();
             self.depth_texture = texture::Texture::create_depth_texture(
                 &self.device,
                 self.inner_state.render_size().width,
@@ -336,21 +349,19 @@ impl<IS: InnerState> State<IS> {
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
-
-        // Update the light
-        if false {
-            let old_position: nalgebra::Point3<_> = self.light_uniform.position.into();
-            self.light_uniform.position = (nalgebra::Rotation3::from_axis_angle(
-                &nalgebra::Vector3::y_axis(),
-                std::f32::consts::PI / 180.0,
-            ) * old_position)
-                .into();
-            self.queue.write_buffer(
-                &self.light_buffer,
-                0,
-                bytemuck::cast_slice(&[self.light_uniform]),
-            );
-        }
+    }
+    /// Replace the lights and material used for shading
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[LightingUniform::from(&lighting)]),
+        );
+        self.lighting = lighting;
+    }
+    /// Number of lights to draw markers for, one instance each, where light.wgsl hides headlights
+    fn light_markers(&self) -> u32 {
+        self.lighting.lights.len().min(MAX_LIGHTS) as u32
     }
     // TODO Consider moving this function outside of `State`, like the function for creating a render pipeline
     /// Create the devices needed for cases with or without a window
@@ -381,6 +392,20 @@ impl<IS: InnerState> State<IS> {
         (adapter, device, queue)
     }
     fn input(&mut self, event: UnifiedEvent) -> bool {
-        self.camera_controller.process_events(event)
+        let edit = event
+            .key
+            .and_then(|key| self.camera_controller.keymap.action(key))
+            .and_then(|action| action.lighting_edit());
+        if let (UnifiedKeyKind::Press, Some(edit)) = (event.kind, edit) {
+            // Point lights start as far from the scene as the camera
+            let distance = (self.camera.eye - self.camera.target).magnitude();
+            let mut lighting = self.lighting.clone();
+            lighting.edit(&mut self.selected_light, edit, distance);
+            self.set_lighting(lighting);
+            return true;
+        }
+        let aspect = self.camera.aspect;
+        self.camera_controller
+            .process_events(event, self.inner_state.output_size(), aspect)
     }
 }
@@ -0,0 +1,203 @@
+//! Compute pass adding toon shading and outlines to rendered pixels, run before they are turned into characters
+
+use wgpu::util::DeviceExt;
+use wgpu::TextureView;
+use winit::dpi::PhysicalSize;
+
+use crate::gpu::texture;
+use crate::shading::Shading;
+
+/// Width and height of the workgroups, which must match `post_process.wgsl`
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PostProcessUniform {
+    pub outlines: u32,
+    pub toon_bands: u32,
+    pub depth_threshold: f32,
+    pub outline_intensity: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
+    pub _padding: [u32; 2],
+}
+
+impl PostProcessUniform {
+    /// Only the toon shading and outlines are used, since other effects need ray casting
+    /// The near and far planes are needed to turn the depth buffer back into view-space depth
+    pub fn new(shading: &Shading, znear: f32, zfar: f32) -> Self {
+        let outlines = shading.outlines.unwrap_or_default();
+        Self {
+            outlines: shading.outlines.is_some() as u32,
+            toon_bands: shading.toon.map_or(0, |toon| toon.bands.max(2) as u32),
+            depth_threshold: outlines.depth_threshold,
+            outline_intensity: outlines.intensity,
+            znear,
+            zfar,
+            _padding: [0; 2],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PostProcess {
+    pub compute_pipeline: wgpu::ComputePipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+impl PostProcess {
+    const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: Self::TEXTURE_FORMAT,
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: Self::TEXTURE_FORMAT,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Post Process Bind Group Layout"),
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Process Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[PostProcessUniform::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Process Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Post Process Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Post Process Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("post_process.wgsl").into()),
+            }),
+            entry_point: "post_process",
+        });
+
+        Self {
+            compute_pipeline,
+            bind_group_layout,
+            uniform_buffer,
+        }
+    }
+
+    /// Create the texture written by the pass, which is read by the ASCII compute shader
+    pub fn create_output_texture(device: &wgpu::Device, size: PhysicalSize<u32>) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::TEXTURE_FORMAT,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            label: Some("Post Process Texture"),
+        })
+    }
+
+    /// Bind the rendered pixels and depth to the pass
+    /// This is rebuilt every frame, since the depth texture is replaced whenever the window is resized
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        input_view: &TextureView,
+        depth_texture: &texture::Texture,
+        output_view: &TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Process Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(output_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Run the pass over the whole rendered image
+    pub fn run_compute(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        uniform: PostProcessUniform,
+        bind_group: &wgpu::BindGroup,
+        render_size: PhysicalSize<u32>,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Post Process Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.dispatch_workgroups(
+            render_size.width.div_ceil(WORKGROUP_SIZE),
+            render_size.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
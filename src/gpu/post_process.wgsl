@@ -0,0 +1,77 @@
+// Compute shader for non-photorealistic effects, run on rendered pixels before they are turned into characters
+// Matches `Toon` and `Outlines` in `shading.rs`, except that shapes are only told apart by jumps in depth
+
+struct PostProcess {
+    // Zero when outlines are turned off
+    outlines: u32,
+    // Zero when toon shading is turned off
+    toon_bands: u32,
+    depth_threshold: f32,
+    outline_intensity: f32,
+    znear: f32,
+    zfar: f32,
+}
+
+@group(0) @binding(0) var input_texture: texture_storage_2d<rgba8unorm, read>;
+@group(0) @binding(1) var depth_texture: texture_depth_2d;
+@group(0) @binding(2) var output_texture: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3) var<uniform> params: PostProcess;
+
+// Stand-in depth for the background, which is cleared to the far plane
+const background_depth: f32 = 3.0e38;
+
+/// View-space depth, undoing the perspective projection
+fn linear_depth(coord: vec2<i32>) -> f32 {
+    let depth = textureLoad(depth_texture, coord, 0);
+    if depth >= 1.0 {
+        return background_depth;
+    }
+    // Depth is stored in the range 0 to 1, see `OPENGL_TO_WGPU_MATRIX`
+    let n = params.znear;
+    let f = params.zfar;
+    return n * f / (f - depth * (f - n));
+}
+
+/// Round an intensity to the middle of its band
+fn quantise(intensity: f32) -> f32 {
+    let bands = f32(params.toon_bands);
+    let band = min(floor(clamp(intensity, 0.0, 1.0) * bands), bands - 1.0);
+    return (band + 0.5) / bands;
+}
+
+/// Whether a pixel is in front of a neighbour by more than the threshold
+/// Only the nearer pixel is marked, so outlines are a single pixel wide
+fn is_edge(depth: f32, coord: vec2<i32>, size: vec2<i32>) -> bool {
+    if any(coord < vec2<i32>(0)) || any(coord >= size) {
+        return false;
+    }
+    return linear_depth(coord) - depth > params.depth_threshold * depth;
+}
+
+@compute @workgroup_size(8, 8)
+fn post_process(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(input_texture));
+    let coord = vec2<i32>(global_id.xy);
+    if any(coord >= size) {
+        return;
+    }
+
+    var texel = textureLoad(input_texture, coord);
+    let depth = linear_depth(coord);
+    // Leave the background alone
+    if depth < background_depth {
+        if params.toon_bands > 0u {
+            texel.w = quantise(texel.w);
+        }
+        if params.outlines != 0u {
+            let edge = is_edge(depth, coord + vec2<i32>(1, 0), size)
+                || is_edge(depth, coord - vec2<i32>(1, 0), size)
+                || is_edge(depth, coord + vec2<i32>(0, 1), size)
+                || is_edge(depth, coord - vec2<i32>(0, 1), size);
+            if edge {
+                texel.w = params.outline_intensity;
+            }
+        }
+    }
+    textureStore(output_texture, coord, texel);
+}
@@ -4,26 +4,32 @@ use tracing::Level;
 use tracing_subscriber;
 use winit::dpi::PhysicalSize;
 
-use crate::gpu::input::{UnifiedEvent, UnifiedKeyCode};
+use crate::ascii::glyph_render::{get_font, load_font, AsciiMatrices, GlyphError, GlyphSet};
+use crate::gpu::input::{UnifiedEvent, UnifiedKeyKind};
 use crate::gpu::state_windowless::WindowlessState;
 use crate::gpu::{InnerState, State};
 
 // use crate::basic_rasterizer::BasicAsciiRasterizer;
 use crate::rasterizer::chars_to_widget;
 use crate::rasterizer::ColoredChar;
+use crate::tui::cell_size::cell_aspect_ratio;
+use crate::tui::keymap::{KeyAction, Keymap};
 
 use crossterm::{
-    event::{self},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
 };
 use ratatui::prelude::{CrosstermBackend, Terminal};
 use std::io::{stdout, Result};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 /// Perform shutdown of terminal
 pub fn shutdown() -> Result<()> {
-    stdout().execute(LeaveAlternateScreen)?;
+    execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen)?;
     disable_raw_mode()?;
     Ok(())
 }
@@ -31,11 +37,213 @@ pub fn shutdown() -> Result<()> {
 /// Start the terminal
 pub fn startup() -> Result<()> {
     enable_raw_mode()?;
-    execute!(std::io::stderr(), EnterAlternateScreen)?;
+    execute!(std::io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
     Ok(())
 }
 
-pub async fn run_new() -> Result<()> {
+/// Size of the grid of pixels that the SSIM rasterizer compares against each glyph
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 16;
+
+/// Glyphs for the SSIM rasterizer, drawn at the size of each cell's grid of pixels
+pub type Glyphs = AsciiMatrices<GLYPH_WIDTH, GLYPH_HEIGHT>;
+
+type TuiState = State<WindowlessState<GLYPH_WIDTH, GLYPH_HEIGHT>>;
+
+/// Draw the glyphs for the SSIM rasterizer, defaulting to the bundled font and ASCII
+/// Gives no glyphs when neither a font nor a glyph set is chosen, so that the basic rasterizer is used
+pub fn load_glyphs(
+    font: Option<&Path>,
+    glyph_set: Option<GlyphSet>,
+) -> std::result::Result<Option<Glyphs>, GlyphError> {
+    if font.is_none() && glyph_set.is_none() {
+        return Ok(None);
+    }
+    let glyph_set = glyph_set.unwrap_or_default();
+    let glyphs = match font {
+        Some(path) => Glyphs::with_glyphs(&load_font(path)?, &glyph_set),
+        None => Glyphs::with_glyphs(&get_font(), &glyph_set),
+    };
+    Ok(Some(glyphs))
+}
+
+/// How often the input thread checks whether the event loop has finished, while no input arrives
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Anything the event loop waits for, sent from the input and render threads
+enum LoopEvent {
+    Input(Event),
+    /// Reading input failed, so the input thread has stopped
+    InputFailed(std::io::Error),
+    Frame(RenderedFrame),
+    /// The render thread panicked, such as when no GPU could be found, with the panic message
+    RenderFailed(String),
+}
+
+/// Instructions for the render thread
+#[derive(Debug)]
+enum RenderCommand {
+    Input(UnifiedEvent),
+    Resize(PhysicalSize<u32>),
+    Quit,
+}
+
+/// Frame already turned into characters, so the event loop only has to draw it
+struct RenderedFrame {
+    chars: Vec<ColoredChar>,
+    width: usize,
+}
+
+/// Commands that arrived while a frame was being rendered, all of which are applied before rendering again
+/// Only the latest size matters, but every input is kept so that none are dropped
+#[derive(Debug, Default)]
+struct PendingWork {
+    resize: Option<PhysicalSize<u32>>,
+    inputs: Vec<UnifiedEvent>,
+    quit: bool,
+}
+
+impl PendingWork {
+    fn add(&mut self, command: RenderCommand) {
+        match command {
+            RenderCommand::Input(event) => self.inputs.push(event),
+            RenderCommand::Resize(size) => self.resize = Some(size),
+            RenderCommand::Quit => self.quit = true,
+        }
+    }
+}
+
+/// Send terminal events to the event loop until it stops listening
+fn read_input(events: &flume::Sender<LoopEvent>) -> Result<()> {
+    while !events.is_disconnected() {
+        if event::poll(INPUT_POLL_INTERVAL)?
+            && events.send(LoopEvent::Input(event::read()?)).is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn apply_input(state: &mut TuiState, event: UnifiedEvent) {
+    // Bound like the terminal viewer, so that they don't clash with the lighting keys
+    let action = event
+        .key
+        .and_then(|key| state.camera_controller.keymap.action(key));
+    if event.kind == UnifiedKeyKind::Press {
+        match action {
+            Some(KeyAction::CycleDithering) => {
+                state.inner_state.rasterizer.cycle_dithering(&state.device)
+            }
+            Some(KeyAction::ToggleOutlines) => state.inner_state.shading.toggle_outlines(),
+            Some(KeyAction::ToggleToon) => state.inner_state.shading.toggle_toon(),
+            _ => {}
+        }
+    }
+    state.input(event);
+    state.update();
+    // Most terminals don't report key releases, so each press only moves the camera once
+    state.camera_controller.reset_velocity();
+}
+
+fn rendered_frame(state: &TuiState) -> RenderedFrame {
+    let rasterizer = &state.inner_state.rasterizer;
+    let chars = state
+        .inner_state
+        .output_image
+        .chunks(4usize)
+        .map(|c| ColoredChar {
+            symbol: rasterizer.code_to_symbol(c[3]),
+            ..ColoredChar::from(c[3])
+        })
+        .collect();
+    RenderedFrame {
+        chars,
+        width: state.inner_state.output_size().width as usize,
+    }
+}
+
+/// Own the GPU state, rendering a frame whenever commands arrive until told to quit
+/// The first frame is rendered straight away to avoid a blank screen upon loading
+fn render_loop(
+    size: PhysicalSize<u32>,
+    cell_aspect_ratio: f32,
+    glyphs: Option<Glyphs>,
+    keymap: Keymap,
+    commands: flume::Receiver<RenderCommand>,
+    events: flume::Sender<LoopEvent>,
+) {
+    // The SSIM rasterizer compares a grid of pixels the size of its glyphs, while the basic one uses single pixels
+    let grid_size = match glyphs {
+        Some(_) => PhysicalSize::new(GLYPH_WIDTH as u32, GLYPH_HEIGHT as u32),
+        None => PhysicalSize::new(1, 1),
+    };
+    let mut state = pollster::block_on(TuiState::new(
+        size,
+        grid_size,
+        cell_aspect_ratio,
+        glyphs,
+        keymap,
+    ));
+    state.camera_controller.speed *= 3.0;
+
+    let mut work = PendingWork::default();
+    loop {
+        if let Some(size) = work.resize.take() {
+            state.resize(size);
+            state.update();
+        }
+        for event in work.inputs.drain(..) {
+            apply_input(&mut state, event);
+        }
+        if pollster::block_on(state.render()).is_err() {
+            error!("Something went wrong with rendering.")
+        }
+        if events
+            .send(LoopEvent::Frame(rendered_frame(&state)))
+            .is_err()
+        {
+            break;
+        }
+
+        // Wait for something to change, then take everything else that arrived in the meantime
+        let Ok(command) = commands.recv() else {
+            break;
+        };
+        work.add(command);
+        for command in commands.try_iter() {
+            work.add(command);
+        }
+        if work.quit {
+            break;
+        }
+    }
+}
+
+/// Run the render thread, telling the event loop if it panics rather than leaving it waiting for frames forever
+fn report_render_failure(
+    events: flume::Sender<LoopEvent>,
+    render: impl FnOnce(flume::Sender<LoopEvent>),
+) {
+    let failed = events.clone();
+    if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| render(events))) {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        let _ = failed.send(LoopEvent::RenderFailed(message));
+    }
+}
+
+/// Event loop reacting to input, resizes and finished frames as they arrive
+/// Frames are rendered on their own thread and input is read on another, so neither holds up the other
+/// The ratio of height to width of the terminal characters is queried from the terminal if not given
+pub async fn run_new(
+    keymap: Keymap,
+    glyphs: Option<Glyphs>,
+    cell_aspect: Option<f32>,
+) -> Result<()> {
     let file_appender = tracing_appender::rolling::hourly("logging", "ssim_gpu.log");
     tracing_subscriber::fmt()
         .with_max_level(Level::INFO)
@@ -44,72 +252,137 @@ pub async fn run_new() -> Result<()> {
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
     terminal.clear()?;
 
-    // let rasterizer = BasicAsciiRasterizer::default();
-
-    let width = terminal.size()?.width as u32;
-    let height = terminal.size()?.height as u32;
-    let mut state = State::<WindowlessState<1, 1>>::new(
-        PhysicalSize { width, height },
-        PhysicalSize {
-            width: 1,
-            height: 1,
-        },
-    )
-    .await;
-    state.camera_controller.speed *= 3.0;
-    // state.camera_controller.speed /= 10.0;
-
-    // Render the first frame to avoid blank screen upon loading
-    if (state.render().await).is_err() {
-        error!("Something went wrong with rendering.")
-    }
+    // Query the terminal before the input thread starts taking its replies
+    let cell_aspect_ratio = cell_aspect_ratio(cell_aspect);
+    let size = terminal.size()?;
+    let size = PhysicalSize {
+        width: size.width as u32,
+        height: size.height as u32,
+    };
 
-    loop {
-        terminal.draw(|frame| {
-            // TODO Fix frame not updating immediately after resize event. Maybe because of await?
-            let frame_width = frame.size().width as u32;
-            let frame_height = frame.size().height as u32;
-            if frame_width != state.inner_state.output_size().width
-                || frame_height != state.inner_state.output_size().height
-            {
-                state.resize(PhysicalSize {
-                    width: frame_width,
-                    height: frame_height,
-                });
+    // The render thread takes the keymap, but quitting is decided here
+    let quit_keymap = keymap.clone();
+    let (event_sender, events) = flume::unbounded();
+    let (commands, command_receiver) = flume::unbounded();
+    let input_thread = {
+        let events = event_sender.clone();
+        thread::spawn(move || {
+            if let Err(error) = read_input(&events) {
+                let _ = events.send(LoopEvent::InputFailed(error));
             }
+        })
+    };
+    let render_thread = thread::spawn(move || {
+        report_render_failure(event_sender, |events| {
+            render_loop(
+                size,
+                cell_aspect_ratio,
+                glyphs,
+                keymap,
+                command_receiver,
+                events,
+            )
+        })
+    });
 
-            let colored_chars: Vec<_> = state
-                .inner_state
-                .output_image
-                .chunks(4usize)
-                .map(|c| c[3])
-                .map(ColoredChar::from)
-                .collect();
-            let widget = chars_to_widget(
-                colored_chars,
-                state.inner_state.output_size().width as usize,
-            );
-
-            frame.render_widget(widget, frame.size());
-        })?;
-
-        let tui_event = event::read()?;
-        let unified_event: UnifiedEvent = (&tui_event).into();
-        if unified_event.keycode == UnifiedKeyCode::Esc {
-            break;
+    let mut frame: Option<RenderedFrame> = None;
+    let mut result = Ok(());
+    while let Ok(event) = events.recv_async().await {
+        match event {
+            LoopEvent::Frame(rendered) => frame = Some(rendered),
+            // Redraw the last frame straight away, then again once it has been rendered at the new size
+            LoopEvent::Input(Event::Resize(width, height)) => {
+                let size = PhysicalSize {
+                    width: width as u32,
+                    height: height as u32,
+                };
+                let _ = commands.send(RenderCommand::Resize(size));
+            }
+            LoopEvent::Input(event) => {
+                let unified_event: UnifiedEvent = (&event).into();
+                if unified_event.quits(&quit_keymap) {
+                    break;
+                }
+                let _ = commands.send(RenderCommand::Input(unified_event));
+                continue;
+            }
+            LoopEvent::InputFailed(error) => {
+                result = Err(error);
+                break;
+            }
+            LoopEvent::RenderFailed(message) => {
+                result = Err(std::io::Error::other(format!(
+                    "Rendering failed: {}",
+                    message
+                )));
+                break;
+            }
+        }
+        if let Some(frame) = frame.as_ref() {
+            terminal.draw(|f| {
+                let widget = chars_to_widget(frame.chars.clone(), frame.width);
+                f.render_widget(widget, f.size());
+            })?;
         }
+    }
+
+    // Both threads stop once the event loop stops listening to them
+    let _ = commands.send(RenderCommand::Quit);
+    drop(events);
+    let _ = render_thread.join();
+    let _ = input_thread.join();
+    result
+}
 
-        // TODO Add logic to compare current size of frame
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::input::UnifiedKeyCode;
 
-        state.input(unified_event);
-        state.update();
-        match state.render().await {
-            Ok(_) => {}
-            Err(_) => {
-                error!("Something went wrong with rendering.")
-            }
-        }
-        state.camera_controller.reset_velocity();
+    #[test]
+    fn test_pending_work() {
+        let key = |keycode| UnifiedEvent {
+            keycode,
+            key: None,
+            kind: UnifiedKeyKind::Press,
+            mouse: None,
+        };
+        let mut work = PendingWork::default();
+        work.add(RenderCommand::Resize(PhysicalSize::new(10, 5)));
+        work.add(RenderCommand::Input(key(UnifiedKeyCode::H)));
+        work.add(RenderCommand::Resize(PhysicalSize::new(20, 8)));
+        work.add(RenderCommand::Input(key(UnifiedKeyCode::J)));
+
+        // Only the latest size is used, but every input is applied in order
+        assert_eq!(work.resize, Some(PhysicalSize::new(20, 8)));
+        let keycodes: Vec<_> = work.inputs.iter().map(|event| event.keycode).collect();
+        assert_eq!(keycodes, vec![UnifiedKeyCode::H, UnifiedKeyCode::J]);
+        assert!(!work.quit);
+        work.add(RenderCommand::Quit);
+        assert!(work.quit);
+    }
+
+    #[test]
+    fn test_load_glyphs() {
+        // Without either option the basic rasterizer is used
+        assert!(matches!(load_glyphs(None, None), Ok(None)));
+        let glyphs = load_glyphs(None, Some(GlyphSet::blocks()))
+            .unwrap()
+            .unwrap();
+        assert!(glyphs.len() > 1);
+        assert!(matches!(
+            load_glyphs(Some(Path::new("./data/does_not_exist.ttf")), None),
+            Err(GlyphError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_render_failure_reported() {
+        let (events, received) = flume::unbounded();
+        report_render_failure(events, |_| panic!("no adapter"));
+        assert!(matches!(
+            received.try_recv(),
+            Ok(LoopEvent::RenderFailed(message)) if message == "no adapter"
+        ));
     }
-    Ok(())
 }
@@ -2,14 +2,16 @@ use tracing::warn;
 use tracing::Level;
 use tracing_subscriber;
 
+use crate::gpu::input::UnifiedEvent;
 use crate::gpu::state_windowed::WindowedState;
 use crate::gpu::State;
+use crate::tui::keymap::Keymap;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
 };
 
-pub async fn run() {
+pub async fn run(keymap: Keymap) {
     tracing_subscriber::fmt().with_max_level(Level::WARN).init();
 
     let event_loop = EventLoop::new();
@@ -20,7 +22,9 @@ pub async fn run() {
         .unwrap();
 
     // State::new uses async code, so we're going to wait for it to finish
-    let mut state = State::<WindowedState>::new(window).await;
+    let mut state = State::<WindowedState>::new(window, keymap).await;
+    // Needed to tell which character a key types
+    let mut modifiers = ModifiersState::empty();
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -30,18 +34,18 @@ pub async fn run() {
                 ref event,
                 window_id,
             } if window_id == state.window().id() => {
-                if !state.input(event.into()) {
+                if let WindowEvent::ModifiersChanged(new_modifiers) = event {
+                    modifiers = *new_modifiers;
+                }
+                let unified_event = UnifiedEvent::from_window_event(event, modifiers);
+                if !state.input(unified_event) {
                     match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
+                        WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                        WindowEvent::KeyboardInput { .. }
+                            if unified_event.quits(&state.camera_controller.keymap) =>
+                        {
+                            *control_flow = ControlFlow::Exit
+                        }
                         WindowEvent::Resized(physical_size) => {
                             state.resize(*physical_size);
                         }
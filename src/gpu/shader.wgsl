@@ -9,11 +9,22 @@ struct Camera {
 var<uniform> camera: Camera;
 
 struct Light {
+    // Direction towards directional lights, or position of point lights
     position: vec3<f32>,
+    // 0 for directional, 1 for point and 2 for headlight, matching `gpu::light`
+    kind: u32,
     color: vec3<f32>,
+    intensity: f32,
+}
+struct Lighting {
+    lights: array<Light, 4>,
+    count: u32,
+    ambient: f32,
+    specular: f32,
+    shininess: f32,
 }
 @group(1) @binding(0)
-var<uniform> light: Light;
+var<uniform> lighting: Lighting;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -64,27 +75,31 @@ fn vs_main(
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let object_color = vec4f(0.5, 0.1, 0.1, 1.0); // NOTE I changed this, obviously
-    
-    // We don't need (or want) much ambient light, so 0.1 is fine
-    let ambient_strength = 0.2;
-    let ambient_color = light.color * ambient_strength;
 
-    let light_dir = normalize(light.position - in.world_position);
+    // Same lighting model as `Lighting::shade` on the CPU
+    let normal = normalize(in.world_normal);
     let view_dir = normalize(camera.view_pos.xyz - in.world_position);
-    let half_dir = normalize(view_dir + light_dir);
-
-    let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
-    let diffuse_color = light.color * diffuse_strength;
+    var light_strength = lighting.ambient;
+    for (var i = 0u; i < min(lighting.count, 4u); i++) {
+        let light = lighting.lights[i];
+        var light_dir = view_dir;
+        if light.kind == 0u {
+            light_dir = normalize(light.position);
+        } else if light.kind == 1u {
+            light_dir = normalize(light.position - in.world_position);
+        }
 
-    let specular_strength = pow(max(dot(in.world_normal, half_dir), 0.0), 32.0);
-    let specular_color = specular_strength * light.color;
-
-    // let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
-    // return vec4<f32>(result, object_color.a);
+        let diffuse_strength = dot(normal, light_dir);
+        if diffuse_strength > 0.0 {
+            let half_dir = normalize(view_dir + light_dir);
+            let specular_strength = lighting.specular * pow(max(dot(normal, half_dir), 0.0), lighting.shininess);
+            // Weighting each light by the brightness of its colour
+            let brightness = dot(light.color, vec3<f32>(0.2126, 0.7152, 0.0722));
+            light_strength += brightness * light.intensity * (diffuse_strength + specular_strength);
+        }
+    }
 
     // Encoding intensity information in alpha only for easier TUI debugging
     // Capping max to 1.0
-    let light_strength = min(ambient_strength + diffuse_strength + specular_strength, 1.0);
-    return vec4<f32>(object_color.xyz, light_strength - 0.001);
+    return vec4<f32>(object_color.xyz, min(light_strength, 1.0) - 0.001);
 }
-
@@ -4,7 +4,7 @@ use wgpu::util::DeviceExt;
 use wgpu::TextureView;
 use winit::dpi::PhysicalSize;
 
-use crate::ascii::glyph_render::{get_font, AsciiMatrices, NUM_ASCII_MATRICES};
+use crate::ascii::glyph_render::AsciiMatrices;
 use crate::gpu::state_windowless::ValidGridSize;
 
 #[derive(Debug)]
@@ -22,7 +22,10 @@ pub struct FancyGPURasterizer<const W: usize, const H: usize> {
 
     // Pre-rendered ASCII glyphs
     pub ascii_matrices: AsciiMatrices<W, H>,
+    /// Characters indexed by the codes written by the compute shader
+    pub symbols: Vec<char>,
     pub ascii_bind_group: wgpu::BindGroup,
+    pub ascii_bind_group_layout: wgpu::BindGroupLayout,
     pub ascii_matrix_buffer: wgpu::Buffer,
     pub ascii_stats_buffer: wgpu::Buffer,
 
@@ -38,9 +41,11 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
     const SSIM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
     const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Uint;
 
+    /// Constructor taking glyph matrices, which can be built from any font and glyph set
     pub fn new(
         grid_size: ValidGridSize,
         output_size: PhysicalSize<u32>,
+        ascii_matrices: AsciiMatrices<W, H>,
         device: &wgpu::Device,
         input_view: &TextureView,
         output_view: &TextureView,
@@ -102,11 +107,93 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
                     count: None, // We do not need a count because we are not using an array of textures, just a 3D texture
                 }],
             });
+        let (ssim_texture, ssim_view, ssim_bind_group) = Self::create_ssim_resources(
+            device,
+            &ssim_bind_group_layout,
+            output_size,
+            ascii_matrices.len(),
+        );
+
+        // ASCII information derived from font
+        let ascii_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None, // TODO Check if this should be None Some(97)
+                    },
+                ],
+                label: Some("ASCII Bind Group Layout"),
+            });
+        let (ascii_matrix_buffer, ascii_stats_buffer, ascii_bind_group) =
+            Self::create_ascii_resources(device, &ascii_bind_group_layout, &ascii_matrices);
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &ssim_bind_group_layout,
+                    &ascii_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let (compute_ssim_pipeline, compute_ascii_pipeline) = Self::create_compute_pipelines(
+            device,
+            &compute_pipeline_layout,
+            grid_size,
+            ascii_matrices.len(),
+        );
+
+        Self {
+            grid_size,
+            compute_ssim_pipeline,
+            compute_ascii_pipeline,
+            compute_pipeline_layout,
+            texture_bind_group,
+            texture_bind_group_layout,
+            ssim_bind_group,
+            ssim_bind_group_layout,
+            ssim_texture,
+            ssim_view,
+            symbols: ascii_matrices.symbols(),
+            ascii_matrices,
+            ascii_matrix_buffer,
+            ascii_stats_buffer,
+            ascii_bind_group,
+            ascii_bind_group_layout,
+        }
+    }
+
+    /// Create the texture holding the SSIM of every glyph for every output cell
+    fn create_ssim_resources(
+        device: &wgpu::Device,
+        ssim_bind_group_layout: &wgpu::BindGroupLayout,
+        output_size: PhysicalSize<u32>,
+        num_glyphs: usize,
+    ) -> (wgpu::Texture, wgpu::TextureView, wgpu::BindGroup) {
         let ssim_texture = device.create_texture(&wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
                 width: output_size.width,
                 height: output_size.height,
-                depth_or_array_layers: 1,
+                depth_or_array_layers: num_glyphs as u32,
             },
             mip_level_count: 1,
             sample_count: 1,
@@ -119,58 +206,37 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
         let ssim_view = ssim_texture.create_view(&wgpu::TextureViewDescriptor::default());
         let ssim_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("SSIM Bind Group"),
-            layout: &ssim_bind_group_layout,
+            layout: ssim_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
                 resource: wgpu::BindingResource::TextureView(&ssim_view),
             }],
         });
+        (ssim_texture, ssim_view, ssim_bind_group)
+    }
 
-        // ASCII information derived from font
-        let font = get_font();
-        let ascii_matrices = AsciiMatrices::<W, H>::new(&font);
+    /// Upload the glyph matrices and their statistics to the GPU
+    fn create_ascii_resources(
+        device: &wgpu::Device,
+        ascii_bind_group_layout: &wgpu::BindGroupLayout,
+        ascii_matrices: &AsciiMatrices<W, H>,
+    ) -> (wgpu::Buffer, wgpu::Buffer, wgpu::BindGroup) {
         // FIXME Problem because float32 is not big enough
         let ascii_matrix_raw = ascii_matrices.padded_matrix_list();
         let ascii_stats = ascii_matrices.matrix_stats();
         let ascii_matrix_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("ASCII Matrix Buffer"),
-            contents: bytemuck::cast_slice(&[ascii_matrix_raw]),
+            contents: bytemuck::cast_slice(&ascii_matrix_raw),
             usage: wgpu::BufferUsages::STORAGE,
         });
         let ascii_stats_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("ASCII Means Buffer"),
-            contents: bytemuck::cast_slice(&[ascii_stats]),
+            contents: bytemuck::cast_slice(&ascii_stats),
             usage: wgpu::BufferUsages::UNIFORM,
         });
-        let ascii_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None, // TODO Check if this should be None Some(97)
-                    },
-                ],
-                label: Some("ASCII Bind Group Layout"),
-            });
         let ascii_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("ASCII Bind Group"),
-            layout: &ascii_bind_group_layout,
+            layout: ascii_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -190,25 +256,24 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
                 },
             ],
         });
+        (ascii_matrix_buffer, ascii_stats_buffer, ascii_bind_group)
+    }
 
-        let compute_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Compute Pipeline Layout"),
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &ssim_bind_group_layout,
-                    &ascii_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
-
+    /// Compile the compute shaders with the grid size and number of glyphs baked in as constants
+    fn create_compute_pipelines(
+        device: &wgpu::Device,
+        compute_pipeline_layout: &wgpu::PipelineLayout,
+        grid_size: ValidGridSize,
+        num_glyphs: usize,
+    ) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
         let ssim_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Compute SSIM Shader Source"),
             source: wgpu::ShaderSource::Wgsl(
                 format!(
-                    "const grid_width: u32 = {}u;\nconst grid_height: u32 = {}u;\n{}",
+                    "const grid_width: u32 = {}u;\nconst grid_height: u32 = {}u;\nconst num_glyphs: u32 = {}u;\n{}",
                     grid_size.width(),
                     grid_size.height(),
+                    num_glyphs,
                     include_str!("compute_ssim.wgsl")
                 )
                 .into(),
@@ -217,34 +282,54 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
         let compute_ssim_pipeline =
             device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                 label: Some("Compute SSIM Pipeline Descriptor"),
-                layout: Some(&compute_pipeline_layout),
+                layout: Some(compute_pipeline_layout),
                 module: &ssim_shader_module,
                 entry_point: "compute_ssim",
             });
         let compute_ascii_pipeline =
             device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                 label: Some("Compute ASCII Pipeline Descriptor"),
-                layout: Some(&compute_pipeline_layout),
+                layout: Some(compute_pipeline_layout),
                 module: &ssim_shader_module,
                 entry_point: "ascii_from_ssim",
             });
+        (compute_ssim_pipeline, compute_ascii_pipeline)
+    }
 
-        Self {
-            grid_size,
-            compute_ssim_pipeline,
-            compute_ascii_pipeline,
-            compute_pipeline_layout,
-            texture_bind_group,
-            texture_bind_group_layout,
-            ssim_bind_group,
-            ssim_bind_group_layout,
-            ssim_texture,
-            ssim_view,
-            ascii_matrices,
-            ascii_matrix_buffer,
-            ascii_stats_buffer,
-            ascii_bind_group,
-        }
+    /// Swap out the glyphs at runtime, e.g. after loading a different font or glyph set
+    /// Rebuilds the GPU buffers and recompiles the shaders, since the number of glyphs is baked in
+    pub fn set_glyphs(&mut self, ascii_matrices: AsciiMatrices<W, H>, device: &wgpu::Device) {
+        self.ascii_matrix_buffer.destroy();
+        self.ascii_stats_buffer.destroy();
+        (
+            self.ascii_matrix_buffer,
+            self.ascii_stats_buffer,
+            self.ascii_bind_group,
+        ) = Self::create_ascii_resources(device, &self.ascii_bind_group_layout, &ascii_matrices);
+        (self.compute_ssim_pipeline, self.compute_ascii_pipeline) = Self::create_compute_pipelines(
+            device,
+            &self.compute_pipeline_layout,
+            self.grid_size,
+            ascii_matrices.len(),
+        );
+        let ssim_size = self.ssim_texture.size();
+        self.ssim_texture.destroy();
+        (self.ssim_texture, self.ssim_view, self.ssim_bind_group) = Self::create_ssim_resources(
+            device,
+            &self.ssim_bind_group_layout,
+            PhysicalSize {
+                width: ssim_size.width,
+                height: ssim_size.height,
+            },
+            ascii_matrices.len(),
+        );
+        self.symbols = ascii_matrices.symbols();
+        self.ascii_matrices = ascii_matrices;
+    }
+
+    /// Convert a code written by the compute shader back into its character
+    pub fn code_to_symbol(&self, code: u8) -> char {
+        self.symbols.get(code as usize).copied().unwrap_or(' ')
     }
 
     pub fn resize(
@@ -269,31 +354,13 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
             ],
         });
 
-        self.ssim_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: output_size.width,
-                height: output_size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D3,
-            format: Self::SSIM_FORMAT,
-            view_formats: &[],
-            usage: wgpu::TextureUsages::STORAGE_BINDING,
-            label: Some("SSIM Texture"),
-        });
-        self.ssim_view = self
-            .ssim_texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        self.ssim_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("SSIM Bind Group"),
-            layout: &self.ssim_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&self.ssim_view),
-            }],
-        });
+        self.ssim_texture.destroy();
+        (self.ssim_texture, self.ssim_view, self.ssim_bind_group) = Self::create_ssim_resources(
+            device,
+            &self.ssim_bind_group_layout,
+            output_size,
+            self.ascii_matrices.len(),
+        );
     }
 
     pub fn run_compute(
@@ -314,7 +381,7 @@ impl<const W: usize, const H: usize> FancyGPURasterizer<W, H> {
         compute_pass.dispatch_workgroups(
             output_size.width,
             output_size.height,
-            NUM_ASCII_MATRICES as u32,
+            self.ascii_matrices.len() as u32,
         );
 
         compute_pass.set_pipeline(&self.compute_ascii_pipeline);
@@ -2,6 +2,7 @@ use std::iter;
 
 use crate::gpu::model::{DrawLight, DrawModel};
 use crate::gpu::{InnerState, State};
+use crate::tui::keymap::Keymap;
 use winit::{dpi::PhysicalSize, window::Window};
 
 #[derive(Debug)]
@@ -65,7 +66,7 @@ impl InnerState for WindowedState {
 }
 
 impl State<WindowedState> {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, keymap: Keymap) -> Self {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -82,7 +83,7 @@ impl State<WindowedState> {
         let (adapter, device, queue) =
             Self::create_adapter_device_queue(Some(&surface), &instance).await;
         let inner_state = WindowedState::new(window, surface, size, &adapter, &device);
-        Self::new_from_inner_state(inner_state, device, queue).await
+        Self::new_from_inner_state(inner_state, device, queue, keymap).await
     }
     pub fn window(&self) -> &Window {
         &self.inner_state.window
@@ -130,8 +131,9 @@ impl State<WindowedState> {
 
             render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
             render_pass.set_pipeline(&self.light_render_pipeline);
-            render_pass.draw_light_model(
+            render_pass.draw_light_model_instanced(
                 &self.obj_model,
+                0..self.light_markers(),
                 &self.camera_bind_group,
                 &self.light_bind_group,
             );
@@ -2,14 +2,17 @@ use image::{ImageBuffer, Rgba};
 use std::iter;
 use winit::dpi::PhysicalSize;
 
+use crate::ascii::glyph_render::AsciiMatrices;
+use crate::dither::Dithering;
 use crate::gpu::{
     basic_rasterizer::BasicGPURasterizer,
     model::{DrawLight, DrawModel},
+    post_process::{PostProcess, PostProcessUniform},
     ssim_rasterizer::FancyGPURasterizer,
     InnerState, State,
 };
-
-const FONT_ASPECT_RATIO: f32 = 2.0;
+use crate::shading::Shading;
+use crate::tui::keymap::Keymap;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ValidGridSize {
@@ -42,6 +45,64 @@ impl ValidGridSize {
     }
 }
 
+/// Compute shader turning rendered pixels into characters
+#[derive(Debug)]
+pub enum GPURasterizer<const W: usize, const H: usize> {
+    /// Picks characters by brightness alone, writing ASCII codes directly
+    Basic(BasicGPURasterizer),
+    /// Picks the glyph whose shape is most structurally similar to each grid of pixels
+    Ssim(Box<FancyGPURasterizer<W, H>>),
+}
+
+impl<const W: usize, const H: usize> GPURasterizer<W, H> {
+    pub fn grid_size(&self) -> ValidGridSize {
+        match self {
+            Self::Basic(rasterizer) => rasterizer.grid_size,
+            Self::Ssim(rasterizer) => rasterizer.grid_size,
+        }
+    }
+    /// Move on to the next dithering, which only the basic rasterizer supports
+    pub fn cycle_dithering(&mut self, device: &wgpu::Device) {
+        if let Self::Basic(rasterizer) = self {
+            let dithering = rasterizer.dithering.next();
+            rasterizer.set_dithering(dithering, device);
+        }
+    }
+    /// Convert a code written by the compute shader back into its character
+    pub fn code_to_symbol(&self, code: u8) -> char {
+        match self {
+            Self::Basic(rasterizer) => rasterizer.code_to_symbol(code),
+            Self::Ssim(rasterizer) => rasterizer.code_to_symbol(code),
+        }
+    }
+    pub fn resize(
+        &mut self,
+        output_size: PhysicalSize<u32>,
+        device: &wgpu::Device,
+        input_view: &wgpu::TextureView,
+        output_view: &wgpu::TextureView,
+    ) {
+        match self {
+            Self::Basic(rasterizer) => {
+                rasterizer.resize(output_size, device, input_view, output_view)
+            }
+            Self::Ssim(rasterizer) => {
+                rasterizer.resize(output_size, device, input_view, output_view)
+            }
+        }
+    }
+    pub fn run_compute(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_size: PhysicalSize<u32>,
+    ) {
+        match self {
+            Self::Basic(rasterizer) => rasterizer.run_compute(encoder, output_size),
+            Self::Ssim(rasterizer) => rasterizer.run_compute(encoder, output_size),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct WindowlessState<const W: usize, const H: usize> {
     pub output_size: winit::dpi::PhysicalSize<u32>,
@@ -51,8 +112,15 @@ pub struct WindowlessState<const W: usize, const H: usize> {
     pub intermediate_texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub intermediate_view: wgpu::TextureView,
-    pub rasterizer: BasicGPURasterizer,
-    // pub rasterizer: FancyGPURasterizer<W, H>,
+    /// Rendered pixels after toon shading and outlines, which are turned into characters
+    pub processed_texture: wgpu::Texture,
+    pub processed_view: wgpu::TextureView,
+    pub post_process: PostProcess,
+    /// Only the toon shading and outlines are used on the GPU
+    pub shading: Shading,
+    pub rasterizer: GPURasterizer<W, H>,
+    /// Ratio of height to width of the terminal characters
+    pub cell_aspect_ratio: f32,
 }
 
 impl<const W: usize, const H: usize> WindowlessState<W, H> {
@@ -71,9 +139,12 @@ impl<const W: usize, const H: usize> WindowlessState<W, H> {
         (width + 63) & !63
     }
 
+    /// Uses the SSIM rasterizer when given glyphs, in which case the grid size must match the glyphs
     pub fn new(
         output_size: PhysicalSize<u32>,
         grid_size: ValidGridSize,
+        cell_aspect_ratio: f32,
+        glyphs: Option<AsciiMatrices<W, H>>,
         device: &wgpu::Device,
     ) -> Self {
         // TODO Need to add functionality for changing this
@@ -105,6 +176,15 @@ impl<const W: usize, const H: usize> WindowlessState<W, H> {
         let intermediate_texture = device.create_texture(&intermediate_texture_desc);
         let intermediate_view =
             intermediate_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let processed_texture = PostProcess::create_output_texture(
+            device,
+            PhysicalSize {
+                width: output_size.width * grid_size.width(),
+                height: output_size.height * grid_size.height(),
+            },
+        );
+        let processed_view = processed_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let post_process = PostProcess::new(device);
 
         let texture_desc = wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
@@ -127,9 +207,23 @@ impl<const W: usize, const H: usize> WindowlessState<W, H> {
         let output_image_size = output_size.width as usize * output_size.height as usize * 4;
         let output_image = Vec::<u8>::with_capacity(output_image_size);
 
-        let rasterizer = BasicGPURasterizer::new(grid_size, device, &intermediate_view, &view);
-        // let rasterizer =
-        //     FancyGPURasterizer::new(grid_size, output_size, device, &intermediate_view, &view);
+        let rasterizer = match glyphs {
+            Some(ascii_matrices) => GPURasterizer::Ssim(Box::new(FancyGPURasterizer::new(
+                grid_size,
+                output_size,
+                ascii_matrices,
+                device,
+                &processed_view,
+                &view,
+            ))),
+            None => GPURasterizer::Basic(BasicGPURasterizer::new(
+                grid_size,
+                Dithering::default(),
+                device,
+                &processed_view,
+                &view,
+            )),
+        };
 
         Self {
             output_size,
@@ -139,7 +233,12 @@ impl<const W: usize, const H: usize> WindowlessState<W, H> {
             intermediate_texture,
             view,
             intermediate_view,
+            processed_texture,
+            processed_view,
+            post_process,
+            shading: Shading::default(),
             rasterizer,
+            cell_aspect_ratio,
         }
     }
 }
@@ -150,19 +249,25 @@ impl<const W: usize, const H: usize> InnerState for WindowlessState<W, H> {
     }
     fn render_size(&self) -> PhysicalSize<u32> {
         PhysicalSize {
-            width: self.output_size.width * self.rasterizer.grid_size.width(),
-            height: self.output_size.height * self.rasterizer.grid_size.height(),
+            width: self.output_size.width * self.rasterizer.grid_size().width(),
+            height: self.output_size.height * self.rasterizer.grid_size().height(),
         }
     }
     fn format(&self) -> wgpu::TextureFormat {
         Self::INTERMEDIATE_FORMAT
     }
+    /// Each character is split into a grid of pixels
+    fn pixel_aspect_ratio(&self) -> f32 {
+        self.cell_aspect_ratio * self.rasterizer.grid_size().width() as f32
+            / self.rasterizer.grid_size().height() as f32
+    }
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>, device: &wgpu::Device) {
         self.output_size = new_size;
 
         self.output_buffer.destroy();
         self.texture.destroy();
         self.intermediate_texture.destroy();
+        self.processed_texture.destroy();
 
         // TODO Find a solution without repeating so much code
         let output_buffer_size = (Self::U32_SIZE
@@ -178,8 +283,8 @@ impl<const W: usize, const H: usize> InnerState for WindowlessState<W, H> {
 
         let intermediate_texture_desc = wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
-                width: self.output_size.width * self.rasterizer.grid_size.width(),
-                height: self.output_size.height * self.rasterizer.grid_size.height(),
+                width: self.output_size.width * self.rasterizer.grid_size().width(),
+                height: self.output_size.height * self.rasterizer.grid_size().height(),
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
@@ -194,6 +299,10 @@ impl<const W: usize, const H: usize> InnerState for WindowlessState<W, H> {
         self.intermediate_view = self
             .intermediate_texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        self.processed_texture = PostProcess::create_output_texture(device, self.render_size());
+        self.processed_view = self
+            .processed_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
 
         let texture_desc = wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
@@ -217,13 +326,19 @@ impl<const W: usize, const H: usize> InnerState for WindowlessState<W, H> {
         // TODO Move bind group creation to separate function
 
         self.rasterizer
-            .resize(new_size, device, &self.intermediate_view, &self.view);
+            .resize(new_size, device, &self.processed_view, &self.view);
         // TODO Work out logic for new offset
     }
 }
 
 impl<const W: usize, const H: usize> State<WindowlessState<W, H>> {
-    pub async fn new(output_size: PhysicalSize<u32>, grid_size: PhysicalSize<u32>) -> Self {
+    pub async fn new(
+        output_size: PhysicalSize<u32>,
+        grid_size: PhysicalSize<u32>,
+        cell_aspect_ratio: f32,
+        glyphs: Option<AsciiMatrices<W, H>>,
+        keymap: Keymap,
+    ) -> Self {
         // The instance is a handle to our GPU
         // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
@@ -233,19 +348,9 @@ impl<const W: usize, const H: usize> State<WindowlessState<W, H>> {
         // TODO Consider moving this valid grid size creation into inner state
         let grid_size = ValidGridSize::new(grid_size.width, grid_size.height);
         let (_adapter, device, queue) = Self::create_adapter_device_queue(None, &instance).await;
-        let inner_state = WindowlessState::new(output_size, grid_size, &device);
-        let mut state = Self::new_from_inner_state(inner_state, device, queue).await;
-
-        state.fix_aspect_ratio();
-        state
-    }
-
-    /// Account for the fact that font height is roughly twice the width
-    fn fix_aspect_ratio(&mut self) {
-        let grid_ratio = self.inner_state.rasterizer.grid_size.height() as f32
-            / self.inner_state.rasterizer.grid_size.width() as f32;
-        self.camera.aspect /= FONT_ASPECT_RATIO / grid_ratio;
-        self.update();
+        let inner_state =
+            WindowlessState::new(output_size, grid_size, cell_aspect_ratio, glyphs, &device);
+        Self::new_from_inner_state(inner_state, device, queue, keymap).await
     }
 
     // TODO Need to change this error
@@ -287,8 +392,9 @@ impl<const W: usize, const H: usize> State<WindowlessState<W, H>> {
 
             render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
             render_pass.set_pipeline(&self.light_render_pipeline);
-            render_pass.draw_light_model(
+            render_pass.draw_light_model_instanced(
                 &self.obj_model,
+                0..self.light_markers(),
                 &self.camera_bind_group,
                 &self.light_bind_group,
             );
@@ -301,6 +407,23 @@ impl<const W: usize, const H: usize> State<WindowlessState<W, H>> {
             );
         }
         {
+            let bind_group = self.inner_state.post_process.create_bind_group(
+                &self.device,
+                &self.inner_state.intermediate_view,
+                &self.depth_texture,
+                &self.inner_state.processed_view,
+            );
+            self.inner_state.post_process.run_compute(
+                &mut encoder,
+                &self.queue,
+                PostProcessUniform::new(
+                    &self.inner_state.shading,
+                    self.camera.znear,
+                    self.camera.zfar,
+                ),
+                &bind_group,
+                self.inner_state.render_size(),
+            );
             self.inner_state
                 .rasterizer
                 .run_compute(&mut encoder, self.inner_state.output_size());
@@ -1,8 +1,17 @@
+pub mod arcball;
 pub mod basic_rasterizer;
+pub mod bvh;
+pub mod dither;
+pub mod lighting;
+pub mod measurement;
 pub mod rasterizer;
 pub mod read;
 pub mod render;
 pub mod scene;
+pub mod selection;
+pub mod sequence;
+pub mod shading;
+pub mod supersampling;
 pub mod surface;
 pub mod tui;
 
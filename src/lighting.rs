@@ -0,0 +1,430 @@
+//! Lighting model shared by the CPU and GPU renderers, with ambient, diffuse and Blinn-Phong specular terms.
+
+use nalgebra::{Point3, Rotation3, Unit, Vector3};
+
+/// Largest number of lights, limited by the size of the uniform used by the GPU renderer
+pub const MAX_LIGHTS: usize = 4;
+/// Brightest a lit surface can be, since the rasterizers draw anything brighter as background
+/// shader.wgsl keeps light below this the same way, by subtracting 0.001
+pub const MAX_INTENSITY: f32 = 0.999;
+/// Range of allowed specular exponents
+pub const MIN_SHININESS: f32 = 1.0;
+pub const MAX_SHININESS: f32 = 256.0;
+
+/// Colours lights can be switched between, with a name to show the user
+pub const LIGHT_COLORS: [(&str, [f32; 3]); 6] = [
+    ("white", [1.0, 1.0, 1.0]),
+    ("warm", [1.0, 0.85, 0.6]),
+    ("cool", [0.6, 0.8, 1.0]),
+    ("red", [1.0, 0.2, 0.2]),
+    ("green", [0.2, 1.0, 0.2]),
+    ("blue", [0.3, 0.3, 1.0]),
+];
+
+/// Single light source, where the colour is RGB in the range `0.0..=1.0`
+/// Point lights aren't attenuated with distance, so that moving them only changes the direction of light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    /// Light from infinitely far away, where `direction` points towards the light
+    Directional {
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+    },
+    /// Light from a point in world space
+    Point {
+        position: Point3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+    },
+    /// Light from the camera, so whatever faces the viewer is always lit
+    Headlight { color: [f32; 3], intensity: f32 },
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self::Directional {
+            direction: default_direction(),
+            color: LIGHT_COLORS[0].1,
+            intensity: 0.8,
+        }
+    }
+}
+
+impl Light {
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            Self::Directional { color, .. }
+            | Self::Point { color, .. }
+            | Self::Headlight { color, .. } => *color,
+        }
+    }
+    pub fn set_color(&mut self, new_color: [f32; 3]) {
+        match self {
+            Self::Directional { color, .. }
+            | Self::Point { color, .. }
+            | Self::Headlight { color, .. } => *color = new_color,
+        }
+    }
+    pub fn intensity(&self) -> f32 {
+        match self {
+            Self::Directional { intensity, .. }
+            | Self::Point { intensity, .. }
+            | Self::Headlight { intensity, .. } => *intensity,
+        }
+    }
+    /// Intensity is clamped to be non-negative
+    pub fn set_intensity(&mut self, new_intensity: f32) {
+        match self {
+            Self::Directional { intensity, .. }
+            | Self::Point { intensity, .. }
+            | Self::Headlight { intensity, .. } => *intensity = new_intensity.max(0.0),
+        }
+    }
+    /// Name of the kind of light, to show the user
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Self::Directional { .. } => "Directional",
+            Self::Point { .. } => "Point",
+            Self::Headlight { .. } => "Headlight",
+        }
+    }
+    /// Name of the colour if it is one of `LIGHT_COLORS`, otherwise `"custom"`
+    pub fn color_name(&self) -> &'static str {
+        LIGHT_COLORS
+            .iter()
+            .find(|(_, c)| *c == self.color())
+            .map_or("custom", |(name, _)| name)
+    }
+    /// Swap to the next colour in `LIGHT_COLORS`
+    pub fn next_color(&mut self) {
+        let index = LIGHT_COLORS
+            .iter()
+            .position(|(_, c)| *c == self.color())
+            .map_or(0, |i| (i + 1) % LIGHT_COLORS.len());
+        self.set_color(LIGHT_COLORS[index].1);
+    }
+    /// Swap to the next kind of light, keeping the colour and intensity
+    /// Point lights are placed `distance` away along the direction of the directional light they replace
+    pub fn next_kind(&mut self, distance: f32) {
+        let (color, intensity) = (self.color(), self.intensity());
+        *self = match self {
+            Self::Directional { direction, .. } => Self::Point {
+                position: Point3::from(direction.normalize() * distance),
+                color,
+                intensity,
+            },
+            Self::Point { .. } => Self::Headlight { color, intensity },
+            Self::Headlight { .. } => Self::Directional {
+                direction: default_direction(),
+                color,
+                intensity,
+            },
+        };
+    }
+    /// Rotate the direction or position of the light about the world origin
+    /// Headlights follow the camera, so aren't affected
+    pub fn rotate(&mut self, rotation: &Rotation3<f32>) {
+        match self {
+            Self::Directional { direction, .. } => *direction = rotation * *direction,
+            Self::Point { position, .. } => *position = rotation * *position,
+            Self::Headlight { .. } => {}
+        }
+    }
+    /// Unit vector from a point towards the light, and the distance to the light
+    /// The distance is `f32::MAX` for directional lights
+    /// Returns `None` if the point is at the light
+    pub fn incidence(
+        &self,
+        point: &Point3<f32>,
+        eye: &Point3<f32>,
+    ) -> Option<(Unit<Vector3<f32>>, f32)> {
+        match self {
+            Self::Directional { direction, .. } => {
+                Some((Unit::try_new(*direction, f32::EPSILON)?, f32::MAX))
+            }
+            Self::Point { position, .. } => Unit::try_new_and_get(position - point, f32::EPSILON),
+            Self::Headlight { .. } => Unit::try_new_and_get(eye - point, f32::EPSILON),
+        }
+    }
+    /// Direction for directional lights, position for point lights and zero for headlights
+    /// This is how the light is packed for the GPU
+    pub fn direction_or_position(&self) -> Vector3<f32> {
+        match self {
+            Self::Directional { direction, .. } => *direction,
+            Self::Point { position, .. } => position.coords,
+            Self::Headlight { .. } => Vector3::zeros(),
+        }
+    }
+}
+
+/// Change made by the lighting editors, which acts on the selected light or on how surfaces respond to light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightingEdit {
+    SelectNext,
+    Add,
+    Remove,
+    CycleKind,
+    CycleColor,
+    Rotate(Rotation3<f32>),
+    Intensity(f32),
+    Ambient(f32),
+    Specular(f32),
+    /// Factor the specular exponent is multiplied by
+    Shininess(f32),
+}
+
+/// Every light in a scene, along with how surfaces respond to them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lighting {
+    pub lights: Vec<Light>,
+    /// Intensity added everywhere, regardless of the lights
+    pub ambient: f32,
+    /// Strength of the specular highlights, relative to the diffuse term
+    pub specular: f32,
+    /// Specular exponent, where larger values give smaller and sharper highlights
+    pub shininess: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            lights: vec![Light::default()],
+            ambient: 0.1,
+            specular: 0.3,
+            shininess: 32.0,
+        }
+    }
+}
+
+impl Lighting {
+    /// Intensity of a point on a surface seen from `eye`, capped at `MAX_INTENSITY`
+    /// `visibility` gives the fraction of light reaching the point from a direction and distance, which is how shadows are added
+    /// The CPU renderer has no colour for light, so each light is weighted by the brightness of its colour
+    pub fn shade(
+        &self,
+        point: &Point3<f32>,
+        normal: &Vector3<f32>,
+        eye: &Point3<f32>,
+        visibility: impl Fn(&Unit<Vector3<f32>>, f32) -> f32,
+    ) -> f32 {
+        let view_dir = (eye - point).try_normalize(f32::EPSILON).unwrap_or(*normal);
+        let lit: f32 = self
+            .lights
+            .iter()
+            .filter_map(|light| {
+                let (light_dir, distance) = light.incidence(point, eye)?;
+                let diffuse = normal.dot(&light_dir);
+                // Surfaces facing away aren't lit, and don't need shadow rays
+                if diffuse <= 0.0 {
+                    return None;
+                }
+                let half_dir = (view_dir + light_dir.into_inner()).normalize();
+                let specular = self.specular * normal.dot(&half_dir).max(0.0).powf(self.shininess);
+                Some(
+                    luminance(light.color())
+                        * light.intensity()
+                        * (diffuse + specular)
+                        * visibility(&light_dir, distance),
+                )
+            })
+            .sum();
+        (self.ambient + lit).min(MAX_INTENSITY)
+    }
+    /// Apply an edit, keeping `selected` on a light as lights are added and removed
+    /// Lights that become point lights are placed `distance` from the origin
+    pub fn edit(&mut self, selected: &mut usize, edit: LightingEdit, distance: f32) {
+        match edit {
+            LightingEdit::SelectNext => *selected = (*selected + 1) % self.lights.len().max(1),
+            LightingEdit::Add => {
+                if self.add_light(Light::default()) {
+                    *selected = self.lights.len() - 1;
+                }
+            }
+            LightingEdit::Remove => {
+                if *selected < self.lights.len() {
+                    self.lights.remove(*selected);
+                    *selected = (*selected).min(self.lights.len().saturating_sub(1));
+                }
+            }
+            LightingEdit::Ambient(delta) => self.ambient = (self.ambient + delta).clamp(0.0, 1.0),
+            LightingEdit::Specular(delta) => self.specular = (self.specular + delta).max(0.0),
+            LightingEdit::Shininess(factor) => {
+                self.shininess = (self.shininess * factor).clamp(MIN_SHININESS, MAX_SHININESS)
+            }
+            _ => {
+                let Some(light) = self.lights.get_mut(*selected) else {
+                    return;
+                };
+                match edit {
+                    LightingEdit::CycleKind => light.next_kind(distance),
+                    LightingEdit::CycleColor => light.next_color(),
+                    LightingEdit::Rotate(rotation) => light.rotate(&rotation),
+                    LightingEdit::Intensity(delta) => {
+                        light.set_intensity(light.intensity() + delta)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    /// Add a light, unless there are already `MAX_LIGHTS`
+    /// Returns whether the light was added
+    pub fn add_light(&mut self, light: Light) -> bool {
+        if self.lights.len() >= MAX_LIGHTS {
+            return false;
+        }
+        self.lights.push(light);
+        true
+    }
+}
+
+/// Light from above and in front
+fn default_direction() -> Vector3<f32> {
+    Vector3::new(0.0, 1.0, 1.0).normalize()
+}
+
+/// Perceived brightness of an RGB colour
+pub fn luminance(color: [f32; 3]) -> f32 {
+    0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_shadows(_: &Unit<Vector3<f32>>, _: f32) -> f32 {
+        1.0
+    }
+
+    #[test]
+    fn test_shade() {
+        let lighting = Lighting {
+            lights: vec![Light::Directional {
+                direction: Vector3::y(),
+                color: [1.0, 1.0, 1.0],
+                intensity: 0.5,
+            }],
+            ambient: 0.1,
+            specular: 0.0,
+            shininess: 1.0,
+        };
+        let point = Point3::origin();
+        let eye = Point3::new(0.0, 0.0, 10.0);
+
+        // Facing the light
+        let lit = lighting.shade(&point, &Vector3::y(), &eye, no_shadows);
+        assert!((lit - 0.6).abs() < 1e-6);
+        // Facing away only gets ambient light
+        let unlit = lighting.shade(&point, &-Vector3::y(), &eye, no_shadows);
+        assert!((unlit - 0.1).abs() < 1e-6);
+        // Fully shadowed
+        let shadowed = lighting.shade(&point, &Vector3::y(), &eye, |_, _| 0.0);
+        assert!((shadowed - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_specular_and_headlight() {
+        let mut lighting = Lighting {
+            lights: vec![Light::Headlight {
+                color: [1.0, 1.0, 1.0],
+                intensity: 0.5,
+            }],
+            ambient: 0.0,
+            specular: 0.2,
+            shininess: 16.0,
+        };
+        let point = Point3::origin();
+        let eye = Point3::new(0.0, 0.0, 10.0);
+        // Looking straight on, the highlight is at its brightest
+        let facing = lighting.shade(&point, &Vector3::z(), &eye, no_shadows);
+        assert!((facing - 0.6).abs() < 1e-6);
+
+        // Dim coloured lights are weighted by brightness
+        lighting.lights[0].set_color([0.0, 0.0, 1.0]);
+        let blue = lighting.shade(&point, &Vector3::z(), &eye, no_shadows);
+        assert!((blue - 0.6 * 0.0722).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fully_lit_is_drawn() {
+        use crate::basic_rasterizer::BasicAsciiRasterizer;
+        use crate::rasterizer::{ColoredPixel, Rasterizer};
+
+        let lighting = Lighting {
+            lights: vec![Light::Headlight {
+                color: [1.0, 1.0, 1.0],
+                intensity: 2.0,
+            }],
+            ..Lighting::default()
+        };
+        let point = Point3::origin();
+        let eye = Point3::new(0.0, 0.0, 10.0);
+        let intensity = lighting.shade(&point, &Vector3::z(), &eye, no_shadows);
+        assert_eq!(intensity, MAX_INTENSITY);
+
+        // The brightest highlight is drawn with the lightest glyph rather than as background
+        let pixels = [ColoredPixel::from(intensity)];
+        let chunks: Vec<&[ColoredPixel]> = pixels.chunks(1).collect();
+        let chars = BasicAsciiRasterizer::default().pixels_to_stdout(chunks, 1);
+        assert_eq!(chars[0].symbol, '.');
+    }
+
+    #[test]
+    fn test_point_light() {
+        let light = Light::Point {
+            position: Point3::new(0.0, 4.0, 3.0),
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+        };
+        let (dir, distance) = light
+            .incidence(&Point3::origin(), &Point3::origin())
+            .unwrap();
+        assert!((distance - 5.0).abs() < 1e-6);
+        assert!((dir.into_inner() - Vector3::new(0.0, 0.8, 0.6)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_cycling() {
+        let mut light = Light::default();
+        light.next_kind(10.0);
+        assert!(matches!(light, Light::Point { .. }));
+        light.next_kind(10.0);
+        assert!(matches!(light, Light::Headlight { .. }));
+        light.next_kind(10.0);
+        assert!(matches!(light, Light::Directional { .. }));
+        assert_eq!(light.intensity(), Light::default().intensity());
+
+        for (name, _) in LIGHT_COLORS[1..].iter().chain(LIGHT_COLORS[..1].iter()) {
+            light.next_color();
+            assert_eq!(light.color_name(), *name);
+        }
+
+        let mut lighting = Lighting::default();
+        while lighting.add_light(Light::default()) {}
+        assert_eq!(lighting.lights.len(), MAX_LIGHTS);
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut lighting = Lighting::default();
+        let mut selected = 0;
+        lighting.edit(&mut selected, LightingEdit::Add, 10.0);
+        assert_eq!((lighting.lights.len(), selected), (2, 1));
+        lighting.edit(&mut selected, LightingEdit::CycleKind, 10.0);
+        assert!(matches!(lighting.lights[1], Light::Point { .. }));
+        assert!(matches!(lighting.lights[0], Light::Directional { .. }));
+        lighting.edit(&mut selected, LightingEdit::SelectNext, 10.0);
+        assert_eq!(selected, 0);
+
+        // Removing the last light leaves the selection on the one before
+        lighting.edit(&mut selected, LightingEdit::SelectNext, 10.0);
+        lighting.edit(&mut selected, LightingEdit::Remove, 10.0);
+        assert_eq!((lighting.lights.len(), selected), (1, 0));
+
+        lighting.edit(&mut selected, LightingEdit::Shininess(1000.0), 10.0);
+        assert_eq!(lighting.shininess, MAX_SHININESS);
+        lighting.edit(&mut selected, LightingEdit::Ambient(-1.0), 10.0);
+        assert_eq!(lighting.ambient, 0.0);
+    }
+}
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 use clap::Parser;
-use pdb_tui::tui::ui::{run, shutdown, startup};
+use pdb_tui::tui::{
+    cell_size::cell_aspect_ratio,
+    graphics::OutputMode,
+    keymap::Keymap,
+    ui::{run, shutdown, startup},
+};
 use std::io::Result;
 
 /// Program to render PDBs within a terminal user interface
@@ -10,12 +15,30 @@ struct Args {
     /// PDB file to be loaded
     #[arg(short, long, num_args=1.., default_value = "./data/surface.obj")]
     inputs: Vec<String>,
+    /// How the scene is drawn, using a terminal graphics protocol when available
+    #[arg(short, long, value_enum, default_value_t = OutputMode::Auto)]
+    output: OutputMode,
+    /// Ratio of height to width of terminal characters, queried from the terminal if not given
+    #[arg(long)]
+    cell_aspect: Option<f32>,
+    /// Start on the screen for calibrating the character aspect ratio
+    #[arg(long)]
+    calibrate: bool,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    // Read the config before taking over the terminal, so that any mistakes in it are readable
+    let keymap = Keymap::from_config().map_err(std::io::Error::other)?;
     startup()?;
-    let result = run(args.inputs);
+    let cell_aspect_ratio = cell_aspect_ratio(args.cell_aspect);
+    let result = run(
+        args.inputs,
+        args.output,
+        cell_aspect_ratio,
+        args.calibrate,
+        keymap,
+    );
     shutdown()?;
     result?;
     Ok(())
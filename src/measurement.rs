@@ -0,0 +1,191 @@
+//! Distances, angles and dihedrals between picked atoms, such as when checking contacts between a ligand and a protein.
+//!
+//! Values are measured in the coordinates of the PDB file, so moving the shapes around never changes them.
+
+use crate::{read::AtomInfo, scene::ObjectId};
+use chrono::{DateTime, Local};
+use nalgebra::Point3;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// What is measured, which decides how many atoms have to be picked
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MeasurementKind {
+    #[default]
+    Distance,
+    /// Angle at the middle of three atoms
+    Angle,
+    /// Torsion angle about the bond between the middle two of four atoms
+    Dihedral,
+}
+
+impl MeasurementKind {
+    /// Number of atoms that have to be picked
+    pub fn atom_count(&self) -> usize {
+        match self {
+            Self::Distance => 2,
+            Self::Angle => 3,
+            Self::Dihedral => 4,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Distance => "distance",
+            Self::Angle => "angle",
+            Self::Dihedral => "dihedral",
+        }
+    }
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Self::Distance => "Å",
+            Self::Angle | Self::Dihedral => "°",
+        }
+    }
+}
+
+/// Measurement between picked atoms, keeping what was picked so that it can be drawn over the canvas
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub kind: MeasurementKind,
+    pub atoms: Vec<ObjectId>,
+    /// Names of the atoms, see `AtomInfo::label`
+    pub labels: Vec<String>,
+    /// In Ångströms for distances and degrees for angles
+    pub value: f32,
+}
+
+impl Measurement {
+    /// Measure between picked atoms, or return `None` if the wrong number of atoms were picked
+    pub fn new(kind: MeasurementKind, atoms: &[(ObjectId, &AtomInfo)]) -> Option<Self> {
+        if atoms.len() != kind.atom_count() {
+            return None;
+        }
+        let p: Vec<Point3<f32>> = atoms.iter().map(|(_, atom)| atom.position).collect();
+        let value = match kind {
+            MeasurementKind::Distance => distance(&p[0], &p[1]),
+            MeasurementKind::Angle => angle(&p[0], &p[1], &p[2]),
+            MeasurementKind::Dihedral => dihedral(&p[0], &p[1], &p[2], &p[3]),
+        };
+        Some(Self {
+            kind,
+            atoms: atoms.iter().map(|(id, _)| *id).collect(),
+            labels: atoms.iter().map(|(_, atom)| atom.label()).collect(),
+            value,
+        })
+    }
+    /// Value with its unit, such as `3.80 Å`
+    pub fn value_text(&self) -> String {
+        format!("{:.2} {}", self.value, self.kind.unit())
+    }
+}
+
+pub fn distance(a: &Point3<f32>, b: &Point3<f32>) -> f32 {
+    (b - a).norm()
+}
+
+/// Angle `abc` at `b` in degrees, in the range `0.0..=180.0`
+pub fn angle(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>) -> f32 {
+    (a - b).angle(&(c - b)).to_degrees()
+}
+
+/// Dihedral angle in degrees about the bond `bc`, in the range `-180.0..=180.0`
+/// Positive angles are clockwise when looking from `b` to `c`, following the IUPAC convention
+pub fn dihedral(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>, d: &Point3<f32>) -> f32 {
+    let (b1, b2, b3) = (b - a, c - b, d - c);
+    let n1 = b1.cross(&b2);
+    let n2 = b2.cross(&b3);
+    b2.normalize()
+        .dot(&n1.cross(&n2))
+        .atan2(n1.dot(&n2))
+        .to_degrees()
+}
+
+/// Measurements as CSV, with a column for each of up to four atoms
+pub fn to_csv(measurements: &[Measurement]) -> String {
+    let mut csv = String::from("kind,value,unit,atom1,atom2,atom3,atom4\n");
+    for measurement in measurements {
+        let mut fields = vec![
+            measurement.kind.name().to_string(),
+            format!("{:.3}", measurement.value),
+            measurement.kind.unit().to_string(),
+        ];
+        fields.extend(measurement.labels.iter().cloned());
+        fields.resize(7, String::new());
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        let _ = writeln!(csv, "{}", fields.join(","));
+    }
+    csv
+}
+
+/// Quote a field if it would otherwise break the CSV
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Write the measurements to a timestamped file in the `measurements` directory, returning its path
+pub fn export_csv(measurements: &[Measurement]) -> std::io::Result<String> {
+    let now: DateTime<Local> = Local::now();
+    let path = format!(
+        "measurements/measurements_{}.csv",
+        now.format("%Y%m%d_%H%M%S")
+    );
+    if let Some(directory) = Path::new(&path).parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    std::fs::write(&path, to_csv(measurements))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(name: &str, x: f32, y: f32, z: f32) -> AtomInfo {
+        AtomInfo::test("A", ("GLY", 3), name, Point3::new(x, y, z))
+    }
+
+    #[test]
+    fn test_geometry() {
+        let origin = Point3::origin();
+        let x = Point3::new(1.0, 0.0, 0.0);
+        let y = Point3::new(0.0, 2.0, 0.0);
+        assert!((distance(&x, &y) - 5f32.sqrt()).abs() < 1e-6);
+        assert!((angle(&x, &origin, &y) - 90.0).abs() < 1e-4);
+        assert!((angle(&x, &origin, &(-x.coords).into()) - 180.0).abs() < 1e-4);
+
+        // Cis and trans, then a quarter turn either way about the x axis
+        let a = Point3::new(0.0, 1.0, 0.0);
+        let b = Point3::origin();
+        let c = Point3::new(1.0, 0.0, 0.0);
+        let dihedral_to = |d| dihedral(&a, &b, &c, &d);
+        assert!(dihedral_to(Point3::new(1.0, 1.0, 0.0)).abs() < 1e-4);
+        assert!((dihedral_to(Point3::new(1.0, -1.0, 0.0)).abs() - 180.0).abs() < 1e-4);
+        assert!((dihedral_to(Point3::new(1.0, 0.0, 1.0)) - 90.0).abs() < 1e-4);
+        assert!((dihedral_to(Point3::new(1.0, 0.0, -1.0)) + 90.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_measurement() {
+        let id = |sub_shape| ObjectId {
+            shape: 0,
+            sub_shape: Some(sub_shape),
+        };
+        let n = atom("N", 0.0, 0.0, 0.0);
+        let ca = atom("CA", 3.0, 4.0, 0.0);
+        let kind = MeasurementKind::Distance;
+        assert_eq!(Measurement::new(kind, &[(id(0), &n)]), None);
+
+        let measurement = Measurement::new(kind, &[(id(0), &n), (id(1), &ca)]).unwrap();
+        assert_eq!(measurement.atoms, vec![id(0), id(1)]);
+        assert_eq!(measurement.value_text(), "5.00 Å");
+        assert_eq!(
+            to_csv(&[measurement]),
+            "kind,value,unit,atom1,atom2,atom3,atom4\ndistance,5.000,Å,A:GLY3:N,A:GLY3:CA,,\n"
+        );
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+    }
+}
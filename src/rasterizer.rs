@@ -1,3 +1,4 @@
+use crate::dither::Dithering;
 use ratatui::{
     prelude::Style,
     style::Color,
@@ -16,9 +17,79 @@ impl ColoredPixel {
         (self.intensity * 255.0).round() as u8
     }
 
-    // TODO Add function for converting `ColoredPixel` to RGB and RGBA value
+    /// Convert to an RGB value by shading the pixel colour with its intensity
+    /// Uncoloured pixels (black or reset) are shaded as white so that they remain visible
     pub fn to_rgb(&self) -> (u8, u8, u8) {
-        todo!()
+        let (r, g, b) = match self.color {
+            Color::Black | Color::Reset => (255, 255, 255),
+            color => color_to_rgb(color),
+        };
+        let intensity = self.intensity.clamp(0.0, 1.0);
+        let shade = |c: u8| (c as f32 * intensity).round() as u8;
+        (shade(r), shade(g), shade(b))
+    }
+}
+
+/// Approximate RGB value of a terminal colour
+/// Terminal emulators are free to choose their own palette, so this follows the `xterm` defaults
+pub fn color_to_rgb(color: Color) -> (u8, u8, u8) {
+    match color {
+        Color::Reset | Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (92, 92, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        Color::White => (255, 255, 255),
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(i) => indexed_to_rgb(i),
+    }
+}
+
+/// Convert from the 256 colour palette
+fn indexed_to_rgb(index: u8) -> (u8, u8, u8) {
+    const ANSI: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => color_to_rgb(ANSI[index as usize]),
+        16..=231 => {
+            let i = (index - 16) as usize;
+            (
+                CUBE_LEVELS[i / 36],
+                CUBE_LEVELS[(i / 6) % 6],
+                CUBE_LEVELS[i % 6],
+            )
+        }
+        232..=255 => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
     }
 }
 
@@ -89,6 +160,13 @@ pub trait Rasterizer {
     /// Get the grid-size used for rasterizing
     fn grid_height(&self) -> usize;
     fn grid_width(&self) -> usize;
+    /// Get the dithering applied before quantising intensities
+    /// Rasterizers without dithering support always use hard thresholds
+    fn dithering(&self) -> Dithering {
+        Dithering::None
+    }
+    /// Change the dithering applied before quantising intensities
+    fn set_dithering(&mut self, _dithering: Dithering) {}
 }
 
 pub fn chars_to_widget(chars: Vec<ColoredChar>, output_width: usize) -> impl Widget {
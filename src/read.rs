@@ -1,7 +1,9 @@
-use nalgebra::Isometry3;
+use nalgebra::{Isometry3, Point3};
 use parry3d::shape::{Ball, Compound, SharedShape};
 use pdbtbx::Element;
-use pdbtbx::{open_pdb, Atom, StrictnessLevel};
+use pdbtbx::{open_pdb, Atom, Chain, PDBError, Residue, StrictnessLevel};
+use std::collections::HashSet;
+use std::io::BufRead;
 use std::path::Path;
 use std::sync::Arc;
 use tobj::{load_obj, LoadOptions, Mesh, Model};
@@ -12,23 +14,192 @@ pub struct PDBStructure {
     pub chains: u16,
 }
 
-// TODO Make this return a Result type
-pub fn get_models_from_obj<Q>(path: Q) -> Vec<Model>
+/// Details of an atom kept alongside its ball, so that it can be identified when picked
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtomInfo {
+    pub serial_number: usize,
+    pub name: String,
+    /// Symbol of the element, or empty if unknown
+    pub element: String,
+    pub residue_name: String,
+    pub residue_number: isize,
+    pub chain: String,
+    pub b_factor: f32,
+    pub occupancy: f32,
+    /// Position in the coordinates of the file, before the shapes are moved
+    pub position: Point3<f32>,
+    /// Secondary structure of the residue, or `None` if the file has no `HELIX` or `SHEET` records
+    pub secondary_structure: Option<SecondaryStructure>,
+}
+
+/// Secondary structure of a residue
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SecondaryStructure {
+    Helix,
+    Strand,
+    #[default]
+    Coil,
+}
+
+impl SecondaryStructure {
+    /// Symbol shown under the sequence, following DSSP
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Helix => 'H',
+            Self::Strand => 'E',
+            Self::Coil => '-',
+        }
+    }
+}
+
+/// Range of residues in a chain with the same secondary structure, from a `HELIX` or `SHEET` record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecondaryStructureRange {
+    pub chain: String,
+    pub start: isize,
+    pub end: isize,
+    pub kind: SecondaryStructure,
+}
+
+impl SecondaryStructureRange {
+    fn contains(&self, chain: &str, residue_number: isize) -> bool {
+        self.chain == chain && (self.start..=self.end).contains(&residue_number)
+    }
+}
+
+impl AtomInfo {
+    pub fn new(chain: &Chain, residue: &Residue, atom: &Atom) -> Self {
+        Self {
+            serial_number: atom.serial_number(),
+            name: atom.name().to_string(),
+            element: atom
+                .element()
+                .map_or_else(String::new, |e| e.symbol().to_string()),
+            residue_name: residue.name().unwrap_or_default().to_string(),
+            residue_number: residue.serial_number(),
+            chain: chain.id().to_string(),
+            b_factor: atom.b_factor() as f32,
+            occupancy: atom.occupancy() as f32,
+            position: Point3::new(atom.x() as f32, atom.y() as f32, atom.z() as f32),
+            secondary_structure: None,
+        }
+    }
+    /// Short name of the atom in the form `chain:residue:atom`, such as `A:GLY3:CA`
+    pub fn label(&self) -> String {
+        format!(
+            "{}:{}{}:{}",
+            self.chain, self.residue_name, self.residue_number, self.name
+        )
+    }
+    /// Carbon atom with only the fields that tests tend to care about filled in
+    #[cfg(test)]
+    pub fn test(chain: &str, residue: (&str, isize), name: &str, position: Point3<f32>) -> Self {
+        let (residue_name, residue_number) = residue;
+        Self {
+            serial_number: 1,
+            name: name.to_string(),
+            element: "C".to_string(),
+            residue_name: residue_name.to_string(),
+            residue_number,
+            chain: chain.to_string(),
+            b_factor: 0.0,
+            occupancy: 1.0,
+            position,
+            secondary_structure: None,
+        }
+    }
+}
+
+/// Read the `HELIX` and `SHEET` records of a PDB file, which `pdbtbx` skips over
+/// Columns are fixed by the PDB format, and records that can't be read are ignored
+pub fn parse_secondary_structure(text: &str) -> Vec<SecondaryStructureRange> {
+    fn field(line: &str, start: usize, end: usize) -> Option<&str> {
+        line.get(start..end).map(str::trim)
+    }
+    text.lines()
+        .filter_map(|line| {
+            let (kind, chain, start, end) = match line.get(..6)? {
+                "HELIX " => (SecondaryStructure::Helix, 19, 21..25, 33..37),
+                "SHEET " => (SecondaryStructure::Strand, 21, 22..26, 33..37),
+                _ => return None,
+            };
+            Some(SecondaryStructureRange {
+                chain: field(line, chain, chain + 1)?.to_string(),
+                start: field(line, start.start, start.end)?.parse().ok()?,
+                end: field(line, end.start, end.end)?.parse().ok()?,
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Summary of a PDB file shown before loading it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdbHeader {
+    /// Joined from the `TITLE` records, which continue each other
+    pub title: Option<String>,
+    /// In Ångströms, from `REMARK   2`, which is missing for structures not solved by diffraction
+    pub resolution: Option<f32>,
+    /// Number of different chain identifiers among the atoms
+    pub chains: usize,
+}
+
+/// Read the header of a PDB file without building its structure, so that it is quick enough to preview files
+/// Stops at the end of the first model, since the rest of the file only repeats its chains
+/// Like `parse_secondary_structure`, records that can't be read are ignored
+pub fn parse_header<R: BufRead>(reader: R) -> std::io::Result<PdbHeader> {
+    let mut title = String::new();
+    let mut resolution = None;
+    let mut chains = HashSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        if matches!(line.split_whitespace().next(), Some("ENDMDL" | "END")) {
+            break;
+        }
+        match line.get(..6) {
+            Some("TITLE ") => {
+                let part = line.get(10..).unwrap_or_default().trim();
+                if !title.is_empty() && !part.is_empty() {
+                    title.push(' ');
+                }
+                title.push_str(part);
+            }
+            Some("REMARK") if line.get(6..10) == Some("   2") && resolution.is_none() => {
+                resolution = line
+                    .get(10..)
+                    .and_then(|rest| rest.trim().strip_prefix("RESOLUTION."))
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .and_then(|value| value.parse().ok());
+            }
+            Some("ATOM  ") | Some("HETATM") => {
+                if let Some(chain) = line.get(21..22) {
+                    chains.insert(chain.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(PdbHeader {
+        title: (!title.is_empty()).then_some(title),
+        resolution,
+        chains: chains.len(),
+    })
+}
+
+pub fn get_models_from_obj<Q>(path: Q) -> Result<Vec<Model>, tobj::LoadError>
 where
     Q: AsRef<Path>,
 {
-    assert!(path.as_ref().exists());
-    // TODO Swap out unwrap for something
-    let (models, _materials) = load_obj(path.as_ref(), &LoadOptions::default()).unwrap();
-    models
+    let (models, _materials) = load_obj(path.as_ref(), &LoadOptions::default())?;
+    Ok(models)
 }
 
-pub fn get_meshes_from_obj<Q>(path: Q) -> Vec<Mesh>
+pub fn get_meshes_from_obj<Q>(path: Q) -> Result<Vec<Mesh>, tobj::LoadError>
 where
     Q: AsRef<Path>,
 {
-    let models = get_models_from_obj(path);
-    models.into_iter().map(|model| model.mesh).collect()
+    let models = get_models_from_obj(path)?;
+    Ok(models.into_iter().map(|model| model.mesh).collect())
 }
 
 // TODO Decide on a radius for each atom type
@@ -50,24 +221,59 @@ pub fn get_compound_from_atoms(atoms: &[&Atom]) -> Compound {
     Compound::new(balls)
 }
 
-/// Create compound shapes for each chain in the PDB
-pub fn get_shapes_from_pdb<Q>(path: Q) -> Vec<Compound>
+/// Shape of each chain in each model of a PDB file, along with the serial number of the model and its atoms
+/// The atoms are in the same order as the balls of the compound
+pub type PdbChains = Vec<(usize, Compound, Vec<AtomInfo>)>;
+
+/// Read the chains of a PDB file, along with descriptions of any problems `pdbtbx` found that weren't bad enough
+/// to stop it reading the file
+/// Like `open_pdb`, the error holds descriptions of the problems if the file couldn't be read
+pub fn get_shapes_from_pdb<Q>(path: Q) -> Result<(PdbChains, Vec<String>), Vec<String>>
 where
     Q: AsRef<str>,
 {
-    // TODO Handle errors correctly
+    let describe = |errors: Vec<PDBError>| -> Vec<String> {
+        errors
+            .iter()
+            .map(|error| error.short_description().to_string())
+            .collect()
+    };
     // PDBtbx library does not expect `AsRef<Path>` but rather `AsRef<str>`!
-    let (pdb, _errors) = open_pdb(path, StrictnessLevel::Medium).unwrap();
+    let secondary_structure = std::fs::read_to_string(path.as_ref())
+        .map(|text| parse_secondary_structure(&text))
+        .unwrap_or_default();
+    let (pdb, warnings) = open_pdb(path, StrictnessLevel::Medium).map_err(describe)?;
 
-    let bb_atoms: Vec<Vec<&Atom>> = pdb
-        .chains()
-        .map(|c| c.atoms().filter(|a| a.is_backbone()).collect())
-        // .map(|c| c.atoms().collect())
+    let chains = pdb
+        .models()
+        .flat_map(|model| {
+            model
+                .chains()
+                .map(move |chain| (model.serial_number(), chain))
+        })
+        .map(|(model, chain)| {
+            let (bb_atoms, mut atom_info): (Vec<&Atom>, Vec<AtomInfo>) = chain
+                .residues()
+                .flat_map(|residue| {
+                    residue
+                        .atoms()
+                        .filter(|a| a.is_backbone())
+                        .map(move |atom| (atom, AtomInfo::new(chain, residue, atom)))
+                })
+                .unzip();
+            if !secondary_structure.is_empty() {
+                for atom in atom_info.iter_mut() {
+                    let range = secondary_structure
+                        .iter()
+                        .find(|range| range.contains(&atom.chain, atom.residue_number));
+                    atom.secondary_structure =
+                        Some(range.map_or_else(Default::default, |r| r.kind));
+                }
+            }
+            (model, get_compound_from_atoms(&bb_atoms[..]), atom_info)
+        })
         .collect();
-    bb_atoms
-        .iter()
-        .map(|atoms| get_compound_from_atoms(&atoms[..]))
-        .collect()
+    Ok((chains, describe(warnings)))
 }
 
 #[cfg(test)]
@@ -84,6 +290,60 @@ mod tests {
         assert!(pdb.is_ok())
     }
 
+    #[test]
+    fn test_parse_secondary_structure() {
+        let text = "\
+HELIX    1   1 ALA A   12  GLU A   25  1                                  14
+SHEET    1   A 2 VAL B 100  LEU B 104  0
+ATOM      1  N   ALA A  12      11.104   6.134  -6.504  1.00  0.00           N
+HELIX";
+        assert_eq!(
+            parse_secondary_structure(text),
+            vec![
+                SecondaryStructureRange {
+                    chain: "A".to_string(),
+                    start: 12,
+                    end: 25,
+                    kind: SecondaryStructure::Helix,
+                },
+                SecondaryStructureRange {
+                    chain: "B".to_string(),
+                    start: 100,
+                    end: 104,
+                    kind: SecondaryStructure::Strand,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let text = "\
+HEADER    VIRAL PROTEIN                           20-FEB-20   6M0J
+TITLE     CRYSTAL STRUCTURE OF SARS-COV-2 SPIKE RECEPTOR-BINDING DOMAIN BOUND
+TITLE    2 WITH ACE2
+REMARK   2
+REMARK   2 RESOLUTION.    2.45 ANGSTROMS.
+ATOM      1  N   ALA A  12      11.104   6.134  -6.504  1.00  0.00           N
+ATOM      2  CA  ALA A  12      11.639   6.071  -5.147  1.00  0.00           C
+HETATM    3  O   HOH E  13      12.000   6.000  -5.000  1.00  0.00           O
+ENDMDL
+ATOM      4  N   ALA B  12      11.104   6.134  -6.504  1.00  0.00           N";
+        // Only the chains of the first model are counted
+        assert_eq!(
+            parse_header(text.as_bytes()).unwrap(),
+            PdbHeader {
+                title: Some(
+                    "CRYSTAL STRUCTURE OF SARS-COV-2 SPIKE RECEPTOR-BINDING DOMAIN BOUND WITH ACE2"
+                        .to_string()
+                ),
+                resolution: Some(2.45),
+                chains: 2,
+            }
+        );
+        assert_eq!(parse_header(&b""[..]).unwrap(), PdbHeader::default());
+    }
+
     #[test]
     fn test_reading_obj() {
         let test_obj = "./data/surface.obj";
@@ -1,16 +1,29 @@
 // #![allow(dead_code)]
 use crate::{
     rasterizer::{ColoredChar, ColoredPixel, Rasterizer},
-    scene::{create_ray, Scene},
+    scene::{create_ray_segment, ObjectId, Scene},
+    shading::{Shading, Shadows},
+    supersampling::{resolve_samples, Sample, Supersampling},
     surface::ValidShape,
 };
-use image::{imageops::flip_vertical_in_place, GrayImage, ImageResult};
+use image::{imageops::flip_vertical_in_place, GrayImage, ImageResult, RgbaImage};
+use nalgebra::Unit;
 use parry3d::query::RayCast;
 use ratatui::style::Color;
 use std::path::Path;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+use std::time::{Duration, Instant};
 
 const SCREEN_PIXELS_X: usize = 320;
 const SCREEN_PIXELS_Y: usize = 180;
+/// Number of pixel rows in each tile rendered by a single thread
+const TILE_ROWS: usize = 8;
+/// Width and height of the blocks of pixels sharing a single ray in preview renders
+/// Must divide `TILE_ROWS` so that blocks don't straddle tiles
+const PREVIEW_STRIDE: usize = 4;
 
 pub enum CanvasError {
     PixelOutOfRange { x: usize, y: usize },
@@ -21,12 +34,25 @@ pub struct Canvas<R: Rasterizer> {
     pub frame_buffer: Vec<ColoredChar>,
     // TODO Consider changing pixel buffer to 2D array for more convenience
     pub pixel_buffer: Vec<ColoredPixel>,
+    /// View-space depth of whatever each pixel shows, or `f32::MAX` for the background
     pub toi_buffer: Vec<f32>,
+    /// Shape and part of the shape each pixel shows, or `None` for the background
+    pub id_buffer: Vec<Option<ObjectId>>,
     width: usize,
     height: usize,
     pub rasterizer: R,
     /// Pixel intensity used for the background
     pub bg_pixel: ColoredPixel,
+    /// Rays cast per pixel for anti-aliasing
+    pub supersampling: Supersampling,
+    /// Ambient occlusion, depth cueing and shadows applied to each hit
+    pub shading: Shading,
+    /// Number of threads used for ray casting, where `None` uses every available core
+    pub threads: Option<usize>,
+    /// Incremented whenever the frame buffer changes, so that users can tell when to redraw
+    generation: u64,
+    /// Whether the canvas only holds a preview, which should be refined once there is time
+    needs_refinement: bool,
 }
 impl<R: Rasterizer> Canvas<R> {
     /// Constructor for canvas.
@@ -45,15 +71,22 @@ impl<R: Rasterizer> Canvas<R> {
         let size = width * height;
         let pixel_buffer = vec![bg_pixel; size];
         let toi_buffer = vec![f32::MAX; size];
+        let id_buffer = vec![None; size];
         let frame_buffer = vec![];
         let mut out = Canvas {
             frame_buffer,
             pixel_buffer,
             toi_buffer,
+            id_buffer,
             width,
             height,
             rasterizer,
             bg_pixel,
+            supersampling: Supersampling::default(),
+            shading: Shading::default(),
+            threads: None,
+            generation: 0,
+            needs_refinement: false,
         };
         out.update_frame();
         out
@@ -79,9 +112,11 @@ impl<R: Rasterizer> Canvas<R> {
 
         self.pixel_buffer = vec![self.bg_pixel; size];
         self.toi_buffer = vec![f32::MAX; size];
+        self.id_buffer = vec![None; size];
         self.frame_buffer = self
             .rasterizer
-            .pixels_to_stdout(self.pixels_as_chunks(), self.render_width())
+            .pixels_to_stdout(self.pixels_as_chunks(), self.render_width());
+        self.generation += 1;
     }
     /// Return width
     /// Width made private by default to discourage resizing without resizing other quantities
@@ -97,7 +132,12 @@ impl<R: Rasterizer> Canvas<R> {
     pub fn update_frame(&mut self) {
         self.frame_buffer = self
             .rasterizer
-            .pixels_to_stdout(self.pixels_as_chunks(), self.render_width())
+            .pixels_to_stdout(self.pixels_as_chunks(), self.render_width());
+        self.generation += 1;
+    }
+    /// Changes whenever the frame buffer is updated
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
     /// Reshape the vector of pixels to a 2D vector that can be accepted by `Rasterizer`
     fn pixels_as_chunks(&self) -> Vec<&[ColoredPixel]> {
@@ -162,55 +202,191 @@ impl<R: Rasterizer> Canvas<R> {
             self.toi_buffer[idx] = toi;
         }
     }
+    /// Identify the object shown by a pixel, or `None` for the background or if the pixel is out of range
+    pub fn object_at(&self, x: usize, y: usize) -> Option<ObjectId> {
+        self.pixel_to_index(x, y)
+            .ok()
+            .and_then(|idx| self.id_buffer[idx])
+    }
+    /// Identify the nearest object shown anywhere within a character cell, such as one under the mouse
+    /// `column` and `row` run over `0..render_width()` and `0..render_height()`, with rows counted from the top like the terminal
+    pub fn object_at_cell(&self, column: usize, row: usize) -> Option<ObjectId> {
+        // Small pixel coordinates are at the bottom of the screen
+        let row = self.render_height().checked_sub(row + 1)?;
+        let xs = column * self.grid_width()..(column + 1) * self.grid_width();
+        let ys = row * self.grid_height()..(row + 1) * self.grid_height();
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .filter_map(|(x, y)| self.pixel_to_index(x, y).ok())
+            .filter_map(|idx| self.id_buffer[idx].map(|id| (id, self.toi_buffer[idx])))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(id, _)| id)
+    }
     /// Set all the buffers to just display the background
     pub fn flush_buffers(&mut self) {
         for x in 0..self.width {
             for y in 0..self.height {
                 self.set_pixel(x, y, self.bg_pixel);
                 self.set_toi(x, y, f32::MAX);
+                if let Ok(idx) = self.pixel_to_index(x, y) {
+                    self.id_buffer[idx] = None;
+                }
             }
         }
     }
+    /// Number of threads that will be used for ray casting
+    pub fn threads(&self) -> usize {
+        self.threads
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1)
+            })
+            .max(1)
+    }
     /// Update the canvas with the current state of the scene
-    pub fn draw_scene_to_canvas<S: RayCast + ValidShape>(&mut self, scene: &Scene<S>) {
-        self.flush_buffers();
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let x_clip = pixel_to_clip(x, self.width);
-                let y_clip = pixel_to_clip(y, self.height);
-                let ray = create_ray(x_clip, y_clip, scene);
-                // FIXME make sure this works when using something other than meshes
-                for colored_shape in scene.shapes().iter() {
-                    // FIXME Make sure max_toi is reasonable
-                    let toi_result = colored_shape.shape.cast_ray_and_get_normal(
-                        &colored_shape.world_transform,
-                        &ray,
-                        scene.scene_projection.perspective.zfar() + 100.0,
-                        true,
-                    );
-                    // TODO Consider whether we should take `abs` of intensity
-                    if let Some(ri) = toi_result {
-                        let normal = ri.normal;
-                        // Taking ReLU of intensity to give darkness if incident on normal pointing in wrong direction
-                        // TODO Consider using `std::clamp` function for more readability
-                        let intensity: f32 = scene
-                            .lights
-                            .iter()
-                            .fold(0.0, |i, l| i + normal.dot(l).max(0.0));
-                        self.set_pixel_toi(
-                            x,
-                            y,
-                            ColoredPixel {
-                                intensity,
-                                color: colored_shape.color,
-                            },
-                            ri.toi,
-                        );
+    pub fn draw_scene_to_canvas<S: RayCast + ValidShape + Sync>(&mut self, scene: &Scene<S>) {
+        let offsets = self.supersampling.offsets();
+        self.draw_pass(scene, 1, &offsets, self.shading, &|| false);
+        self.needs_refinement = false;
+        self.update_frame()
+    }
+    /// Quickly update the canvas at a quarter of the resolution without supersampling, ambient occlusion or soft shadows
+    /// Meant for while the user is interacting, followed by `refine_scene_on_canvas` once they stop
+    pub fn draw_scene_preview<S: RayCast + ValidShape + Sync>(&mut self, scene: &Scene<S>) {
+        let shading = Shading {
+            ambient_occlusion: None,
+            shadows: self.shading.shadows.map(|_| Shadows::hard()),
+            ..self.shading
+        };
+        self.draw_pass(scene, PREVIEW_STRIDE, &[(0.0, 0.0)], shading, &|| false);
+        self.needs_refinement = true;
+        self.update_frame()
+    }
+    /// Whether the canvas only holds a preview
+    pub fn needs_refinement(&self) -> bool {
+        self.needs_refinement
+    }
+    /// Render the scene at full detail, giving up as soon as `cancel` returns `true`
+    /// Returns whether the render finished, in which case the frame is updated
+    /// A cancelled render leaves the frame and every buffer showing the previous preview
+    pub fn refine_scene_on_canvas<S: RayCast + ValidShape + Sync>(
+        &mut self,
+        scene: &Scene<S>,
+        cancel: &(dyn Fn() -> bool + Sync),
+    ) -> bool {
+        // Draw into scratch buffers, which only replace the preview once every tile is drawn
+        let size = self.width * self.height;
+        let pixel_buffer = std::mem::replace(&mut self.pixel_buffer, vec![self.bg_pixel; size]);
+        let toi_buffer = std::mem::replace(&mut self.toi_buffer, vec![f32::MAX; size]);
+        let id_buffer = std::mem::replace(&mut self.id_buffer, vec![None; size]);
+
+        let offsets = self.supersampling.offsets();
+        let finished = self.draw_pass(scene, 1, &offsets, self.shading, cancel);
+        if finished {
+            self.needs_refinement = false;
+            self.update_frame();
+        } else {
+            self.pixel_buffer = pixel_buffer;
+            self.toi_buffer = toi_buffer;
+            self.id_buffer = id_buffer;
+        }
+        finished
+    }
+    /// Fill the pixel and depth buffers, casting rays for blocks of `stride * stride` pixels
+    /// The canvas is split into tiles of rows which are shared out between threads.
+    /// Each pixel only depends on the scene, so the output doesn't depend on the number of threads.
+    /// `cancel` is checked before each tile, and the return value is whether every tile was drawn.
+    fn draw_pass<S: RayCast + ValidShape + Sync>(
+        &mut self,
+        scene: &Scene<S>,
+        stride: usize,
+        offsets: &[(f32, f32)],
+        shading: Shading,
+        cancel: &(dyn Fn() -> bool + Sync),
+    ) -> bool {
+        debug_assert_eq!(TILE_ROWS % stride, 0);
+        let renderer = TileRenderer {
+            scene,
+            offsets,
+            shading,
+            width: self.width,
+            height: self.height,
+            bg_pixel: self.bg_pixel,
+            stride,
+        };
+        let tile_size = (self.width * TILE_ROWS).max(1);
+        let threads = self.threads().min(self.height.div_ceil(TILE_ROWS)).max(1);
+        let cancelled = AtomicBool::new(false);
+
+        // Rows are contiguous in memory, so tiles can be handed out as disjoint slices
+        let tiles = Mutex::new(
+            self.pixel_buffer
+                .chunks_mut(tile_size)
+                .zip(self.toi_buffer.chunks_mut(tile_size))
+                .zip(self.id_buffer.chunks_mut(tile_size))
+                .enumerate(),
+        );
+        std::thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| loop {
+                    if cancelled.load(Ordering::Relaxed) || cancel() {
+                        cancelled.store(true, Ordering::Relaxed);
+                        break;
                     }
-                }
+                    let Some((tile, ((pixels, tois), ids))) = tiles.lock().unwrap().next() else {
+                        break;
+                    };
+                    renderer.draw_tile(tile * TILE_ROWS, pixels, tois, ids);
+                });
+            }
+        });
+        let finished = !cancelled.into_inner();
+
+        // Outlines depend on neighbouring pixels, so can only be found once every tile is drawn
+        if let (true, Some(outlines)) = (finished, shading.outlines) {
+            // Only silhouettes of whole shapes are outlined, rather than every atom
+            let shapes: Vec<_> = self
+                .id_buffer
+                .iter()
+                .map(|id| id.map(|id| id.shape))
+                .collect();
+            let edges = outlines.edges(&shapes, &self.toi_buffer, self.width);
+            for (pixel, _) in self.pixel_buffer.iter_mut().zip(edges).filter(|(_, e)| *e) {
+                pixel.intensity = outlines.intensity;
             }
         }
-        self.update_frame()
+        finished
+    }
+    /// Time drawing the scene with an increasing number of threads, up to every available core
+    /// Each time is the average over `repeats` frames
+    pub fn benchmark_threads<S: RayCast + ValidShape + Sync>(
+        &mut self,
+        scene: &Scene<S>,
+        repeats: usize,
+    ) -> Vec<(usize, Duration)> {
+        let original_threads = self.threads;
+        self.threads = None;
+        let max_threads = self.threads();
+
+        let mut thread_counts: Vec<usize> = std::iter::successors(Some(1usize), |n| Some(n * 2))
+            .take_while(|n| *n < max_threads)
+            .collect();
+        thread_counts.push(max_threads);
+
+        let repeats = repeats.max(1);
+        let results = thread_counts
+            .into_iter()
+            .map(|threads| {
+                self.threads = Some(threads);
+                let now = Instant::now();
+                for _ in 0..repeats {
+                    self.draw_scene_to_canvas(scene);
+                }
+                (threads, now.elapsed() / repeats as u32)
+            })
+            .collect();
+        self.threads = original_threads;
+        results
     }
     /// Wrapper for saving image. Filetype will be inferred from path
     pub fn save_image<Q>(&self, path: Q) -> ImageResult<()>
@@ -224,6 +400,24 @@ impl<R: Rasterizer> Canvas<R> {
         flip_vertical_in_place(&mut image_buffer);
         image_buffer.save(path)
     }
+    /// Convert the pixel buffer to a colour image, where the background is transparent
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        let pixels_transformed = self
+            .pixel_buffer
+            .iter()
+            .zip(self.toi_buffer.iter())
+            .flat_map(|(p, toi)| {
+                let (r, g, b) = p.to_rgb();
+                let a = if *toi == f32::MAX { 0 } else { u8::MAX };
+                [r, g, b, a]
+            })
+            .collect();
+        let mut image_buffer =
+            RgbaImage::from_raw(self.width as u32, self.height as u32, pixels_transformed).unwrap();
+        // Flip because small coord means small index, but top of image should have large y
+        flip_vertical_in_place(&mut image_buffer);
+        image_buffer
+    }
 }
 
 impl<R: Rasterizer + Default> Default for Canvas<R> {
@@ -242,6 +436,124 @@ fn clip_to_pixel(clip_coord: f32, num_pixels: usize) -> usize {
     ((clip_coord + 1.0) / pixel_width).floor() as usize
 }
 
+/// Everything needed to render a tile independently of the rest of the canvas
+struct TileRenderer<'a, S: RayCast + ValidShape> {
+    scene: &'a Scene<S>,
+    offsets: &'a [(f32, f32)],
+    shading: Shading,
+    width: usize,
+    height: usize,
+    bg_pixel: ColoredPixel,
+    /// Width and height of the blocks of pixels sharing the same samples
+    stride: usize,
+}
+
+impl<S: RayCast + ValidShape> TileRenderer<'_, S> {
+    /// Render a tile of rows starting from `first_row`
+    /// The buffers only hold the pixels of the tile
+    fn draw_tile(
+        &self,
+        first_row: usize,
+        pixels: &mut [ColoredPixel],
+        tois: &mut [f32],
+        ids: &mut [Option<ObjectId>],
+    ) {
+        let rows = pixels.len() / self.width;
+        // Sample from the centre of each block
+        let block_offset = (self.stride - 1) as f32 / 2.0;
+        let mut samples = Vec::with_capacity(self.offsets.len());
+        for block_y in (0..rows).step_by(self.stride) {
+            for block_x in (0..self.width).step_by(self.stride) {
+                let y = first_row + block_y;
+                samples.clear();
+                samples.extend(self.offsets.iter().map(|(dx, dy)| {
+                    let x_clip = sample_to_clip(block_x, dx + block_offset, self.width);
+                    let y_clip = sample_to_clip(y, dy + block_offset, self.height);
+                    cast_ray_into_scene(x_clip, y_clip, self.scene, &self.shading)
+                }));
+                let resolved = resolve_samples(&samples, self.bg_pixel);
+                let (pixel, toi, id) = resolved.map_or((self.bg_pixel, f32::MAX, None), |s| {
+                    (s.pixel, s.depth, Some(s.id))
+                });
+
+                for row in block_y..(block_y + self.stride).min(rows) {
+                    for column in block_x..(block_x + self.stride).min(self.width) {
+                        let idx = row * self.width + column;
+                        pixels[idx] = pixel;
+                        tois[idx] = toi;
+                        ids[idx] = id;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Find the colour, view-space depth and identity of the nearest object hit by the ray through a point in clip space
+/// Anything beyond the far plane is ignored
+fn cast_ray_into_scene<S: RayCast + ValidShape>(
+    x_clip: f32,
+    y_clip: f32,
+    scene: &Scene<S>,
+    shading: &Shading,
+) -> Option<Sample> {
+    let (ray, max_toi) = create_ray_segment(x_clip, y_clip, scene);
+    let (shape, intersection) = scene.cast_ray(&ray, max_toi)?;
+    let point = ray.point_at(intersection.toi);
+    let depth = scene.view_depth(&point);
+    // Shadow and occlusion rays start from the side of the surface facing the camera
+    let facing_normal = Unit::new_normalize(if intersection.normal.dot(&ray.dir) > 0.0 {
+        -intersection.normal
+    } else {
+        intersection.normal
+    });
+    let rotation = sample_rotation(x_clip, y_clip);
+    let mut intensity = scene.lighting.shade(
+        &point,
+        &intersection.normal,
+        &scene.eye(),
+        |light_dir, distance| match &shading.shadows {
+            Some(shadows) => {
+                shadows.visibility(scene, &point, &facing_normal, light_dir, distance, rotation)
+            }
+            None => 1.0,
+        },
+    );
+    if let Some(toon) = &shading.toon {
+        intensity = toon.quantise(intensity);
+    }
+    if let Some(ambient_occlusion) = &shading.ambient_occlusion {
+        intensity *= ambient_occlusion.factor(scene, &point, &facing_normal, rotation);
+    }
+    if let Some(depth_cueing) = &shading.depth_cueing {
+        intensity *= depth_cueing.factor(depth, &scene.scene_projection.perspective);
+    }
+    let id = scene.object_id(shape, &ray, &intersection);
+    Some(Sample {
+        pixel: ColoredPixel {
+            intensity,
+            color: scene.color_of(id),
+        },
+        depth,
+        id,
+    })
+}
+
+/// Pseudo-random angle for a point in clip space, used to vary sample patterns between neighbouring pixels
+/// Noise is less distracting than the banding from using the same pattern everywhere
+fn sample_rotation(x_clip: f32, y_clip: f32) -> f32 {
+    let hash = x_clip.to_bits().wrapping_mul(0x9e37_79b9).rotate_left(13)
+        ^ y_clip.to_bits().wrapping_mul(0x85eb_ca6b);
+    let hash = (hash ^ (hash >> 16)).wrapping_mul(0x7feb_352d);
+    hash as f32 / u32::MAX as f32 * std::f32::consts::TAU
+}
+
+/// Convert from a point offset from the centre of a pixel to clip space
+/// The offset is measured in pixels
+fn sample_to_clip(pixel: usize, offset: f32, num_pixels: usize) -> f32 {
+    pixel_to_clip(pixel, num_pixels) + offset * 2.0 / num_pixels as f32
+}
+
 /// Convert from the centre of a pixel to clip space
 /// Will return value outside range if `pixel >= pixels` or `pixel < 0`
 fn pixel_to_clip(pixel: usize, num_pixels: usize) -> f32 {
@@ -255,6 +567,7 @@ mod tests {
 
     use super::*;
     use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
 
     #[test]
     /// Test that checks conversion from clip coordinates to pixels
@@ -282,8 +595,183 @@ mod tests {
         assert!(Path::new(test_obj).exists());
 
         let mut scene = Scene::default();
-        scene.load_meshes_from_path(test_obj);
+        scene.load_meshes_from_path(test_obj).unwrap();
         let mut canvas = Canvas::<BasicAsciiRasterizer>::default();
         canvas.draw_scene_to_canvas(&scene);
     }
+
+    #[test]
+    /// Supersampling should give the same image where every sample hits the same shape
+    fn test_supersampled_drawing() {
+        use crate::supersampling::SamplePattern;
+
+        let test_obj = "./data/surface.obj";
+        let mut scene = Scene::default();
+        scene.load_meshes_from_path(test_obj).unwrap();
+        scene.shapes_to_center();
+
+        let mut canvas = Canvas::new(40, 20, BasicAsciiRasterizer::default());
+        canvas.draw_scene_to_canvas(&scene);
+        let single = canvas.toi_buffer.clone();
+
+        canvas.supersampling = Supersampling::new(SamplePattern::Grid, 3);
+        canvas.draw_scene_to_canvas(&scene);
+        let hits = |buffer: &[f32]| buffer.iter().filter(|toi| **toi < f32::MAX).count();
+        // The central sample is still cast, so anything hit before is still hit
+        assert!(hits(&canvas.toi_buffer) >= hits(&single));
+    }
+
+    #[test]
+    /// Depths should match those of rays hitting a sphere placed in view space
+    fn test_depth_against_sphere() {
+        use crate::scene::{create_ray, ColoredShape};
+        use nalgebra::{Isometry3, Point3};
+        use parry3d::shape::Ball;
+
+        let radius = 5.0;
+        let mut scene = Scene::<Ball>::default();
+        scene.add_shapes(vec![ColoredShape {
+            shape: Ball::new(radius),
+            world_transform: Isometry3::translation(2.0, -1.0, 3.0),
+            color: Color::Red,
+        }]);
+        let center = scene.view * Point3::new(2.0, -1.0, 3.0);
+
+        for (x_clip, y_clip) in [(0.0, 0.0), (0.02, -0.03), (-0.05, 0.01), (0.5, 0.5)] {
+            let ray = create_ray(x_clip, y_clip, &scene);
+            assert!((ray.dir.norm() - 1.0).abs() < 1e-5);
+
+            // Every ray passes through the camera at the origin of view space
+            let view_point = scene
+                .scene_projection
+                .perspective
+                .unproject_point(&Point3::new(x_clip, y_clip, 1.0));
+            let v = view_point.coords.normalize();
+            let b = v.dot(&center.coords);
+            let discriminant = b * b - (center.coords.norm_squared() - radius * radius);
+            let expected = (discriminant >= 0.0).then(|| -(b - discriminant.sqrt()) * v.z);
+
+            let depth =
+                cast_ray_into_scene(x_clip, y_clip, &scene, &Shading::default()).map(|s| s.depth);
+            match (expected, depth) {
+                (Some(e), Some(d)) => assert!((e - d).abs() < 1e-3, "expected {} got {}", e, d),
+                (None, None) => {}
+                _ => panic!("{:?} did not match {:?}", depth, expected),
+            }
+        }
+        // The sphere fits between the near and far planes
+        let (_, max_toi) = create_ray_segment(0.0, 0.0, &scene);
+        let projection = &scene.scene_projection.perspective;
+        assert!((max_toi - (projection.zfar() - projection.znear())).abs() < 1e-3);
+        assert!(projection.zfar() >= -center.z + radius - 1e-3);
+    }
+
+    #[test]
+    /// Every pixel showing the sphere should be identified as the sphere, and the rest as the background
+    fn test_object_ids() {
+        use crate::scene::ColoredShape;
+        use nalgebra::Isometry3;
+        use parry3d::shape::Ball;
+
+        // Near the top of the screen
+        let mut scene = Scene::<Ball>::default();
+        scene.add_shapes(vec![ColoredShape {
+            shape: Ball::new(5.0),
+            world_transform: Isometry3::translation(0.0, 12.0, 0.0),
+            color: Color::Red,
+        }]);
+
+        let mut canvas = Canvas::new(20, 10, BasicAsciiRasterizer::default());
+        canvas.draw_scene_to_canvas(&scene);
+        let sphere = Some(ObjectId {
+            shape: 0,
+            sub_shape: None,
+        });
+        for (id, toi) in canvas.id_buffer.iter().zip(&canvas.toi_buffer) {
+            assert_eq!(id.is_some(), *toi < f32::MAX);
+        }
+        assert_eq!(canvas.object_at_cell(10, 2), sphere);
+        assert_eq!(canvas.object_at_cell(10, 7), None);
+        assert_eq!(canvas.object_at_cell(0, 0), None);
+        assert_eq!(canvas.object_at_cell(10, 10), None);
+        assert_eq!(canvas.object_at(1000, 1000), None);
+    }
+
+    #[test]
+    /// A cancelled refinement should leave the preview, and an uncancelled one should match a full render
+    fn test_progressive_refinement() {
+        let test_obj = "./data/surface.obj";
+        let mut scene = Scene::default();
+        scene.load_meshes_from_path(test_obj).unwrap();
+        scene.shapes_to_center();
+
+        let mut canvas = Canvas::new(40, 20, BasicAsciiRasterizer::default());
+        canvas.draw_scene_to_canvas(&scene);
+        let full = canvas.toi_buffer.clone();
+
+        canvas.draw_scene_preview(&scene);
+        assert!(canvas.needs_refinement());
+        let generation = canvas.generation();
+        assert!(!canvas.refine_scene_on_canvas(&scene, &|| true));
+        assert!(canvas.needs_refinement());
+        assert_eq!(canvas.generation(), generation);
+
+        // Cancelling partway through leaves every buffer as it was, in step with the frame
+        let intensities = |canvas: &Canvas<BasicAsciiRasterizer>| -> Vec<f32> {
+            canvas.pixel_buffer.iter().map(|p| p.intensity).collect()
+        };
+        let symbols = |canvas: &Canvas<BasicAsciiRasterizer>| -> String {
+            canvas.frame_buffer.iter().map(|c| c.symbol).collect()
+        };
+        let (pixels, tois, ids, frame) = (
+            intensities(&canvas),
+            canvas.toi_buffer.clone(),
+            canvas.id_buffer.clone(),
+            symbols(&canvas),
+        );
+        canvas.threads = Some(1);
+        let tiles_drawn = AtomicUsize::new(0);
+        let cancel_after_one = || tiles_drawn.fetch_add(1, Ordering::Relaxed) >= 1;
+        assert!(!canvas.refine_scene_on_canvas(&scene, &cancel_after_one));
+        assert_eq!(intensities(&canvas), pixels);
+        assert_eq!(canvas.toi_buffer, tois);
+        assert_eq!(canvas.id_buffer, ids);
+        assert_eq!(symbols(&canvas), frame);
+        assert_eq!(canvas.generation(), generation);
+
+        assert!(canvas.refine_scene_on_canvas(&scene, &|| false));
+        assert!(!canvas.needs_refinement());
+        assert_eq!(canvas.toi_buffer, full);
+    }
+
+    #[test]
+    /// The generation should only change when the frame buffer is rebuilt
+    fn test_generation() {
+        let mut canvas = Canvas::new(4, 4, BasicAsciiRasterizer::default());
+        let generation = canvas.generation();
+        assert_eq!(canvas.generation(), generation);
+        canvas.update_frame();
+        assert_ne!(canvas.generation(), generation);
+    }
+
+    #[test]
+    /// The output should be identical however many threads are used
+    fn test_threads_deterministic() {
+        let test_obj = "./data/surface.obj";
+        let mut scene = Scene::default();
+        scene.load_meshes_from_path(test_obj).unwrap();
+        scene.shapes_to_center();
+
+        let mut canvas = Canvas::new(37, 19, BasicAsciiRasterizer::default());
+        canvas.threads = Some(1);
+        canvas.draw_scene_to_canvas(&scene);
+        let single_toi = canvas.toi_buffer.clone();
+        let single_frame: String = canvas.frame_buffer.iter().map(|c| c.symbol).collect();
+
+        canvas.threads = Some(5);
+        canvas.draw_scene_to_canvas(&scene);
+        let multi_frame: String = canvas.frame_buffer.iter().map(|c| c.symbol).collect();
+        assert_eq!(single_toi, canvas.toi_buffer);
+        assert_eq!(single_frame, multi_frame);
+    }
 }
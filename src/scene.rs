@@ -2,31 +2,74 @@
 
 // #![allow(dead_code)]
 use crate::{
-    read::{get_meshes_from_obj, get_shapes_from_pdb},
+    bvh::SceneBvh,
+    lighting::Lighting,
+    measurement::Measurement,
+    read::{get_meshes_from_obj, get_shapes_from_pdb, AtomInfo},
+    selection::Selection,
+    sequence::ChainSequence,
     surface::{ToTriMesh, ValidShape},
 };
-use nalgebra::{Isometry3, Perspective3, Point3, Vector3};
+use nalgebra::{Isometry3, Perspective3, Point2, Point3, Vector3};
+use parry3d::bounding_volume::{Aabb, BoundingVolume};
 use parry3d::mass_properties::MassProperties;
 use parry3d::{
-    query::{Ray, RayCast},
+    query::{Ray, RayCast, RayIntersection},
     shape::{Compound, TriMesh},
 };
 use ratatui::style::Color;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use thiserror::Error;
 
 const ASPECT_RATIO: f32 = 16.0 / 9.0;
 /// Default for FOV in radians
 const FOVY: f32 = std::f32::consts::FRAC_PI_4;
 const ZNEAR_DEFAULT: f32 = 1.0;
 const ZFAR_DEFAULT: f32 = 100.0;
+/// Closest that the near plane can get to the camera, to keep depth precision reasonable
+const ZNEAR_MIN: f32 = 0.1;
+/// Room left around atoms when zooming to them, roughly the largest van der Waals radius in Ångströms
+const ZOOM_MARGIN: f32 = 2.0;
 
-/// The ratio of height to width of terminal characters.
-/// This depends on the font being used by the terminal emulator
-// TODO This needs to be replaced by something sensitive to the rasterizer
-const CHAR_ASPECT_RATIO: f32 = 2.0;
+/// Colours given to shapes by `Scene::recolor`, in order
+pub const PALETTE: [Color; 6] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+/// Colour of highlighted objects, which stands out from the colours in `PALETTE`
+pub const HIGHLIGHT_COLOR: Color = Color::White;
+
+/// Colour after `color` in `PALETTE`, starting from the beginning for colours that aren't in it
+pub fn next_palette_color(color: Color) -> Color {
+    let next = PALETTE
+        .iter()
+        .position(|c| *c == color)
+        .map_or(0, |i| i + 1);
+    PALETTE[next % PALETTE.len()]
+}
+
+/// The ratio of height to width of terminal characters, used when the terminal can't be queried.
+/// The real value depends on the font being used by the terminal emulator
+pub const DEFAULT_CELL_ASPECT_RATIO: f32 = 2.0;
 
 /// Take a point in 2D projection of clip space and convert to ray in world space
+/// The ray starts on the near plane and has a unit direction, so times-of-impact are distances
 pub fn create_ray<S: RayCast + ValidShape>(x_clip: f32, y: f32, scene: &Scene<S>) -> Ray {
+    create_ray_segment(x_clip, y, scene).0
+}
+
+/// Like `create_ray`, but also returns the distance along the ray to the far plane
+pub fn create_ray_segment<S: RayCast + ValidShape>(
+    x_clip: f32,
+    y: f32,
+    scene: &Scene<S>,
+) -> (Ray, f32) {
     // Compute two points in clip-space.
     let near_ndc_point = Point3::new(x_clip, y, -1.0);
     let far_ndc_point = Point3::new(x_clip, y, 1.0);
@@ -43,11 +86,9 @@ pub fn create_ray<S: RayCast + ValidShape>(x_clip: f32, y: f32, scene: &Scene<S>
 
     // Compute the view-space line parameters.
     let origin: Point3<f32> = scene.view.inverse() * near_view_point;
-    // FIXME Turn this into unit normal to avoid TOI being incorrect
-    // FIXME Check other places which assume maximum TOI
-    let dir: Vector3<f32> = scene.view.inverse() * (far_view_point - near_view_point);
-    // dir.normalize_mut();
-    Ray::new(origin, dir)
+    let (dir, max_toi) =
+        nalgebra::Unit::new_and_get(scene.view.inverse() * (far_view_point - near_view_point));
+    (Ray::new(origin, dir.into_inner()), max_toi)
 }
 
 /// Adjusts the aspect ratio for the projection according to non-square pixels
@@ -60,34 +101,73 @@ fn adjust_aspect(aspect_ratio: f32, char_aspect_ratio: f32) -> f32 {
 #[derive(Debug)]
 pub struct SceneProjection {
     pub perspective: Perspective3<f32>,
+    /// The ratio of height to width of the terminal characters that the scene is shown with
+    cell_aspect_ratio: f32,
 }
 impl SceneProjection {
-    pub fn new(znear: f32, zfar: f32, aspect_ratio: f32, fovy: f32) -> Self {
-        let adjusted_aspect_ratio = adjust_aspect(aspect_ratio, CHAR_ASPECT_RATIO);
+    pub fn new(
+        znear: f32,
+        zfar: f32,
+        aspect_ratio: f32,
+        fovy: f32,
+        cell_aspect_ratio: f32,
+    ) -> Self {
+        let adjusted_aspect_ratio = adjust_aspect(aspect_ratio, cell_aspect_ratio);
         let perspective = Perspective3::new(adjusted_aspect_ratio, fovy, znear, zfar);
-        SceneProjection { perspective }
-    }
-    /// Create new projection that fits meshes into `znear` and `zfar`
-    /// Will resort to default `znear` and `zfar` if slice of meshes is empty
-    // FIXME Update for new shapes defined in scene
-    pub fn update_for_shapes(&mut self, shapes: &[ColoredShape<TriMesh>]) {
-        // FIXME Have this not be tied to orientation, maybe by using sphere
-        let znear = shapes
-            .iter()
-            .map(|cs| cs.shape.aabb(&cs.world_transform).mins.z)
-            .reduce(f32::min)
-            .unwrap_or(ZNEAR_DEFAULT);
-        let zfar = shapes
+        SceneProjection {
+            perspective,
+            cell_aspect_ratio,
+        }
+    }
+    pub fn cell_aspect_ratio(&self) -> f32 {
+        self.cell_aspect_ratio
+    }
+    /// Change the character aspect ratio, keeping the same aspect ratio in characters
+    pub fn set_cell_aspect_ratio(&mut self, cell_aspect_ratio: f32) {
+        let aspect_ratio = self.perspective.aspect() * self.cell_aspect_ratio;
+        self.cell_aspect_ratio = cell_aspect_ratio;
+        self.perspective
+            .set_aspect(adjust_aspect(aspect_ratio, cell_aspect_ratio));
+    }
+    /// Change the aspect ratio according to the number of characters in each direction
+    pub fn set_aspect(&mut self, aspect_ratio: f32) {
+        self.perspective
+            .set_aspect(adjust_aspect(aspect_ratio, self.cell_aspect_ratio));
+    }
+    /// Fit `znear` and `zfar` tightly around the bounding sphere of the shapes, as seen from the view
+    /// Using a sphere means that rotating the shapes about their centre never clips them
+    /// Will resort to default `znear` and `zfar` if slice of shapes is empty
+    pub fn update_for_shapes<S: ValidShape>(
+        &mut self,
+        shapes: &[ColoredShape<S>],
+        view: &Isometry3<f32>,
+    ) {
+        let aabb = shapes
             .iter()
-            .map(|cs| cs.shape.aabb(&cs.world_transform).maxs.z)
-            .reduce(f32::max)
-            .unwrap_or(ZFAR_DEFAULT);
+            .map(|cs| cs.shape.world_aabb(&cs.world_transform))
+            .reduce(|merged, aabb| merged.merged(&aabb));
+        let (znear, zfar) = match aabb {
+            None => (ZNEAR_DEFAULT, ZFAR_DEFAULT),
+            Some(aabb) => {
+                let sphere = aabb.bounding_sphere();
+                let depth = -(view * sphere.center()).z;
+                let znear = (depth - sphere.radius()).max(ZNEAR_MIN);
+                let zfar = (depth + sphere.radius()).max(znear + ZNEAR_MIN);
+                (znear, zfar)
+            }
+        };
         self.perspective.set_znear_and_zfar(znear, zfar);
     }
 }
 impl Default for SceneProjection {
     fn default() -> Self {
-        Self::new(ZNEAR_DEFAULT, ZFAR_DEFAULT, ASPECT_RATIO, FOVY)
+        Self::new(
+            ZNEAR_DEFAULT,
+            ZFAR_DEFAULT,
+            ASPECT_RATIO,
+            FOVY,
+            DEFAULT_CELL_ASPECT_RATIO,
+        )
     }
 }
 
@@ -103,6 +183,95 @@ impl<S> ColoredShape<S> {
     }
 }
 
+/// Identifies what a ray hit, used for picking and for effects that depend on neighbouring pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId {
+    /// Index of the shape in the scene
+    pub shape: usize,
+    /// Index of the part of the shape, such as an atom, see `ValidShape::sub_shape`
+    pub sub_shape: Option<u32>,
+}
+
+/// Which shapes and parts of shapes are left out when casting rays, so that they aren't drawn, can't be picked and
+/// don't cast shadows
+#[derive(Debug, Default, Clone)]
+pub struct Visibility {
+    hidden_shapes: HashSet<usize>,
+    /// Hidden parts of each shape, see `ValidShape::sub_shape`
+    hidden_parts: HashMap<usize, HashSet<u32>>,
+}
+
+impl Visibility {
+    /// Whether an object is shown, where an object without a sub-shape is the whole shape
+    pub fn is_visible(&self, id: ObjectId) -> bool {
+        !self.hidden_shapes.contains(&id.shape)
+            && id.sub_shape.is_none_or(|part| {
+                self.hidden_parts
+                    .get(&id.shape)
+                    .is_none_or(|parts| !parts.contains(&part))
+            })
+    }
+    pub fn is_shape_hidden(&self, shape: usize) -> bool {
+        self.hidden_shapes.contains(&shape)
+    }
+    pub fn has_hidden_parts(&self, shape: usize) -> bool {
+        self.hidden_parts
+            .get(&shape)
+            .is_some_and(|parts| !parts.is_empty())
+    }
+    /// Show or hide an object, where an object without a sub-shape is the whole shape
+    /// Showing a whole shape keeps any hidden parts hidden
+    pub fn set_visible(&mut self, id: ObjectId, visible: bool) {
+        match (id.sub_shape, visible) {
+            (None, true) => {
+                self.hidden_shapes.remove(&id.shape);
+            }
+            (None, false) => {
+                self.hidden_shapes.insert(id.shape);
+            }
+            (Some(part), true) => {
+                if let Some(parts) = self.hidden_parts.get_mut(&id.shape) {
+                    parts.remove(&part);
+                }
+            }
+            (Some(part), false) => {
+                self.hidden_parts.entry(id.shape).or_default().insert(part);
+            }
+        }
+    }
+    /// Follow shapes to their new indices after some were removed, see `Scene::remove_file`
+    fn reindex(&mut self, new_indices: &[Option<usize>]) {
+        let new_index = |shape: usize| new_indices.get(shape).copied().flatten();
+        self.hidden_shapes = self
+            .hidden_shapes
+            .iter()
+            .filter_map(|s| new_index(*s))
+            .collect();
+        self.hidden_parts = std::mem::take(&mut self.hidden_parts)
+            .into_iter()
+            .filter_map(|(shape, parts)| Some((new_index(shape)?, parts)))
+            .collect();
+    }
+}
+
+impl ObjectId {
+    /// The same object after some shapes were removed, or `None` if its shape was removed
+    fn reindexed(self, new_indices: &[Option<usize>]) -> Option<Self> {
+        Some(Self {
+            shape: new_indices.get(self.shape).copied().flatten()?,
+            ..self
+        })
+    }
+}
+
+/// Where a shape read from a PDB file came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShapeSource {
+    pub file: String,
+    /// Serial number of the model in the file
+    pub model: usize,
+}
+
 // TODO Add a hierarchy of shapes
 
 /// Calculate center of many shapes
@@ -118,19 +287,44 @@ impl<S: ValidShape> ValidShape for Vec<ColoredShape<S>> {
             })
             .reduce(|sum_m, m| sum_m + m)
     }
+    fn world_aabb(&self, position: &Isometry3<f32>) -> Aabb {
+        self.iter()
+            .map(|cs| cs.shape.world_aabb(&(position * cs.world_transform)))
+            .reduce(|merged, aabb| merged.merged(&aabb))
+            .unwrap_or_else(Aabb::new_invalid)
+    }
 }
 
 /// Holding geometric objects related to rendering
 ///
 /// Holds camera position relative to world coordinates
-/// Also holds list of all the light sources
+/// Also holds the light sources
 // TODO Implement debug for this manually
 pub struct Scene<S: RayCast + ValidShape = TriMesh> {
     pub view: Isometry3<f32>,
-    /// Direction that the lights are pointing (as opposed to location of point source)
-    pub lights: Vec<Vector3<f32>>,
+    pub lighting: Lighting,
     pub scene_projection: SceneProjection,
+    /// Measurements between picked atoms, drawn over the shapes
+    pub measurements: Vec<Measurement>,
     shapes: Vec<ColoredShape<S>>,
+    /// Details of the atoms making up each shape, indexed by shape then by sub-shape
+    /// Empty for shapes that weren't read from a PDB file
+    atoms: Vec<Vec<AtomInfo>>,
+    /// Where each shape came from, which is only known for shapes read from PDB files
+    sources: Vec<Option<ShapeSource>>,
+    /// Every file loaded, in the order they were loaded, so that a file loaded twice is listed twice
+    files: Vec<String>,
+    /// Index into `files` of the load each shape came from, which is `None` for shapes that were added directly
+    loads: Vec<Option<usize>>,
+    /// Sequences of the shapes read from PDB files
+    sequences: Vec<ChainSequence>,
+    /// Objects drawn in `HIGHLIGHT_COLOR` rather than the colour of their shape
+    highlighted: HashSet<ObjectId>,
+    /// Colours of parts of shapes that differ from the colour of their shape
+    part_colors: HashMap<ObjectId, Color>,
+    visibility: Visibility,
+    /// Must be kept in sync with the shapes and their transforms
+    bvh: SceneBvh,
 }
 
 impl<S: RayCast + ValidShape> Scene<S> {
@@ -138,33 +332,317 @@ impl<S: RayCast + ValidShape> Scene<S> {
         eye: &Point3<f32>,
         target: &Point3<f32>,
         up: &Vector3<f32>,
-        lights: &[Vector3<f32>],
+        lighting: Lighting,
         scene_projection: SceneProjection,
         shapes: Vec<ColoredShape<S>>,
     ) -> Self {
         let view = Isometry3::face_towards(eye, target, up);
-        let lights = lights.to_owned();
+        let mut bvh = SceneBvh::default();
+        bvh.rebuild(&shapes);
         Scene {
             view,
-            lights,
+            lighting,
             scene_projection,
+            measurements: vec![],
+            atoms: vec![vec![]; shapes.len()],
+            sources: vec![None; shapes.len()],
+            files: vec![],
+            loads: vec![None; shapes.len()],
+            sequences: vec![],
+            highlighted: HashSet::new(),
+            part_colors: HashMap::new(),
+            visibility: Visibility::default(),
             shapes,
+            bvh,
         }
     }
     pub fn shapes(&self) -> &[ColoredShape<S>] {
         &self.shapes[..]
     }
+    /// Add shapes to the scene
+    pub fn add_shapes(&mut self, shapes: Vec<ColoredShape<S>>) {
+        self.shapes.extend(shapes);
+        self.atoms.resize(self.shapes.len(), vec![]);
+        self.sources.resize(self.shapes.len(), None);
+        self.loads.resize(self.shapes.len(), None);
+        self.bvh.rebuild(&self.shapes);
+        self.scene_projection
+            .update_for_shapes(&self.shapes, &self.view);
+    }
+    /// Position of the camera in world space
+    pub fn eye(&self) -> Point3<f32> {
+        self.view.inverse_transform_point(&Point3::origin())
+    }
+    /// Depth of a point in world space along the viewing direction
+    pub fn view_depth(&self, point: &Point3<f32>) -> f32 {
+        -(self.view * point).z
+    }
+    /// Find the nearest shape hit by a ray in world space, returning the index of the shape
+    /// Hidden shapes and parts are skipped over
+    pub fn cast_ray(&self, ray: &Ray, max_toi: f32) -> Option<(usize, RayIntersection)> {
+        self.bvh
+            .cast_ray(&self.shapes, ray, max_toi, true, &self.visibility)
+    }
+    /// Details of the atom that an object is, if it was read from a PDB file
+    pub fn atom(&self, id: ObjectId) -> Option<&AtomInfo> {
+        self.atoms.get(id.shape)?.get(id.sub_shape? as usize)
+    }
+    pub fn sequences(&self) -> &[ChainSequence] {
+        &self.sequences[..]
+    }
+    pub fn source(&self, shape: usize) -> Option<&ShapeSource> {
+        self.sources.get(shape)?.as_ref()
+    }
+    /// Every file that shapes were read from, in the order they were loaded
+    pub fn files(&self) -> Vec<&str> {
+        self.files.iter().map(String::as_str).collect()
+    }
+    /// Record the shapes from `first` onwards as being read from a file
+    fn add_load(&mut self, first: usize, file: &str) {
+        for load in self.loads[first..].iter_mut() {
+            *load = Some(self.files.len());
+        }
+        self.files.push(file.to_string());
+    }
+    /// Remove every shape read by one load of a file, as indexed in `files`, along with anything referring to
+    /// those shapes
+    /// Other loads of the same file are left alone
+    /// Returns whether anything was removed
+    pub fn remove_file(&mut self, index: usize) -> bool {
+        if index >= self.files.len() {
+            return false;
+        }
+        self.files.remove(index);
+        let mut count = 0;
+        let new_indices: Vec<Option<usize>> = self
+            .loads
+            .iter()
+            .map(|load| {
+                (*load != Some(index)).then(|| {
+                    count += 1;
+                    count - 1
+                })
+            })
+            .collect();
+        self.loads = std::mem::take(&mut self.loads)
+            .into_iter()
+            .filter(|load| *load != Some(index))
+            .map(|load| load.map(|load| if load > index { load - 1 } else { load }))
+            .collect();
+        let mut keep = new_indices.iter().map(Option::is_some);
+        self.shapes.retain(|_| keep.next().unwrap_or(true));
+        let mut keep = new_indices.iter().map(Option::is_some);
+        self.atoms.retain(|_| keep.next().unwrap_or(true));
+        let mut keep = new_indices.iter().map(Option::is_some);
+        self.sources.retain(|_| keep.next().unwrap_or(true));
+        self.sequences = std::mem::take(&mut self.sequences)
+            .into_iter()
+            .filter_map(|mut sequence| {
+                sequence.shape = new_indices[sequence.shape]?;
+                Some(sequence)
+            })
+            .collect();
+        self.highlighted = self
+            .highlighted
+            .iter()
+            .filter_map(|id| id.reindexed(&new_indices))
+            .collect();
+        self.part_colors = std::mem::take(&mut self.part_colors)
+            .into_iter()
+            .filter_map(|(id, color)| Some((id.reindexed(&new_indices)?, color)))
+            .collect();
+        self.visibility.reindex(&new_indices);
+        self.measurements = std::mem::take(&mut self.measurements)
+            .into_iter()
+            .filter_map(|mut measurement| {
+                measurement.atoms = measurement
+                    .atoms
+                    .iter()
+                    .map(|id| id.reindexed(&new_indices))
+                    .collect::<Option<_>>()?;
+                Some(measurement)
+            })
+            .collect();
+        self.bvh.rebuild(&self.shapes);
+        self.scene_projection
+            .update_for_shapes(&self.shapes, &self.view);
+        true
+    }
+    pub fn visibility(&self) -> &Visibility {
+        &self.visibility
+    }
+    /// Show or hide an object, where an object without a sub-shape is the whole shape
+    pub fn set_visible(&mut self, id: ObjectId, visible: bool) {
+        self.visibility.set_visible(id, visible);
+    }
+    /// Change the colour of an object, where colouring a whole shape replaces the colours of any of its parts
+    pub fn set_color(&mut self, id: ObjectId, color: Color) {
+        match id.sub_shape {
+            Some(_) => {
+                self.part_colors.insert(id, color);
+            }
+            None => {
+                self.shapes[id.shape].set_color(color);
+                self.part_colors.retain(|part, _| part.shape != id.shape);
+            }
+        }
+    }
+    /// Replace the highlighted objects
+    pub fn set_highlighted(&mut self, ids: impl IntoIterator<Item = ObjectId>) {
+        self.highlighted = ids.into_iter().collect();
+    }
+    /// Colour an object is drawn in, which is the colour of its shape unless it is highlighted or coloured by itself
+    pub fn color_of(&self, id: ObjectId) -> Color {
+        if self.highlighted.contains(&id) {
+            HIGHLIGHT_COLOR
+        } else {
+            self.part_colors
+                .get(&id)
+                .copied()
+                .unwrap_or(self.shapes[id.shape].color)
+        }
+    }
+    /// Position of an atom in world space, following the shape it belongs to
+    pub fn atom_position(&self, id: ObjectId) -> Option<Point3<f32>> {
+        let atom = self.atom(id)?;
+        Some(self.shapes.get(id.shape)?.world_transform * atom.position)
+    }
+    /// Project a point in world space to the 2D projection of clip space, or `None` if it is behind the camera
+    pub fn project_point(&self, point: &Point3<f32>) -> Option<Point2<f32>> {
+        let view_point = self.view * point;
+        if -view_point.z < self.scene_projection.perspective.znear() {
+            return None;
+        }
+        let clip_point = self.scene_projection.perspective.project_point(&view_point);
+        Some(clip_point.xy())
+    }
+    /// Identify the shape and part of the shape hit by a ray in world space
+    pub fn object_id(&self, shape: usize, ray: &Ray, intersection: &RayIntersection) -> ObjectId {
+        let cs = &self.shapes[shape];
+        let sub_shape = if self.visibility.has_hidden_parts(shape) {
+            // Hidden parts may be in front of the part that was hit
+            let max_toi = intersection.toi * (1.0 + f32::EPSILON.sqrt()) + f32::EPSILON;
+            let visible = |part| {
+                self.visibility.is_visible(ObjectId {
+                    shape,
+                    sub_shape: Some(part),
+                })
+            };
+            cs.shape
+                .cast_ray_to_visible_part(&cs.world_transform, ray, max_toi, true, &visible)
+                .and_then(|(_, part)| part)
+        } else {
+            let local_ray = ray.inverse_transform_by(&cs.world_transform);
+            cs.shape.sub_shape(&local_ray, intersection)
+        };
+        ObjectId { shape, sub_shape }
+    }
     /// Change the scene projection according to new width and height of canvas
     pub fn update_aspect(&mut self, width: usize, height: usize) {
         let aspect_ratio = width as f32 / height as f32;
-        let adjusted_aspect_ratio = adjust_aspect(aspect_ratio, CHAR_ASPECT_RATIO);
+        self.scene_projection.set_aspect(aspect_ratio);
+    }
+    /// Change the ratio of height to width of the characters the scene is shown with
+    pub fn set_cell_aspect_ratio(&mut self, cell_aspect_ratio: f32) {
         self.scene_projection
-            .perspective
-            .set_aspect(adjusted_aspect_ratio);
+            .set_cell_aspect_ratio(cell_aspect_ratio);
+    }
+    /// Change the vertical field of view, in radians
+    pub fn set_field_of_view(&mut self, fovy: f32) {
+        self.scene_projection.perspective.set_fovy(fovy);
+    }
+    /// Every object picked out by a selection
+    /// Shapes that weren't read from a PDB file have no atoms, so are only picked out as whole shapes by `all`
+    pub fn select(&self, selection: &Selection) -> Vec<ObjectId> {
+        let mut ids = vec![];
+        for (shape, atoms) in self.atoms.iter().enumerate() {
+            if atoms.is_empty() && *selection == Selection::All {
+                ids.push(ObjectId {
+                    shape,
+                    sub_shape: None,
+                });
+            }
+            ids.extend(
+                atoms
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, atom)| selection.matches(atom))
+                    .map(|(sub_shape, _)| ObjectId {
+                        shape,
+                        sub_shape: Some(sub_shape as u32),
+                    }),
+            );
+        }
+        ids
+    }
+    /// Move the camera, without turning it, so that it looks at the middle of some objects and they fill the view
+    /// Does nothing if there are no objects
+    pub fn zoom_to(&mut self, ids: &[ObjectId]) {
+        let aabb = ids
+            .iter()
+            .filter_map(|id| match id.sub_shape {
+                Some(_) => {
+                    let position = self.atom_position(*id)?;
+                    Some(Aabb::from_half_extents(
+                        position,
+                        Vector3::repeat(ZOOM_MARGIN),
+                    ))
+                }
+                None => {
+                    let cs = self.shapes.get(id.shape)?;
+                    Some(cs.shape.world_aabb(&cs.world_transform))
+                }
+            })
+            .reduce(|merged, aabb| merged.merged(&aabb));
+        let Some(aabb) = aabb else {
+            return;
+        };
+        let sphere = aabb.bounding_sphere();
+        // Fit the sphere into whichever of the width and height is narrower
+        let perspective = &self.scene_projection.perspective;
+        let half_height = (perspective.fovy() / 2.0).tan();
+        let half_fov = half_height.min(half_height * perspective.aspect()).atan();
+        let distance = sphere.radius() / half_fov.sin();
+        let center = self.view * sphere.center();
+        let transform = Isometry3::translation(-center.x, -center.y, -distance - center.z);
+        self.transform_view(&transform);
+    }
+    /// Load a file while the scene is already being shown, placing the new shapes like the existing ones
+    /// Returns any warnings about the file
+    pub fn load_file<Q: AsRef<Path>>(&mut self, path: Q) -> Result<Vec<String>, LoadError>
+    where
+        S: LoadShapes,
+    {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        if !path.is_file() {
+            return Err(LoadError::NotFound(name));
+        }
+        if !S::can_load(path) {
+            return Err(LoadError::Unsupported(name));
+        }
+        let first = self.shapes.len();
+        let warnings = S::load(self, path)?;
+        // Moving the shapes around changes their world transforms, which the new shapes need to match
+        match self.shapes[..first].first().map(|cs| cs.world_transform) {
+            Some(transform) => {
+                for cs in self.shapes[first..].iter_mut() {
+                    cs.world_transform = transform;
+                }
+                self.bvh.rebuild(&self.shapes);
+                self.scene_projection
+                    .update_for_shapes(&self.shapes, &self.view);
+            }
+            None => self.shapes_to_center(),
+        }
+        self.color_shapes(first);
+        Ok(warnings)
     }
     /// Change the view according to transformation
     pub fn transform_view(&mut self, transform: &Isometry3<f32>) {
         self.view = transform * self.view;
+        self.scene_projection
+            .update_for_shapes(&self.shapes, &self.view);
     }
     /// Transform shapes by a transformation
     /// Internally, prepends trasnformation to existing internal transformation
@@ -172,6 +650,9 @@ impl<S: RayCast + ValidShape> Scene<S> {
         for cs in self.shapes.iter_mut() {
             cs.world_transform = transform * cs.world_transform;
         }
+        self.bvh.refit(&self.shapes);
+        self.scene_projection
+            .update_for_shapes(&self.shapes, &self.view);
     }
     /// Make the mesh be at the center of the view
     pub fn shapes_to_center(&mut self) {
@@ -187,25 +668,67 @@ impl<S: RayCast + ValidShape> Scene<S> {
     /// Recolor the shapes in a way that maximises visibility
     // TODO Change this function to maximise diversity based on relative distances
     pub fn recolor(&mut self) {
-        let ordering = [
-            Color::Red,
-            Color::Green,
-            Color::Yellow,
-            Color::Blue,
-            Color::Magenta,
-            Color::Cyan,
-        ];
-        for (i, shape) in self.shapes.iter_mut().enumerate() {
-            shape.set_color(ordering[i % ordering.len()])
+        self.color_shapes(0);
+        self.part_colors.clear();
+    }
+    /// Give the shapes from `first` onwards colours from the palette
+    fn color_shapes(&mut self, first: usize) {
+        for (i, shape) in self.shapes.iter_mut().enumerate().skip(first) {
+            shape.set_color(PALETTE[i % PALETTE.len()])
         }
     }
 }
 
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("Could not find {0}.")]
+    NotFound(String),
+    #[error("Can't show {0} alongside the shapes already loaded.")]
+    Unsupported(String),
+    /// Holds descriptions of what was wrong with the file
+    #[error("Could not read {0}: {}", .1.join(" "))]
+    Invalid(String, Vec<String>),
+}
+
+/// Shapes that files can be read as while the viewer is running
+pub trait LoadShapes: RayCast + ValidShape + Sized {
+    /// Whether a file can be read as this kind of shape, judging by its extension
+    fn can_load(path: &Path) -> bool;
+    /// Add the shapes read from a file to the end of those in a scene, returning warnings about the file
+    fn load(scene: &mut Scene<Self>, path: &Path) -> Result<Vec<String>, LoadError>;
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+impl LoadShapes for TriMesh {
+    fn can_load(path: &Path) -> bool {
+        has_extension(path, "obj")
+    }
+    fn load(scene: &mut Scene<Self>, path: &Path) -> Result<Vec<String>, LoadError> {
+        scene.load_meshes_from_path(path)?;
+        Ok(vec![])
+    }
+}
+
+impl LoadShapes for Compound {
+    fn can_load(path: &Path) -> bool {
+        has_extension(path, "pdb")
+    }
+    fn load(scene: &mut Scene<Self>, path: &Path) -> Result<Vec<String>, LoadError> {
+        scene.load_shapes_from_pdb(path.to_string_lossy())
+    }
+}
+
 impl Scene<TriMesh> {
     /// Adds meshes found at path to existing meshes vector
-    pub fn load_meshes_from_path<Q: AsRef<Path>>(&mut self, path: Q) {
-        let tobj_meshes = get_meshes_from_obj(path);
-        let mut new_meshes = tobj_meshes
+    pub fn load_meshes_from_path<Q: AsRef<Path>>(&mut self, path: Q) -> Result<(), LoadError> {
+        let file = path.as_ref().display().to_string();
+        let tobj_meshes = get_meshes_from_obj(&path)
+            .map_err(|error| LoadError::Invalid(file.clone(), vec![error.to_string()]))?;
+        let new_meshes = tobj_meshes
             .iter()
             .map(|m| m.to_tri_mesh())
             .map(|m| ColoredShape {
@@ -214,16 +737,32 @@ impl Scene<TriMesh> {
                 color: Color::Black,
             })
             .collect();
-        self.shapes.append(&mut new_meshes);
-        self.scene_projection.update_for_shapes(&self.shapes);
+        let first = self.shapes.len();
+        self.add_shapes(new_meshes);
+        self.add_load(first, &file);
+        Ok(())
     }
 }
 
 impl Scene<Compound> {
-    // TODO Add proper signature
-    pub fn load_shapes_from_pdb<Q: AsRef<str>>(&mut self, path: Q) {
-        let compounds = get_shapes_from_pdb(path);
-        let mut shapes = compounds
+    /// Add the chains of a PDB file, returning any warnings about the file
+    pub fn load_shapes_from_pdb<Q: AsRef<str>>(
+        &mut self,
+        path: Q,
+    ) -> Result<Vec<String>, LoadError> {
+        let file = path.as_ref().to_string();
+        let (chains, warnings) =
+            get_shapes_from_pdb(path).map_err(|errors| LoadError::Invalid(file.clone(), errors))?;
+        self.add_pdb_shapes(&file, chains);
+        Ok(warnings)
+    }
+    /// Add the shape of each chain read from a PDB file, along with the model it is in and its atoms
+    pub fn add_pdb_shapes(&mut self, file: &str, chains: Vec<(usize, Compound, Vec<AtomInfo>)>) {
+        let (models, (compounds, atoms)): (Vec<_>, (Vec<_>, Vec<_>)) = chains
+            .into_iter()
+            .map(|(model, compound, atoms)| (model, (compound, atoms)))
+            .unzip();
+        let shapes = compounds
             .into_iter()
             .map(|c| ColoredShape {
                 shape: c,
@@ -231,9 +770,21 @@ impl Scene<Compound> {
                 color: Color::Black,
             })
             .collect();
-        self.shapes.append(&mut shapes);
-        // FIXME Make this work
-        // self.scene_projection.update_for_shapes(&self.shapes);
+        let first = self.shapes.len();
+        self.add_shapes(shapes);
+        for (shape_atoms, new_atoms) in self.atoms[first..].iter_mut().zip(atoms) {
+            *shape_atoms = new_atoms;
+        }
+        self.add_load(first, file);
+        for (source, model) in self.sources[first..].iter_mut().zip(models) {
+            *source = Some(ShapeSource {
+                file: file.to_string(),
+                model,
+            });
+        }
+        for (shape, atoms) in self.atoms.iter().enumerate().skip(first) {
+            self.sequences.push(ChainSequence::from_atoms(shape, atoms));
+        }
     }
 }
 
@@ -242,19 +793,17 @@ impl<S: RayCast + ValidShape> Default for Scene<S> {
         let eye = Point3::new(0.0f32, 0.0f32, -50.0f32);
         let target = Point3::new(0.0f32, 0.0f32, 0.0f32);
         let up = Vector3::new(0.0f32, 1.0f32, 0.0f32);
-        let lights = vec![
-            0.7 * Vector3::new(0.0f32, 1.0f32, 1.0f32),
-            // Vector3::new(0.0f32, -1.0f32, -1.0f32),
-        ];
+        let lighting = Lighting::default();
         let scene_projection = SceneProjection::default();
         let shapes = vec![];
-        Self::new(&eye, &target, &up, &lights, scene_projection, shapes)
+        Self::new(&eye, &target, &up, lighting, scene_projection, shapes)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::measurement::MeasurementKind;
     use parry3d::shape::{Ball, SharedShape};
     use std::sync::Arc;
 
@@ -277,4 +826,217 @@ mod tests {
 
         assert_eq!(scene.shapes.len(), 1)
     }
+
+    #[test]
+    fn test_object_id() {
+        let balls = [-15.0, 0.0, 15.0]
+            .map(|x| {
+                (
+                    Isometry3::<f32>::translation(x, 0.0, 0.0),
+                    SharedShape(Arc::new(Ball::new(5.0))),
+                )
+            })
+            .to_vec();
+        let mut scene = Scene::<Compound>::default();
+        scene.add_shapes(vec![ColoredShape {
+            world_transform: Isometry3::translation(0.0, 0.0, -50.0),
+            shape: Compound::new(balls),
+            color: Color::Black,
+        }]);
+
+        for (x, part) in [(-15.0, 0), (0.0, 1), (15.0, 2)] {
+            let ray = Ray::new(Point3::new(x, 0.0, 0.0), -Vector3::z());
+            let (shape, intersection) = scene.cast_ray(&ray, f32::MAX).unwrap();
+            let id = scene.object_id(shape, &ray, &intersection);
+            assert_eq!(
+                id,
+                ObjectId {
+                    shape: 0,
+                    sub_shape: Some(part)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_atom_lookup() {
+        let mut scene = Scene::<Ball>::default();
+        scene.add_shapes(vec![ColoredShape {
+            world_transform: Isometry3::identity(),
+            shape: Ball::new(1.0),
+            color: Color::Black,
+        }]);
+        let atom = AtomInfo::test("A", ("GLY", 3), "CA", Point3::origin());
+        scene.atoms[0].push(atom.clone());
+
+        let id = |shape, sub_shape| ObjectId { shape, sub_shape };
+        assert_eq!(scene.atom(id(0, Some(0))), Some(&atom));
+        assert_eq!(scene.atom(id(0, Some(1))), None);
+        assert_eq!(scene.atom(id(0, None)), None);
+        assert_eq!(scene.atom(id(1, Some(0))), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mut scene = Scene::<Ball>::default();
+        scene.add_shapes(vec![ColoredShape {
+            world_transform: Isometry3::identity(),
+            shape: Ball::new(1.0),
+            color: Color::Red,
+        }]);
+        let id = ObjectId {
+            shape: 0,
+            sub_shape: None,
+        };
+        assert_eq!(scene.color_of(id), Color::Red);
+        scene.set_highlighted([id]);
+        assert_eq!(scene.color_of(id), HIGHLIGHT_COLOR);
+        scene.set_highlighted([]);
+        assert_eq!(scene.color_of(id), Color::Red);
+    }
+
+    #[test]
+    fn test_project_point() {
+        let scene = Scene::<TriMesh>::default();
+        let target = scene.project_point(&Point3::origin()).unwrap();
+        assert!(target.coords.norm() < 1e-6);
+        // Anything behind the camera can't be shown
+        let behind = scene.eye() + (scene.eye() - Point3::origin());
+        assert_eq!(scene.project_point(&behind), None);
+
+        // Points on a ray project back to where the ray was cast from
+        let ray = create_ray(0.5, -0.25, &scene);
+        let point = scene.project_point(&ray.point_at(20.0)).unwrap();
+        assert!((point - Point2::new(0.5, -0.25)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_select_and_zoom() {
+        let mut scene = Scene::<Compound>::default();
+        let atoms: Vec<AtomInfo> = [("A", "GLY"), ("A", "HOH"), ("B", "GLY")]
+            .iter()
+            .enumerate()
+            .map(|(i, (chain, residue_name))| {
+                let position = Point3::new(10.0 * i as f32, 0.0, 0.0);
+                AtomInfo::test(chain, (residue_name, i as isize), "CA", position)
+            })
+            .collect();
+        let balls = atoms
+            .iter()
+            .map(|atom| {
+                (
+                    Isometry3::from(atom.position.coords),
+                    SharedShape(Arc::new(Ball::new(1.0))),
+                )
+            })
+            .collect();
+        scene.add_pdb_shapes("test.pdb", vec![(1, Compound::new(balls), atoms)]);
+
+        let id = |sub_shape| ObjectId {
+            shape: 0,
+            sub_shape: Some(sub_shape),
+        };
+        let chain_a = scene.select(&Selection::Chain("A".to_string()));
+        assert_eq!(chain_a, vec![id(0), id(1)]);
+        assert_eq!(
+            scene.select(&Selection::ResidueName("HOH".to_string())),
+            vec![id(1)]
+        );
+        assert_eq!(scene.select(&Selection::All).len(), 3);
+
+        // Zooming to a single atom centres it, with its margin just filling the height of a wide view
+        scene.set_cell_aspect_ratio(1.0);
+        scene.update_aspect(20, 10);
+        scene.zoom_to(&[id(2)]);
+        let position = scene.atom_position(id(2)).unwrap();
+        assert!(scene.project_point(&position).unwrap().coords.norm() < 1e-4);
+        let radius = Vector3::repeat(ZOOM_MARGIN).norm();
+        let expected = radius / (FOVY / 2.0).sin();
+        assert!((scene.view_depth(&position) - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_remove_file() {
+        let chain = |chain: &str| {
+            let atom = AtomInfo::test(chain, ("GLY", 1), "CA", Point3::origin());
+            let ball = SharedShape(Arc::new(Ball::new(1.0)));
+            let compound = Compound::new(vec![(Isometry3::identity(), ball)]);
+            (1, compound, vec![atom])
+        };
+        let mut scene = Scene::<Compound>::default();
+        scene.add_pdb_shapes("first.pdb", vec![chain("A"), chain("B")]);
+        scene.add_pdb_shapes("second.pdb", vec![chain("C")]);
+        assert_eq!(scene.files(), vec!["first.pdb", "second.pdb"]);
+
+        let id = |shape| ObjectId {
+            shape,
+            sub_shape: Some(0),
+        };
+        scene.set_visible(id(2), false);
+        scene.set_color(id(1), Color::Red);
+        scene.measurements.push(Measurement {
+            kind: MeasurementKind::Distance,
+            atoms: vec![id(0), id(1)],
+            labels: vec![],
+            value: 0.0,
+        });
+
+        assert!(!scene.remove_file(2));
+        assert!(scene.remove_file(0));
+        assert_eq!(scene.files(), vec!["second.pdb"]);
+        assert_eq!(scene.shapes().len(), 1);
+        assert_eq!(scene.atom(id(0)).unwrap().chain, "C");
+        assert_eq!(scene.sequences()[0].shape, 0);
+        assert_eq!(scene.source(0).unwrap().file, "second.pdb");
+        // Anything referring to the removed shapes goes, and the rest follows the shapes to their new indices
+        assert!(!scene.visibility().is_visible(id(0)));
+        assert_eq!(scene.color_of(id(0)), scene.shapes()[0].color);
+        assert!(scene.measurements.is_empty());
+
+        // Loading a file again can be removed without touching the earlier load of it
+        scene.add_pdb_shapes("second.pdb", vec![chain("D")]);
+        assert_eq!(scene.files(), vec!["second.pdb", "second.pdb"]);
+        assert!(scene.remove_file(0));
+        assert_eq!(scene.files(), vec!["second.pdb"]);
+        assert_eq!(scene.atom(id(0)).unwrap().chain, "D");
+        assert!(scene.remove_file(0));
+        assert!(scene.shapes().is_empty());
+    }
+
+    #[test]
+    fn test_load_file_errors() {
+        let mut scene = Scene::<TriMesh>::default();
+        assert!(matches!(
+            scene.load_file("./data/missing.obj"),
+            Err(LoadError::NotFound(_))
+        ));
+        assert!(matches!(
+            scene.load_file("./data/rbd.pdb"),
+            Err(LoadError::Unsupported(_))
+        ));
+
+        // A broken file is reported rather than stopping the viewer
+        let path = std::env::temp_dir().join("pdb_tui_test_broken.obj");
+        std::fs::write(&path, "v 0.0 zero\nf 1 2 3\n").unwrap();
+        let result = scene.load_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(LoadError::Invalid(..))));
+        assert!(scene.shapes.is_empty());
+
+        assert!(scene.load_file("./data/surface.obj").is_ok());
+        assert_eq!(scene.files(), vec!["./data/surface.obj"]);
+    }
+
+    #[test]
+    fn test_cell_aspect_ratio() {
+        let mut scene = Scene::<TriMesh>::default();
+        scene.update_aspect(80, 20);
+        assert!((scene.scene_projection.perspective.aspect() - 2.0).abs() < 1e-6);
+
+        // Square cells mean the aspect ratio in characters is the real aspect ratio
+        scene.set_cell_aspect_ratio(1.0);
+        assert!((scene.scene_projection.perspective.aspect() - 4.0).abs() < 1e-6);
+        scene.update_aspect(40, 20);
+        assert!((scene.scene_projection.perspective.aspect() - 2.0).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,141 @@
+//! Selections of atoms by their chain, residue, name or element, as typed in commands such as `hide resn HOH`.
+
+use crate::read::AtomInfo;
+use std::ops::RangeInclusive;
+use thiserror::Error;
+
+/// Keywords starting each kind of selection
+pub const SELECTION_KEYWORDS: [&str; 6] = ["all", "chain", "resn", "resi", "name", "elem"];
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SelectionError {
+    #[error("Expected a selection such as `chain A`.")]
+    Missing,
+    #[error("Unknown selection `{0}`.")]
+    UnknownKeyword(String),
+    #[error("Selection `{0}` needs a value.")]
+    MissingValue(String),
+    #[error("Could not read residue numbers from `{0}`.")]
+    InvalidResidueNumbers(String),
+    #[error("Unexpected `{0}` after selection.")]
+    TrailingWords(String),
+}
+
+/// Which atoms a command acts on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// Every shape, including those not read from PDB files
+    All,
+    Chain(String),
+    ResidueName(String),
+    ResidueNumbers(RangeInclusive<isize>),
+    AtomName(String),
+    Element(String),
+}
+
+impl Selection {
+    /// Read a selection from the words of a command, such as `["resi", "10-20"]`
+    pub fn parse(words: &[&str]) -> Result<Self, SelectionError> {
+        let (&keyword, rest) = words.split_first().ok_or(SelectionError::Missing)?;
+        if keyword == "all" {
+            return match rest.first() {
+                Some(word) => Err(SelectionError::TrailingWords(word.to_string())),
+                None => Ok(Self::All),
+            };
+        }
+        if !SELECTION_KEYWORDS.contains(&keyword) {
+            return Err(SelectionError::UnknownKeyword(keyword.to_string()));
+        }
+        let value = match rest {
+            [] => return Err(SelectionError::MissingValue(keyword.to_string())),
+            [value] => value.to_string(),
+            [_, word, ..] => return Err(SelectionError::TrailingWords(word.to_string())),
+        };
+        Ok(match keyword {
+            "chain" => Self::Chain(value),
+            "resn" => Self::ResidueName(value.to_ascii_uppercase()),
+            "resi" => Self::ResidueNumbers(parse_residue_numbers(&value)?),
+            "name" => Self::AtomName(value.to_ascii_uppercase()),
+            _ => Self::Element(value.to_ascii_uppercase()),
+        })
+    }
+    pub fn matches(&self, atom: &AtomInfo) -> bool {
+        match self {
+            Self::All => true,
+            Self::Chain(chain) => atom.chain == *chain,
+            Self::ResidueName(name) => atom.residue_name.eq_ignore_ascii_case(name),
+            Self::ResidueNumbers(numbers) => numbers.contains(&atom.residue_number),
+            Self::AtomName(name) => atom.name.eq_ignore_ascii_case(name),
+            Self::Element(element) => atom.element.eq_ignore_ascii_case(element),
+        }
+    }
+}
+
+/// Single residue number such as `10`, or a range such as `10-20`, where negative numbers are allowed
+fn parse_residue_numbers(value: &str) -> Result<RangeInclusive<isize>, SelectionError> {
+    let invalid = || SelectionError::InvalidResidueNumbers(value.to_string());
+    // Skip the first character so that a leading minus sign isn't taken for a range
+    let split = value
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '-')
+        .map(|(i, _)| i);
+    let (start, end) = match split {
+        Some(i) => (&value[..i], &value[i + 1..]),
+        None => (value, value),
+    };
+    let start: isize = start.parse().map_err(|_| invalid())?;
+    let end: isize = end.parse().map_err(|_| invalid())?;
+    Ok(start.min(end)..=start.max(end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Selection::parse(&["all"]), Ok(Selection::All));
+        assert_eq!(
+            Selection::parse(&["chain", "A"]),
+            Ok(Selection::Chain("A".to_string()))
+        );
+        assert_eq!(
+            Selection::parse(&["resn", "hoh"]),
+            Ok(Selection::ResidueName("HOH".to_string()))
+        );
+        assert_eq!(
+            Selection::parse(&["resi", "20-10"]),
+            Ok(Selection::ResidueNumbers(10..=20))
+        );
+        assert_eq!(
+            Selection::parse(&["resi", "-3"]),
+            Ok(Selection::ResidueNumbers(-3..=-3))
+        );
+        assert_eq!(Selection::parse(&[]), Err(SelectionError::Missing));
+        assert_eq!(
+            Selection::parse(&["chain"]),
+            Err(SelectionError::MissingValue("chain".to_string()))
+        );
+        assert_eq!(
+            Selection::parse(&["resi", "a"]),
+            Err(SelectionError::InvalidResidueNumbers("a".to_string()))
+        );
+        assert_eq!(
+            Selection::parse(&["colour", "A"]),
+            Err(SelectionError::UnknownKeyword("colour".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let atom = AtomInfo::test("B", ("GLY", 12), "CA", Point3::origin());
+        assert!(Selection::All.matches(&atom));
+        assert!(Selection::Chain("B".to_string()).matches(&atom));
+        assert!(!Selection::Chain("A".to_string()).matches(&atom));
+        assert!(Selection::ResidueNumbers(10..=12).matches(&atom));
+        assert!(Selection::AtomName("ca".to_string()).matches(&atom));
+        assert!(!Selection::Element("N".to_string()).matches(&atom));
+    }
+}
@@ -0,0 +1,263 @@
+//! Sequences of the chains read from PDB files, with their secondary structure.
+//!
+//! Sequences are built from the atoms kept alongside each shape, so that residues can be matched to the atoms picked in
+//! the canvas and back.
+
+use crate::{
+    measurement::{dihedral, distance},
+    read::{AtomInfo, SecondaryStructure},
+    scene::ObjectId,
+};
+use nalgebra::Point3;
+
+/// Range of the virtual torsion between four consecutive alpha carbons in a helix, in degrees
+const HELIX_TORSION: std::ops::RangeInclusive<f32> = 30.0..=80.0;
+/// Range of distances between alpha carbons three residues apart in a helix, in Ångströms
+const HELIX_SPAN: std::ops::RangeInclusive<f32> = 4.2..=6.2;
+/// Smallest size of the virtual torsion between four consecutive alpha carbons in a strand, in degrees
+const STRAND_TORSION: f32 = 120.0;
+/// Smallest distance between alpha carbons two residues apart in a strand, in Ångströms
+const STRAND_SPAN: f32 = 6.0;
+
+/// One-letter code of a residue, or `X` if it isn't a standard amino acid or nucleotide
+pub fn one_letter_code(residue_name: &str) -> char {
+    match residue_name.to_ascii_uppercase().as_str() {
+        "ALA" => 'A',
+        "ARG" => 'R',
+        "ASN" => 'N',
+        "ASP" => 'D',
+        "CYS" => 'C',
+        "GLN" => 'Q',
+        "GLU" => 'E',
+        "GLY" => 'G',
+        "HIS" => 'H',
+        "ILE" => 'I',
+        "LEU" => 'L',
+        "LYS" => 'K',
+        "MET" | "MSE" => 'M',
+        "PHE" => 'F',
+        "PRO" => 'P',
+        "SER" => 'S',
+        "THR" => 'T',
+        "TRP" => 'W',
+        "TYR" => 'Y',
+        "VAL" => 'V',
+        "SEC" => 'U',
+        "PYL" => 'O',
+        "A" | "DA" => 'A',
+        "C" | "DC" => 'C',
+        "G" | "DG" => 'G',
+        "U" => 'U',
+        "DT" => 'T',
+        _ => 'X',
+    }
+}
+
+/// Residue in a sequence, along with the atoms that belong to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceResidue {
+    pub number: isize,
+    pub name: String,
+    pub code: char,
+    pub secondary_structure: SecondaryStructure,
+    /// Sub-shapes of the atoms of the residue
+    pub atoms: Vec<u32>,
+}
+
+/// Sequence of the residues of a chain, which is a single shape
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainSequence {
+    /// Index of the shape in the scene
+    pub shape: usize,
+    pub chain: String,
+    pub residues: Vec<SequenceResidue>,
+}
+
+impl ChainSequence {
+    /// Group the atoms of a shape into residues, in the order they were read
+    /// Secondary structure is estimated from the alpha carbons if the file didn't give it
+    pub fn from_atoms(shape: usize, atoms: &[AtomInfo]) -> Self {
+        let mut residues: Vec<SequenceResidue> = vec![];
+        let mut alpha_carbons = vec![];
+        let mut from_file = true;
+        for (sub_shape, atom) in atoms.iter().enumerate() {
+            let same_residue = residues.last().is_some_and(|residue| {
+                residue.number == atom.residue_number && residue.name == atom.residue_name
+            });
+            if !same_residue {
+                residues.push(SequenceResidue {
+                    number: atom.residue_number,
+                    name: atom.residue_name.clone(),
+                    code: one_letter_code(&atom.residue_name),
+                    secondary_structure: atom.secondary_structure.unwrap_or_default(),
+                    atoms: vec![],
+                });
+                alpha_carbons.push(None);
+                from_file &= atom.secondary_structure.is_some();
+            }
+            residues.last_mut().unwrap().atoms.push(sub_shape as u32);
+            if atom.name == "CA" {
+                *alpha_carbons.last_mut().unwrap() = Some(atom.position);
+            }
+        }
+        if !from_file {
+            for (residue, secondary_structure) in residues
+                .iter_mut()
+                .zip(estimate_secondary_structure(&alpha_carbons))
+            {
+                residue.secondary_structure = secondary_structure;
+            }
+        }
+        Self {
+            shape,
+            chain: atoms
+                .first()
+                .map_or_else(String::new, |atom| atom.chain.clone()),
+            residues,
+        }
+    }
+    /// Index of the residue that an atom of the chain belongs to
+    pub fn residue_of(&self, id: ObjectId) -> Option<usize> {
+        if id.shape != self.shape {
+            return None;
+        }
+        let sub_shape = id.sub_shape?;
+        self.residues
+            .iter()
+            .position(|residue| residue.atoms.contains(&sub_shape))
+    }
+    /// Every atom of the residues in a range of indices
+    pub fn object_ids(&self, residues: std::ops::RangeInclusive<usize>) -> Vec<ObjectId> {
+        let shape = self.shape;
+        self.residues
+            .get(residues)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|residue| residue.atoms.iter())
+            .map(|&sub_shape| ObjectId {
+                shape,
+                sub_shape: Some(sub_shape),
+            })
+            .collect()
+    }
+}
+
+/// Rough secondary structure from the alpha carbons of consecutive residues, for files without `HELIX` or `SHEET` records
+/// Helices and strands are told apart by the virtual torsion between four alpha carbons and how far apart they are
+/// Residues without an alpha carbon break up the chain
+pub fn estimate_secondary_structure(
+    alpha_carbons: &[Option<Point3<f32>>],
+) -> Vec<SecondaryStructure> {
+    let mut secondary_structure = vec![SecondaryStructure::Coil; alpha_carbons.len()];
+    for (i, window) in alpha_carbons.windows(4).enumerate() {
+        let [Some(a), Some(b), Some(c), Some(d)] = window else {
+            continue;
+        };
+        let torsion = dihedral(a, b, c, d);
+        let kind = if HELIX_TORSION.contains(&torsion) && HELIX_SPAN.contains(&distance(a, d)) {
+            SecondaryStructure::Helix
+        } else if torsion.abs() >= STRAND_TORSION && distance(a, c) >= STRAND_SPAN {
+            SecondaryStructure::Strand
+        } else {
+            continue;
+        };
+        for residue in secondary_structure[i..i + 4].iter_mut() {
+            // Helices take priority where they overlap strands
+            if *residue != SecondaryStructure::Helix {
+                *residue = kind;
+            }
+        }
+    }
+    secondary_structure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(
+        name: &str,
+        residue_name: &str,
+        residue_number: isize,
+        position: Point3<f32>,
+    ) -> AtomInfo {
+        AtomInfo::test("A", (residue_name, residue_number), name, position)
+    }
+
+    /// Alpha carbons of an ideal alpha helix, with 3.6 residues per turn and a rise of 1.5 Å
+    fn helix(count: usize) -> Vec<Option<Point3<f32>>> {
+        (0..count)
+            .map(|i| {
+                let angle = (i as f32 * 100.0).to_radians();
+                Some(Point3::new(
+                    2.3 * angle.cos(),
+                    2.3 * angle.sin(),
+                    1.5 * i as f32,
+                ))
+            })
+            .collect()
+    }
+
+    /// Alpha carbons of a strand, zigzagging with 3.3 Å between residues
+    fn strand(count: usize) -> Vec<Option<Point3<f32>>> {
+        (0..count)
+            .map(|i| Some(Point3::new(3.3 * i as f32, (i % 2) as f32, 0.0)))
+            .collect()
+    }
+
+    #[test]
+    fn test_one_letter_code() {
+        assert_eq!(one_letter_code("GLY"), 'G');
+        assert_eq!(one_letter_code("mse"), 'M');
+        assert_eq!(one_letter_code("HOH"), 'X');
+    }
+
+    #[test]
+    fn test_estimate_secondary_structure() {
+        use SecondaryStructure::*;
+        assert_eq!(estimate_secondary_structure(&helix(6)), vec![Helix; 6]);
+        assert_eq!(estimate_secondary_structure(&strand(5)), vec![Strand; 5]);
+        // Too short to tell
+        assert_eq!(estimate_secondary_structure(&helix(3)), vec![Coil; 3]);
+        let mut broken = helix(6);
+        broken[3] = None;
+        assert_eq!(estimate_secondary_structure(&broken), vec![Coil; 6]);
+    }
+
+    #[test]
+    fn test_chain_sequence() {
+        let positions = helix(4);
+        let atoms: Vec<AtomInfo> = ["ALA", "GLY", "TRP", "LYS"]
+            .iter()
+            .zip(positions)
+            .enumerate()
+            .flat_map(|(i, (name, position))| {
+                let number = i as isize + 10;
+                [
+                    atom("N", name, number, Point3::origin()),
+                    atom("CA", name, number, position.unwrap()),
+                ]
+            })
+            .collect();
+        let sequence = ChainSequence::from_atoms(2, &atoms);
+        assert_eq!(sequence.chain, "A");
+        let codes: String = sequence.residues.iter().map(|r| r.code).collect();
+        assert_eq!(codes, "AGWK");
+        assert!(sequence
+            .residues
+            .iter()
+            .all(|r| r.secondary_structure == SecondaryStructure::Helix));
+
+        let id = |shape, sub_shape| ObjectId {
+            shape,
+            sub_shape: Some(sub_shape),
+        };
+        assert_eq!(sequence.residue_of(id(2, 3)), Some(1));
+        assert_eq!(sequence.residue_of(id(1, 3)), None);
+        assert_eq!(
+            sequence.object_ids(1..=2),
+            vec![id(2, 2), id(2, 3), id(2, 4), id(2, 5)]
+        );
+        assert_eq!(sequence.object_ids(3..=9), vec![]);
+    }
+}
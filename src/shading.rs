@@ -0,0 +1,458 @@
+//! Shading effects applied on top of the lighting, which help show the shape of complicated surfaces.
+
+use crate::{scene::Scene, surface::ValidShape};
+use nalgebra::{Perspective3, Point3, Unit, Vector3};
+use parry3d::query::{Ray, RayCast};
+
+/// Angle between successive directions in the spiral used to spread samples over the hemisphere
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// Estimate of how much ambient light reaches each point, by casting rays over the hemisphere around the normal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    /// Number of rays cast per hit
+    pub samples: usize,
+    /// How far rays are cast, as a fraction of the depth of the scene
+    pub radius: f32,
+    /// How much fully occluded points are darkened, in the range `0.0..=1.0`
+    pub strength: f32,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            samples: 12,
+            radius: 0.1,
+            strength: 0.8,
+        }
+    }
+}
+
+impl AmbientOcclusion {
+    /// Factor multiplying the intensity of a point, where `1.0` means no occlusion
+    /// `rotation` turns the sample pattern, so that neighbouring pixels use different directions
+    pub fn factor<S: RayCast + ValidShape>(
+        &self,
+        scene: &Scene<S>,
+        point: &Point3<f32>,
+        normal: &Unit<Vector3<f32>>,
+        rotation: f32,
+    ) -> f32 {
+        if self.samples == 0 {
+            return 1.0;
+        }
+        let projection = &scene.scene_projection.perspective;
+        let max_toi = self.radius * (projection.zfar() - projection.znear());
+        // Start just above the surface to avoid hitting it straight away
+        let origin = point + normal.into_inner() * (max_toi * 1e-3);
+
+        let occluded = hemisphere_directions(normal, self.samples, rotation)
+            .filter(|dir| scene.cast_ray(&Ray::new(origin, *dir), max_toi).is_some())
+            .count();
+        1.0 - self.strength * occluded as f32 / self.samples as f32
+    }
+}
+
+/// Fog which darkens points further away, so that the front of the scene stands out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthCueing {
+    /// How much points on the far plane are darkened, in the range `0.0..=1.0`
+    pub strength: f32,
+}
+
+impl Default for DepthCueing {
+    fn default() -> Self {
+        Self { strength: 0.6 }
+    }
+}
+
+impl DepthCueing {
+    /// Factor multiplying the intensity of a point at a view-space depth
+    /// Falls off linearly from the near plane to the far plane
+    pub fn factor(&self, depth: f32, projection: &Perspective3<f32>) -> f32 {
+        let range = (projection.zfar() - projection.znear()).max(f32::EPSILON);
+        let fraction = ((depth - projection.znear()) / range).clamp(0.0, 1.0);
+        1.0 - self.strength * fraction
+    }
+}
+
+/// Shadows cast by directional lights, found by casting rays from each hit towards the light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shadows {
+    /// Number of rays cast towards each light, where a single ray gives hard shadows
+    pub samples: usize,
+    /// Half-angle in radians of the cone of jittered rays, which blurs the edges of soft shadows
+    pub spread: f32,
+}
+
+impl Shadows {
+    /// Single ray towards each light, giving sharp edges
+    pub fn hard() -> Self {
+        Self {
+            samples: 1,
+            spread: 0.0,
+        }
+    }
+    /// Several jittered rays towards each light, as if each light had some size
+    pub fn soft() -> Self {
+        Self {
+            samples: 8,
+            spread: 0.1,
+        }
+    }
+    /// Fraction of the rays towards a light which aren't blocked, where `1.0` means fully lit
+    /// `light_dir` points towards the light, which is `distance` away, and `rotation` turns the jitter pattern as for ambient occlusion
+    pub fn visibility<S: RayCast + ValidShape>(
+        &self,
+        scene: &Scene<S>,
+        point: &Point3<f32>,
+        normal: &Unit<Vector3<f32>>,
+        light_dir: &Unit<Vector3<f32>>,
+        distance: f32,
+        rotation: f32,
+    ) -> f32 {
+        if self.samples == 0 {
+            return 1.0;
+        }
+        let projection = &scene.scene_projection.perspective;
+        // Start just above the surface to avoid shadowing itself
+        let offset = (projection.zfar() - projection.znear()) * 1e-4;
+        let origin = point + normal.into_inner() * offset;
+
+        let unblocked = cone_directions(light_dir, self.samples, self.spread, rotation)
+            .filter(|dir| {
+                scene
+                    .cast_ray(&Ray::new(origin, *dir), distance - offset)
+                    .is_none()
+            })
+            .count();
+        unblocked as f32 / self.samples as f32
+    }
+}
+
+/// Cartoon-style lighting, where the intensity is rounded into a few flat bands
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toon {
+    /// Number of distinct intensities, at least two
+    pub bands: usize,
+}
+
+impl Default for Toon {
+    fn default() -> Self {
+        Self { bands: 3 }
+    }
+}
+
+impl Toon {
+    /// Round an intensity in the range `0.0..=1.0` to the middle of its band
+    pub fn quantise(&self, intensity: f32) -> f32 {
+        let bands = self.bands.max(2) as f32;
+        let band = (intensity.clamp(0.0, 1.0) * bands).floor().min(bands - 1.0);
+        (band + 0.5) / bands
+    }
+}
+
+/// Dark lines drawn around the silhouettes of shapes, where neighbouring pixels show different shapes or jump in depth
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outlines {
+    /// Smallest jump in depth between neighbouring pixels that counts as an edge, as a fraction of the nearer depth
+    pub depth_threshold: f32,
+    /// Intensity of outline pixels
+    pub intensity: f32,
+}
+
+impl Default for Outlines {
+    fn default() -> Self {
+        Self {
+            depth_threshold: 0.05,
+            intensity: 0.0,
+        }
+    }
+}
+
+impl Outlines {
+    /// Find which pixels lie on an outline, given the shape and depth of each pixel in a row-major buffer
+    /// Only the nearer pixel of each pair across an edge is marked, so outlines are a single pixel wide and hug the front shape
+    /// Shapes can be anything that tells objects apart, such as the index of a shape or a whole `ObjectId`
+    pub fn edges<T: PartialEq>(
+        &self,
+        shapes: &[Option<T>],
+        depths: &[f32],
+        width: usize,
+    ) -> Vec<bool> {
+        let height = shapes.len() / width.max(1);
+        // Called with `a` nearer than `b`
+        let differs = |a: usize, b: usize| {
+            shapes[a] != shapes[b] || depths[b] - depths[a] > self.depth_threshold * depths[a]
+        };
+        (0..shapes.len())
+            .map(|i| {
+                if shapes[i].is_none() {
+                    return false;
+                }
+                let (x, y) = (i % width, i / width);
+                let neighbours = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < width).then(|| i + 1),
+                    (y > 0).then(|| i - width),
+                    (y + 1 < height).then(|| i + width),
+                ];
+                neighbours
+                    .into_iter()
+                    .flatten()
+                    .any(|n| depths[i] <= depths[n] && differs(i, n))
+            })
+            .collect()
+    }
+}
+
+/// Optional effects applied when shading each hit, where `None` disables an effect
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Shading {
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    pub depth_cueing: Option<DepthCueing>,
+    pub shadows: Option<Shadows>,
+    pub toon: Option<Toon>,
+    pub outlines: Option<Outlines>,
+}
+
+impl Shading {
+    /// Turn ambient occlusion on or off, using the default settings when turned on
+    pub fn toggle_ambient_occlusion(&mut self) {
+        self.ambient_occlusion = match self.ambient_occlusion {
+            Some(_) => None,
+            None => Some(AmbientOcclusion::default()),
+        };
+    }
+    /// Turn depth cueing on or off, using the default settings when turned on
+    pub fn toggle_depth_cueing(&mut self) {
+        self.depth_cueing = match self.depth_cueing {
+            Some(_) => None,
+            None => Some(DepthCueing::default()),
+        };
+    }
+    /// Turn toon shading on or off, using the default settings when turned on
+    pub fn toggle_toon(&mut self) {
+        self.toon = match self.toon {
+            Some(_) => None,
+            None => Some(Toon::default()),
+        };
+    }
+    /// Turn outlines on or off, using the default settings when turned on
+    pub fn toggle_outlines(&mut self) {
+        self.outlines = match self.outlines {
+            Some(_) => None,
+            None => Some(Outlines::default()),
+        };
+    }
+    /// Step through no shadows, hard shadows and soft shadows
+    pub fn cycle_shadows(&mut self) {
+        self.shadows = match self.shadows {
+            None => Some(Shadows::hard()),
+            Some(shadows) if shadows.samples <= 1 => Some(Shadows::soft()),
+            Some(_) => None,
+        };
+    }
+}
+
+/// Cosine-weighted directions spread over the hemisphere around the normal in a spiral
+fn hemisphere_directions(
+    normal: &Unit<Vector3<f32>>,
+    samples: usize,
+    rotation: f32,
+) -> impl Iterator<Item = Vector3<f32>> {
+    let (tangent, bitangent) = tangent_frame(normal);
+    let normal = normal.into_inner();
+    (0..samples).map(move |i| {
+        let u = (i as f32 + 0.5) / samples as f32;
+        let r = u.sqrt();
+        let (sin, cos) = (i as f32 * GOLDEN_ANGLE + rotation).sin_cos();
+        tangent * (r * cos) + bitangent * (r * sin) + normal * (1.0 - u).sqrt()
+    })
+}
+
+/// Directions spread evenly in a spiral over a cone around an axis, with half-angle `spread`
+/// A single sample points straight along the axis
+fn cone_directions(
+    axis: &Unit<Vector3<f32>>,
+    samples: usize,
+    spread: f32,
+    rotation: f32,
+) -> impl Iterator<Item = Vector3<f32>> {
+    let (tangent, bitangent) = tangent_frame(axis);
+    let axis = axis.into_inner();
+    let radius = spread.tan();
+    (0..samples).map(move |i| {
+        let r = if samples > 1 {
+            radius * (i as f32 / (samples - 1) as f32).sqrt()
+        } else {
+            0.0
+        };
+        let (sin, cos) = (i as f32 * GOLDEN_ANGLE + rotation).sin_cos();
+        (axis + tangent * (r * cos) + bitangent * (r * sin)).normalize()
+    })
+}
+
+/// Pair of unit vectors perpendicular to each other and to `normal`
+fn tangent_frame(normal: &Unit<Vector3<f32>>) -> (Vector3<f32>, Vector3<f32>) {
+    // Any vector not parallel to the normal will do for building the tangent frame
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = normal.cross(&helper).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::ColoredShape;
+    use nalgebra::Isometry3;
+    use parry3d::shape::Ball;
+    use ratatui::style::Color;
+
+    #[test]
+    fn test_hemisphere_directions() {
+        let normal = Unit::new_normalize(Vector3::new(0.3, -1.0, 0.2));
+        for dir in hemisphere_directions(&normal, 16, 0.5) {
+            assert!((dir.norm() - 1.0).abs() < 1e-5);
+            assert!(dir.dot(&normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_depth_cueing() {
+        let projection = Perspective3::new(1.0, 1.0, 10.0, 20.0);
+        let cue = DepthCueing { strength: 0.5 };
+        assert_eq!(cue.factor(10.0, &projection), 1.0);
+        assert_eq!(cue.factor(15.0, &projection), 0.75);
+        assert_eq!(cue.factor(30.0, &projection), 0.5);
+    }
+
+    #[test]
+    /// A point in the crevice between two touching balls is darker than one facing outwards
+    fn test_ambient_occlusion() {
+        let mut scene = Scene::<Ball>::default();
+        scene.add_shapes(
+            [-1.0, 1.0]
+                .iter()
+                .map(|x| ColoredShape {
+                    shape: Ball::new(1.0),
+                    world_transform: Isometry3::translation(*x, 0.0, 0.0),
+                    color: Color::Red,
+                })
+                .collect(),
+        );
+        let ao = AmbientOcclusion {
+            samples: 32,
+            radius: 0.5,
+            strength: 1.0,
+        };
+
+        let outside = ao.factor(
+            &scene,
+            &Point3::new(-2.0, 0.0, 0.0),
+            &-Vector3::x_axis(),
+            0.0,
+        );
+        assert!((outside - 1.0).abs() < 1e-6);
+
+        // Point on the first ball facing towards the second
+        let crevice_normal = Unit::new_normalize(Vector3::new(1.0, 1.0, 0.0));
+        let crevice = Point3::new(-1.0, 0.0, 0.0) + crevice_normal.into_inner();
+        let inside = ao.factor(&scene, &crevice, &crevice_normal, 0.0);
+        assert!(inside < outside);
+    }
+
+    #[test]
+    fn test_toon() {
+        let toon = Toon { bands: 4 };
+        assert_eq!(toon.quantise(0.0), 0.125);
+        assert_eq!(toon.quantise(0.3), 0.375);
+        assert_eq!(toon.quantise(1.0), 0.875);
+        assert_eq!(toon.quantise(2.0), 0.875);
+    }
+
+    #[test]
+    fn test_outlines() {
+        // Two shapes side by side in front of the background, with a depth jump inside the second
+        #[rustfmt::skip]
+        let shapes = [
+            None, Some(0), Some(1), Some(1), Some(1),
+        ];
+        let depths = [f32::MAX, 5.0, 5.0, 5.0, 9.0];
+        let edges = Outlines::default().edges(&shapes, &depths, 5);
+        assert_eq!(edges, vec![false, true, true, true, false]);
+
+        // Pixels surrounded by the same shape at the same depth aren't edges
+        let edges = Outlines::default().edges(&[Some(0); 9], &[1.0; 9], 3);
+        assert!(edges.iter().all(|e| !e));
+    }
+
+    #[test]
+    fn test_cone_directions() {
+        let axis = Unit::new_normalize(Vector3::new(1.0, 2.0, -1.0));
+        let spread = 0.2;
+        let directions: Vec<_> = cone_directions(&axis, 8, spread, 1.0).collect();
+        assert!((directions[0] - axis.into_inner()).norm() < 1e-6);
+        for dir in directions {
+            assert!((dir.norm() - 1.0).abs() < 1e-5);
+            assert!(dir.dot(&axis).acos() <= spread + 1e-4);
+        }
+    }
+
+    #[test]
+    /// The second ball shadows the side of the first facing it, but not the side facing away
+    fn test_shadows() {
+        let mut scene = Scene::<Ball>::default();
+        scene.add_shapes(
+            [0.0, 3.0]
+                .iter()
+                .map(|x| ColoredShape {
+                    shape: Ball::new(1.0),
+                    world_transform: Isometry3::translation(*x, 0.0, 0.0),
+                    color: Color::Red,
+                })
+                .collect(),
+        );
+        let light = Vector3::x_axis();
+        let hard = Shadows::hard();
+        let lit = hard.visibility(
+            &scene,
+            &Point3::new(4.0, 0.0, 0.0),
+            &Vector3::x_axis(),
+            &light,
+            f32::MAX,
+            0.0,
+        );
+        assert_eq!(lit, 1.0);
+        let shadowed = hard.visibility(
+            &scene,
+            &Point3::new(1.0, 0.0, 0.0),
+            &Vector3::x_axis(),
+            &light,
+            f32::MAX,
+            0.0,
+        );
+        assert_eq!(shadowed, 0.0);
+
+        // Near the edge of the shadow only some of the jittered rays are blocked
+        let soft = Shadows {
+            samples: 16,
+            spread: 0.3,
+        };
+        let normal = Unit::new_normalize(Vector3::new(1.0, 0.0, 1.0));
+        let edge = soft.visibility(
+            &scene,
+            &Point3::new(0.0, 0.0, 1.0),
+            &normal,
+            &light,
+            f32::MAX,
+            0.0,
+        );
+        assert!(edge > 0.0 && edge < 1.0, "visibility {}", edge);
+    }
+}
@@ -0,0 +1,207 @@
+//! Supersampled anti-aliasing, casting several rays per pixel and combining the results.
+//!
+//! At terminal resolutions a single ray per pixel makes silhouettes very jagged.
+
+use crate::{rasterizer::ColoredPixel, scene::ObjectId};
+use ratatui::style::Color;
+
+/// Largest number of samples along each axis
+pub const MAX_SAMPLES: usize = 4;
+
+/// Layout of the samples within a pixel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplePattern {
+    /// Samples on a regular grid aligned with the pixel
+    #[default]
+    Grid,
+    /// Grid rotated so that no two samples share a row or column, which is better for near-vertical and near-horizontal edges
+    RotatedGrid,
+}
+
+/// Supersampling applied when casting rays, using `samples * samples` rays per pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Supersampling {
+    pub pattern: SamplePattern,
+    samples: usize,
+}
+
+impl Default for Supersampling {
+    /// No supersampling, a single ray through the centre of the pixel
+    fn default() -> Self {
+        Self {
+            pattern: SamplePattern::default(),
+            samples: 1,
+        }
+    }
+}
+
+impl Supersampling {
+    /// Number of samples along each axis is clamped to `1..=MAX_SAMPLES`
+    pub fn new(pattern: SamplePattern, samples: usize) -> Self {
+        Self {
+            pattern,
+            samples: samples.clamp(1, MAX_SAMPLES),
+        }
+    }
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+    /// Increase the number of samples along each axis, wrapping back to one
+    pub fn next_samples(self) -> Self {
+        Self::new(self.pattern, self.samples % MAX_SAMPLES + 1)
+    }
+    /// Swap between sample patterns
+    pub fn next_pattern(self) -> Self {
+        let pattern = match self.pattern {
+            SamplePattern::Grid => SamplePattern::RotatedGrid,
+            SamplePattern::RotatedGrid => SamplePattern::Grid,
+        };
+        Self::new(pattern, self.samples)
+    }
+    /// Offsets of each sample from the centre of the pixel, in units of pixels
+    /// All offsets lie within the pixel, in the range `-0.5..0.5`
+    pub fn offsets(&self) -> Vec<(f32, f32)> {
+        let n = self.samples;
+        let grid = (0..n).flat_map(move |j| {
+            (0..n).map(move |i| {
+                (
+                    (i as f32 + 0.5) / n as f32 - 0.5,
+                    (j as f32 + 0.5) / n as f32 - 0.5,
+                )
+            })
+        });
+        match self.pattern {
+            SamplePattern::Grid => grid.collect(),
+            SamplePattern::RotatedGrid => {
+                // Rotating by `atan(1/2)` is the classic choice for a 2x2 rotated grid
+                let (sin, cos) = 0.5f32.atan().sin_cos();
+                // Shrink so that the corners of the rotated grid still lie inside the pixel
+                let scale = 1.0 / (sin + cos);
+                grid.map(|(x, y)| (scale * (x * cos - y * sin), scale * (x * sin + y * cos)))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// What a single ray hit
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub pixel: ColoredPixel,
+    /// View-space depth of the hit
+    pub depth: f32,
+    /// Shape, and part of the shape, that was hit
+    pub id: ObjectId,
+}
+
+/// Combine the samples of a single pixel, where each sample is `None` if it missed every shape
+/// The intensity is averaged over every sample, with misses counting as the background intensity, so that edges fade out.
+/// The colour is whichever appears most amongst the hits, and the depth and object are from the nearest hit.
+/// Returns `None` if every sample missed.
+pub fn resolve_samples(samples: &[Option<Sample>], background: ColoredPixel) -> Option<Sample> {
+    let hits: Vec<&Sample> = samples.iter().flatten().collect();
+    let nearest = hits
+        .iter()
+        .copied()
+        .reduce(|a, b| if b.depth < a.depth { b } else { a })?;
+
+    let intensity = samples
+        .iter()
+        .map(|s| s.map_or(background.intensity, |s| s.pixel.intensity))
+        .sum::<f32>()
+        / samples.len() as f32;
+
+    let mut counts: Vec<(Color, usize)> = Vec::new();
+    for Sample { pixel, .. } in hits.iter() {
+        match counts.iter_mut().find(|(c, _)| *c == pixel.color) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((pixel.color, 1)),
+        }
+    }
+    // Ties go to the colour seen first
+    let color = counts
+        .iter()
+        .fold(None, |best: Option<&(Color, usize)>, c| match best {
+            Some(b) if b.1 >= c.1 => Some(b),
+            _ => Some(c),
+        })
+        .map(|(c, _)| *c)
+        .unwrap();
+
+    Some(Sample {
+        pixel: ColoredPixel { intensity, color },
+        ..*nearest
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_inside_pixel() {
+        for pattern in [SamplePattern::Grid, SamplePattern::RotatedGrid] {
+            for samples in 1..=MAX_SAMPLES {
+                let offsets = Supersampling::new(pattern, samples).offsets();
+                assert_eq!(offsets.len(), samples * samples);
+                assert!(offsets.iter().all(|(x, y)| x.abs() < 0.5 && y.abs() < 0.5));
+            }
+        }
+        assert_eq!(Supersampling::default().offsets(), vec![(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_rotated_grid_distinct_columns() {
+        let offsets = Supersampling::new(SamplePattern::RotatedGrid, 2).offsets();
+        for (i, a) in offsets.iter().enumerate() {
+            for b in offsets[i + 1..].iter() {
+                assert!((a.0 - b.0).abs() > 1e-3);
+                assert!((a.1 - b.1).abs() > 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_samples() {
+        let background = ColoredPixel {
+            intensity: 1.0,
+            color: Color::Reset,
+        };
+        let red = ColoredPixel {
+            intensity: 0.0,
+            color: Color::Red,
+        };
+        let blue = ColoredPixel {
+            intensity: 0.0,
+            color: Color::Blue,
+        };
+
+        let sample = |pixel, depth, shape| {
+            Some(Sample {
+                pixel,
+                depth,
+                id: ObjectId {
+                    shape,
+                    sub_shape: None,
+                },
+            })
+        };
+
+        assert!(resolve_samples(&[None, None], background).is_none());
+
+        let resolved = resolve_samples(
+            &[
+                sample(red, 2.0, 0),
+                sample(blue, 1.0, 1),
+                sample(red, 3.0, 0),
+                None,
+            ],
+            background,
+        )
+        .unwrap();
+        assert_eq!(resolved.pixel.color, Color::Red);
+        assert_eq!(resolved.pixel.intensity, 0.25);
+        assert_eq!(resolved.depth, 1.0);
+        assert_eq!(resolved.id.shape, 1);
+    }
+}
@@ -1,6 +1,12 @@
-use nalgebra::Point3;
+use nalgebra::{Isometry3, Point3};
+use parry3d::bounding_volume::{Aabb, SimdAabb};
 use parry3d::mass_properties::MassProperties;
-use parry3d::shape::{Ball, Compound, Shape, TriMesh};
+use parry3d::math::{SimdBool, SimdReal, SIMD_WIDTH};
+use parry3d::partitioning::{SimdBestFirstVisitStatus, SimdBestFirstVisitor};
+use parry3d::query::details::RayCompositeShapeToiBestFirstVisitor;
+use parry3d::query::{Ray, RayCast, RayIntersection, SimdRay};
+use parry3d::shape::{Ball, Compound, FeatureId, Shape, TriMesh};
+use parry3d::simba::simd::{SimdBool as _, SimdPartialOrd, SimdValue};
 use tobj::Mesh;
 
 const DEFAULT_DENSITY: f32 = 1.0;
@@ -21,33 +27,165 @@ impl ToTriMesh for Mesh {
     }
 }
 
-/// Trait for something whose center can be calculated
+/// Trait for something whose center and bounds can be calculated
 pub trait ValidShape {
     fn mass_properties_default(&self) -> Option<MassProperties>;
+    /// Bounding box once the shape is placed in the world
+    fn world_aabb(&self, position: &Isometry3<f32>) -> Aabb;
     fn get_com(&self) -> Point3<f32> {
         match self.mass_properties_default() {
             Some(mp) => mp.local_com,
             None => Point3::origin(),
         }
     }
+    /// Index of the part of the shape hit by a ray, such as a triangle of a mesh or an atom of a molecule
+    /// The ray is in the local space of the shape, and `None` means the shape has no parts
+    fn sub_shape(&self, _local_ray: &Ray, _intersection: &RayIntersection) -> Option<u32> {
+        None
+    }
+    /// Cast a ray in world space against the parts of the shape for which `visible` is true, also returning the part
+    /// that was hit, see `sub_shape`
+    /// Shapes that can't leave out parts hit every part
+    fn cast_ray_to_visible_part(
+        &self,
+        position: &Isometry3<f32>,
+        ray: &Ray,
+        max_toi: f32,
+        solid: bool,
+        _visible: &dyn Fn(u32) -> bool,
+    ) -> Option<(RayIntersection, Option<u32>)>
+    where
+        Self: RayCast,
+    {
+        let intersection = self.cast_ray_and_get_normal(position, ray, max_toi, solid)?;
+        let local_ray = ray.inverse_transform_by(position);
+        Some((intersection, self.sub_shape(&local_ray, &intersection)))
+    }
 }
 
 impl ValidShape for TriMesh {
     fn mass_properties_default(&self) -> Option<MassProperties> {
         Some(self.mass_properties(DEFAULT_DENSITY))
     }
+    fn world_aabb(&self, position: &Isometry3<f32>) -> Aabb {
+        self.compute_aabb(position)
+    }
+    /// Triangles hit from behind are numbered after all the others, so wrap back around
+    fn sub_shape(&self, _local_ray: &Ray, intersection: &RayIntersection) -> Option<u32> {
+        match intersection.feature {
+            FeatureId::Face(face) => Some(face % self.indices().len().max(1) as u32),
+            _ => None,
+        }
+    }
 }
 
 impl ValidShape for Compound {
     fn mass_properties_default(&self) -> Option<MassProperties> {
         Some(self.mass_properties(DEFAULT_DENSITY))
     }
+    fn world_aabb(&self, position: &Isometry3<f32>) -> Aabb {
+        self.compute_aabb(position)
+    }
+    /// The feature of a hit on a compound belongs to the part that was hit, so says nothing about which part that was
+    /// Instead the ray is cast again, only as far as the known hit, to find the index of the part
+    fn sub_shape(&self, local_ray: &Ray, intersection: &RayIntersection) -> Option<u32> {
+        let max_toi = intersection.toi * (1.0 + f32::EPSILON.sqrt()) + f32::EPSILON;
+        let mut visitor = RayCompositeShapeToiBestFirstVisitor::new(self, local_ray, max_toi, true);
+        self.qbvh()
+            .traverse_best_first(&mut visitor)
+            .map(|(_, (part, _))| part)
+    }
+    fn cast_ray_to_visible_part(
+        &self,
+        position: &Isometry3<f32>,
+        ray: &Ray,
+        max_toi: f32,
+        solid: bool,
+        visible: &dyn Fn(u32) -> bool,
+    ) -> Option<(RayIntersection, Option<u32>)> {
+        let local_ray = ray.inverse_transform_by(position);
+        let mut visitor = VisiblePartsVisitor {
+            compound: self,
+            ray: &local_ray,
+            simd_ray: SimdRay::splat(local_ray),
+            max_toi,
+            solid,
+            visible,
+        };
+        self.qbvh()
+            .traverse_best_first(&mut visitor)
+            .map(|(_, (part, intersection))| (intersection.transform_by(position), Some(part)))
+    }
+}
+
+/// Best-first search for the nearest hit on the visible parts of a compound, with the ray in the local space of the
+/// compound
+/// Follows the search `parry` uses for composite shapes, which can't skip parts
+struct VisiblePartsVisitor<'a> {
+    compound: &'a Compound,
+    ray: &'a Ray,
+    simd_ray: SimdRay,
+    max_toi: f32,
+    solid: bool,
+    visible: &'a dyn Fn(u32) -> bool,
+}
+
+impl SimdBestFirstVisitor<u32, SimdAabb> for VisiblePartsVisitor<'_> {
+    type Result = (u32, RayIntersection);
+
+    fn visit(
+        &mut self,
+        best_cost_so_far: f32,
+        bv: &SimdAabb,
+        data: Option<[Option<&u32>; SIMD_WIDTH]>,
+    ) -> SimdBestFirstVisitStatus<Self::Result> {
+        let (hit, toi) = bv.cast_local_ray(&self.simd_ray, SimdReal::splat(self.max_toi));
+        let Some(data) = data else {
+            return SimdBestFirstVisitStatus::MaybeContinue {
+                weights: toi,
+                mask: hit,
+                results: [None; SIMD_WIDTH],
+            };
+        };
+
+        let mut weights = [0.0; SIMD_WIDTH];
+        let mut mask = [false; SIMD_WIDTH];
+        let mut results = [None; SIMD_WIDTH];
+        let bitmask = (hit & toi.simd_lt(SimdReal::splat(best_cost_so_far))).bitmask();
+        for (lane, part) in data.iter().enumerate() {
+            let Some(&part) = part else {
+                continue;
+            };
+            if bitmask & (1 << lane) == 0 || !(self.visible)(part) {
+                continue;
+            }
+            let (part_position, part_shape) = &self.compound.shapes()[part as usize];
+            if let Some(intersection) = part_shape.cast_ray_and_get_normal(
+                part_position,
+                self.ray,
+                self.max_toi,
+                self.solid,
+            ) {
+                weights[lane] = intersection.toi;
+                mask[lane] = true;
+                results[lane] = Some((part, intersection));
+            }
+        }
+        SimdBestFirstVisitStatus::MaybeContinue {
+            weights: SimdReal::from(weights),
+            mask: SimdBool::from(mask),
+            results,
+        }
+    }
 }
 
 impl ValidShape for Ball {
     fn mass_properties_default(&self) -> Option<MassProperties> {
         Some(self.mass_properties(DEFAULT_DENSITY))
     }
+    fn world_aabb(&self, position: &Isometry3<f32>) -> Aabb {
+        self.compute_aabb(position)
+    }
 }
 
 #[cfg(test)]
@@ -56,12 +194,40 @@ mod tests {
     use crate::read::get_meshes_from_obj;
     use std::path::Path;
 
+    #[test]
+    fn test_visible_parts() {
+        use parry3d::shape::SharedShape;
+        use std::sync::Arc;
+
+        // A row of balls along the ray
+        let balls = [0.0, 10.0, 20.0]
+            .map(|z| {
+                (
+                    Isometry3::translation(0.0, 0.0, z),
+                    SharedShape(Arc::new(Ball::new(1.0))),
+                )
+            })
+            .to_vec();
+        let compound = Compound::new(balls);
+        let position = Isometry3::translation(0.0, 0.0, 5.0);
+        let ray = Ray::new(Point3::origin(), nalgebra::Vector3::z());
+        let cast = |visible: &dyn Fn(u32) -> bool| {
+            compound
+                .cast_ray_to_visible_part(&position, &ray, f32::MAX, true, visible)
+                .map(|(intersection, part)| (intersection.toi, part))
+        };
+        assert_eq!(cast(&|_| true), Some((4.0, Some(0))));
+        assert_eq!(cast(&|part| part != 0), Some((14.0, Some(1))));
+        assert_eq!(cast(&|part| part == 2), Some((24.0, Some(2))));
+        assert_eq!(cast(&|_| false), None);
+    }
+
     #[test]
     fn test_reading_and_conversion() {
         let test_obj = "./data/surface.obj";
         assert!(Path::new(test_obj).exists());
 
-        let meshes = get_meshes_from_obj(test_obj);
+        let meshes = get_meshes_from_obj(test_obj).unwrap();
         let tri_mesh = meshes[0].to_tri_mesh();
 
         assert!(!tri_mesh.indices().is_empty());
@@ -0,0 +1,152 @@
+//! Finding the size of terminal character cells in pixels, so that rendered shapes aren't stretched.
+
+use crate::scene::DEFAULT_CELL_ASPECT_RATIO;
+use crossterm::terminal::window_size;
+
+/// How long to wait for the terminal to respond to a query in milliseconds
+const QUERY_TIMEOUT_MS: i32 = 100;
+/// Give up on finding a response after reading this many bytes
+const MAX_RESPONSE_LENGTH: usize = 64;
+
+/// Size of a single character cell in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl CellSize {
+    /// The ratio of height to width
+    pub fn aspect_ratio(&self) -> f32 {
+        self.height as f32 / self.width as f32
+    }
+}
+
+/// Find the cell aspect ratio, preferring the override, then querying the terminal, then using the default
+/// Must be called after entering raw mode, but before reading any events
+/// Keys pressed while the terminal is queried are lost, see `query_csi_cell_size`
+pub fn cell_aspect_ratio(aspect_override: Option<f32>) -> f32 {
+    aspect_override
+        .filter(|aspect| aspect.is_finite() && *aspect > 0.0)
+        .or_else(|| query_cell_size().map(|cell_size| cell_size.aspect_ratio()))
+        .unwrap_or(DEFAULT_CELL_ASPECT_RATIO)
+}
+
+/// Query the terminal for the cell size
+/// Tries the pixel fields of `TIOCGWINSZ` first, since some terminals leave them as zero
+pub fn query_cell_size() -> Option<CellSize> {
+    cell_size_from_window_size().or_else(query_csi_cell_size)
+}
+
+fn cell_size_from_window_size() -> Option<CellSize> {
+    let size = window_size().ok()?;
+    if size.columns == 0 || size.rows == 0 {
+        return None;
+    }
+    let cell_size = CellSize {
+        width: size.width / size.columns,
+        height: size.height / size.rows,
+    };
+    (cell_size.width > 0 && cell_size.height > 0).then_some(cell_size)
+}
+
+/// Ask the terminal for the cell size with `CSI 16 t`, waiting a short time for the response
+/// Everything read while waiting is consumed, so keys pressed in that time are dropped rather than becoming events,
+/// since there is no way of handing them back to `crossterm`
+#[cfg(unix)]
+fn query_csi_cell_size() -> Option<CellSize> {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+
+    let mut out = std::io::stdout();
+    out.write_all(b"\x1b[16t").ok()?;
+    out.flush().ok()?;
+
+    let fd = std::io::stdin().as_raw_fd();
+    let mut response = Vec::new();
+    let mut buffer = [0u8; MAX_RESPONSE_LENGTH];
+    while response.len() < MAX_RESPONSE_LENGTH {
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: Polling a single valid `pollfd`
+        if unsafe { libc::poll(&mut poll_fd, 1, QUERY_TIMEOUT_MS) } <= 0 {
+            return None;
+        }
+        // Read from the file descriptor directly, since `Stdin` would buffer bytes meant for later events
+        // SAFETY: The buffer is valid for its whole length
+        let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if read <= 0 {
+            return None;
+        }
+        response.extend_from_slice(&buffer[..read as usize]);
+        if let Some(cell_size) = parse_cell_size_response(&response) {
+            return Some(cell_size);
+        }
+    }
+    None
+}
+
+#[cfg(not(unix))]
+fn query_csi_cell_size() -> Option<CellSize> {
+    None
+}
+
+/// Parse the response to `CSI 16 t`, which has the form `CSI 6 ; height ; width t`
+fn parse_cell_size_response(response: &[u8]) -> Option<CellSize> {
+    let prefix = b"\x1b[6;";
+    let start = response
+        .windows(prefix.len())
+        .position(|window| window == prefix)?
+        + prefix.len();
+    let length = response[start..].iter().position(|b| *b == b't')?;
+    let body = std::str::from_utf8(&response[start..start + length]).ok()?;
+    let (height, width) = body.split_once(';')?;
+    let cell_size = CellSize {
+        width: width.parse().ok()?,
+        height: height.parse().ok()?,
+    };
+    (cell_size.width > 0 && cell_size.height > 0).then_some(cell_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cell_size_response() {
+        assert_eq!(
+            parse_cell_size_response(b"\x1b[6;20;10t"),
+            Some(CellSize {
+                width: 10,
+                height: 20
+            })
+        );
+        // Keys pressed while waiting are skipped over, and so are lost
+        assert_eq!(
+            parse_cell_size_response(b"q\x1b[6;17;8t"),
+            Some(CellSize {
+                width: 8,
+                height: 17
+            })
+        );
+        assert_eq!(parse_cell_size_response(b"\x1b[6;20;10"), None);
+        assert_eq!(parse_cell_size_response(b"\x1b[6;0;10t"), None);
+        assert_eq!(parse_cell_size_response(b"\x1b[4;600;800t"), None);
+    }
+
+    #[test]
+    fn test_aspect_override() {
+        assert_eq!(cell_aspect_ratio(Some(2.5)), 2.5);
+        assert_eq!(
+            CellSize {
+                width: 8,
+                height: 16
+            }
+            .aspect_ratio(),
+            2.0
+        );
+    }
+}
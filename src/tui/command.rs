@@ -0,0 +1,556 @@
+//! Command line opened with `:`, for acting on the scene by typing commands such as `color chain A red`.
+//!
+//! Commands are parsed into the same actions as key presses, so the state of the app decides what they do. The command
+//! line keeps a history of commands run, and completes commands, selections, colours and paths with `<Tab>`.
+
+use crate::{
+    scene::Scene,
+    selection::{Selection, SelectionError, SELECTION_KEYWORDS},
+    surface::ValidShape,
+    tui::ui::NextAction,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use parry3d::query::RayCast;
+use ratatui::{
+    prelude::{Buffer, Rect, Style, Stylize},
+    style::Color,
+    text::{Line, Span},
+    widgets::Widget,
+};
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Every command, in the order they are completed
+pub const COMMANDS: [&str; 11] = [
+    "color", "help", "hide", "load", "q", "quit", "rep", "save", "set", "show", "zoom",
+];
+const COMMANDS_TAKING_SELECTIONS: [&str; 5] = ["color", "colour", "hide", "show", "zoom"];
+const COLOR_NAMES: [&str; 16] = [
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "gray",
+    "darkgray",
+    "black",
+    "lightred",
+    "lightgreen",
+    "lightyellow",
+    "lightblue",
+    "lightmagenta",
+    "lightcyan",
+];
+/// Ways of drawing atoms, which are the only ones completed
+const REPRESENTATIONS: [&str; 1] = ["spheres"];
+/// Ways of drawing atoms that are recognised but can't be drawn yet
+const PLANNED_REPRESENTATIONS: [&str; 2] = ["sticks", "cartoon"];
+const SETTINGS: [&str; 1] = ["fov"];
+/// Range of vertical fields of view allowed, in degrees
+const FOV_RANGE: std::ops::RangeInclusive<f32> = 1.0..=170.0;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CommandError {
+    #[error("Unknown command `{0}`.")]
+    UnknownCommand(String),
+    #[error("`{0}` needs {1}.")]
+    MissingArgument(&'static str, &'static str),
+    #[error("Unexpected `{0}`.")]
+    TrailingWords(String),
+    #[error("Unknown colour `{0}`.")]
+    UnknownColor(String),
+    #[error("Unknown representation `{0}`.")]
+    UnknownRepresentation(String),
+    #[error("Only spheres can be drawn so far, not {0}.")]
+    UnsupportedRepresentation(String),
+    #[error("Unknown setting `{0}`.")]
+    UnknownSetting(String),
+    #[error("Could not read a number from `{0}`.")]
+    InvalidNumber(String),
+    #[error("`{0}` must be between {1} and {2}.")]
+    OutOfRange(&'static str, f32, f32),
+    #[error(transparent)]
+    Selection(#[from] SelectionError),
+}
+
+/// Turn a command, without the leading `:`, into the action it stands for
+pub fn parse_command(line: &str) -> Result<NextAction, CommandError> {
+    let line = line.trim();
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    let no_more_words = |words: &[&str]| match words.first() {
+        Some(word) => Err(CommandError::TrailingWords(word.to_string())),
+        None => Ok(()),
+    };
+    match command {
+        "" => Ok(NextAction::Nothing),
+        "q" | "quit" => no_more_words(&words).map(|_| NextAction::Quit),
+        "help" => no_more_words(&words).map(|_| NextAction::Help),
+        // Paths are taken as the rest of the line, so they may contain spaces
+        "load" if rest.is_empty() => Err(CommandError::MissingArgument("load", "a path")),
+        "load" => Ok(NextAction::Load {
+            path: rest.to_string(),
+        }),
+        "save" if rest.is_empty() => Ok(NextAction::Save),
+        "save" => Ok(NextAction::SaveAs {
+            path: rest.to_string(),
+        }),
+        "color" | "colour" => {
+            let (color, selection) = words.split_last().ok_or(CommandError::MissingArgument(
+                "color",
+                "a selection and a colour",
+            ))?;
+            let color = Color::from_str(color)
+                .map_err(|_| CommandError::UnknownColor(color.to_string()))?;
+            Ok(NextAction::Color {
+                selection: Selection::parse(selection)?,
+                color,
+            })
+        }
+        "hide" | "show" => Ok(NextAction::SetVisible {
+            selection: Selection::parse(&words)?,
+            visible: command == "show",
+        }),
+        "zoom" if words.is_empty() => Ok(NextAction::ZoomTo {
+            selection: Selection::All,
+        }),
+        "zoom" => Ok(NextAction::ZoomTo {
+            selection: Selection::parse(&words)?,
+        }),
+        "rep" => match words[..] {
+            [] => Err(CommandError::MissingArgument("rep", "a representation")),
+            ["spheres"] => Ok(NextAction::Nothing),
+            [representation] if PLANNED_REPRESENTATIONS.contains(&representation) => Err(
+                CommandError::UnsupportedRepresentation(representation.to_string()),
+            ),
+            [representation] => Err(CommandError::UnknownRepresentation(
+                representation.to_string(),
+            )),
+            [_, word, ..] => Err(CommandError::TrailingWords(word.to_string())),
+        },
+        "set" => match words[..] {
+            [] | [_] => Err(CommandError::MissingArgument(
+                "set",
+                "a setting and a value",
+            )),
+            ["fov", value] => {
+                let degrees: f32 = value
+                    .parse()
+                    .map_err(|_| CommandError::InvalidNumber(value.to_string()))?;
+                if !FOV_RANGE.contains(&degrees) {
+                    return Err(CommandError::OutOfRange(
+                        "fov",
+                        *FOV_RANGE.start(),
+                        *FOV_RANGE.end(),
+                    ));
+                }
+                Ok(NextAction::SetFieldOfView { degrees })
+            }
+            [setting, _] => Err(CommandError::UnknownSetting(setting.to_string())),
+            [_, _, word, ..] => Err(CommandError::TrailingWords(word.to_string())),
+        },
+        _ => Err(CommandError::UnknownCommand(command.to_string())),
+    }
+}
+
+/// Ways of finishing the last word of a partly typed command, along with where that word starts
+pub fn completions<S: RayCast + ValidShape>(input: &str, scene: &Scene<S>) -> (usize, Vec<String>) {
+    let start = input.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &input[start..];
+    let before: Vec<&str> = input[..start].split_whitespace().collect();
+    let owned = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    let candidates: Vec<String> = match before[..] {
+        [] => owned(&COMMANDS),
+        ["load" | "save", ..] => return (start, path_completions(word)),
+        ["rep"] => owned(&REPRESENTATIONS),
+        ["set"] => owned(&SETTINGS),
+        [command, ref selection @ ..] if COMMANDS_TAKING_SELECTIONS.contains(&command) => {
+            match selection {
+                [] => owned(&SELECTION_KEYWORDS),
+                ["chain"] => sorted(scene.sequences().iter().map(|s| s.chain.clone())),
+                ["resn"] => sorted(
+                    scene
+                        .sequences()
+                        .iter()
+                        .flat_map(|s| s.residues.iter().map(|r| r.name.clone())),
+                ),
+                _ if matches!(command, "color" | "colour")
+                    && Selection::parse(selection).is_ok() =>
+                {
+                    owned(&COLOR_NAMES)
+                }
+                _ => vec![],
+            }
+        }
+        _ => vec![],
+    };
+    let candidates = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .collect();
+    (start, candidates)
+}
+
+/// Names from the scene without repeats, such as chains found in more than one model
+fn sorted(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut names: Vec<String> = names.collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Files and directories starting with a partly typed path, where directories end with `/`
+fn path_completions(partial: &str) -> Vec<String> {
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(i) => (&partial[..=i], &partial[i + 1..]),
+        None => ("", partial),
+    };
+    let read_from = if directory.is_empty() { "." } else { directory };
+    let Ok(entries) = std::fs::read_dir(Path::new(read_from)) else {
+        return vec![];
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files are only completed once a `.` has been typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", directory, name, slash))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Candidates being cycled through by pressing `<Tab>` repeatedly
+#[derive(Debug, Clone)]
+struct Completion {
+    /// Input before the word being completed
+    prefix: String,
+    candidates: Vec<String>,
+    index: usize,
+}
+
+/// Text being typed after `:`, along with the commands typed before
+#[derive(Debug, Default, Clone)]
+pub struct CommandLine {
+    /// `None` while the command line is closed
+    input: Option<String>,
+    /// Oldest command first
+    history: Vec<String>,
+    /// Entry of the history being shown while stepping through it
+    history_index: Option<usize>,
+    /// What was typed before stepping back through the history
+    draft: String,
+    completion: Option<Completion>,
+    /// Why the last command couldn't be run, shown until the command line is next used
+    error: Option<String>,
+}
+
+impl CommandLine {
+    pub fn is_open(&self) -> bool {
+        self.input.is_some()
+    }
+    /// Whether a key is meant for the command line, which is any key while it is open, or `:` to open it
+    pub fn wants(&self, key: &KeyEvent) -> bool {
+        key.kind == KeyEventKind::Press && (self.is_open() || key.code == KeyCode::Char(':'))
+    }
+    /// Stop showing why the last command failed, returning whether there was anything shown
+    pub fn dismiss_error(&mut self) -> bool {
+        self.error.take().is_some()
+    }
+    /// Edit the command, returning the action it stands for once `<Enter>` is pressed
+    pub fn next_action<S: RayCast + ValidShape>(
+        &mut self,
+        key: KeyEvent,
+        scene: &Scene<S>,
+    ) -> NextAction {
+        let Some(input) = self.input.as_mut() else {
+            if key.code == KeyCode::Char(':') {
+                self.input = Some(String::new());
+                self.error = None;
+            }
+            return NextAction::Nothing;
+        };
+        if !matches!(key.code, KeyCode::Tab | KeyCode::BackTab) {
+            self.completion = None;
+        }
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            // Deleting past the start closes the command line, as in vim
+            KeyCode::Backspace if input.is_empty() => self.close(),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Tab => self.complete(1, scene),
+            KeyCode::BackTab => self.complete(-1, scene),
+            KeyCode::Up => self.step_history(-1),
+            KeyCode::Down => self.step_history(1),
+            KeyCode::Esc => self.close(),
+            KeyCode::Enter => {
+                let line = input.trim().to_string();
+                self.close();
+                if line.is_empty() {
+                    return NextAction::Nothing;
+                }
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                return parse_command(&line).unwrap_or_else(|error| {
+                    self.error = Some(error.to_string());
+                    NextAction::Nothing
+                });
+            }
+            _ => {}
+        }
+        NextAction::Nothing
+    }
+    fn close(&mut self) {
+        self.input = None;
+        self.history_index = None;
+        self.completion = None;
+    }
+    /// Replace the word being typed with the next or previous way of finishing it
+    fn complete<S: RayCast + ValidShape>(&mut self, step: isize, scene: &Scene<S>) {
+        let Some(input) = self.input.as_mut() else {
+            return;
+        };
+        let completion = match self.completion.take() {
+            Some(mut completion) => {
+                let count = completion.candidates.len() as isize;
+                completion.index = (completion.index as isize + step).rem_euclid(count) as usize;
+                completion
+            }
+            None => {
+                let (start, candidates) = completions(input, scene);
+                if candidates.is_empty() {
+                    return;
+                }
+                let index = if step < 0 { candidates.len() - 1 } else { 0 };
+                Completion {
+                    prefix: input[..start].to_string(),
+                    candidates,
+                    index,
+                }
+            }
+        };
+        *input = format!(
+            "{}{}",
+            completion.prefix, completion.candidates[completion.index]
+        );
+        // Only cycle if there is a choice, so that the next word can be completed straight away
+        if completion.candidates.len() > 1 {
+            self.completion = Some(completion);
+        }
+    }
+    /// Step back or forward through the commands run before, ending up back at what was being typed
+    fn step_history(&mut self, step: isize) {
+        let Some(input) = self.input.as_mut() else {
+            return;
+        };
+        let current = self.history_index.unwrap_or(self.history.len());
+        let next = current.saturating_add_signed(step).min(self.history.len());
+        if next == current {
+            return;
+        }
+        if self.history_index.is_none() {
+            self.draft = input.clone();
+        }
+        if next == self.history.len() {
+            *input = std::mem::take(&mut self.draft);
+            self.history_index = None;
+        } else {
+            *input = self.history[next].clone();
+            self.history_index = Some(next);
+        }
+    }
+}
+
+/// Widget for a single row showing the command being typed, or why the last command failed
+pub struct CommandPrompt<'a>(pub &'a CommandLine);
+
+impl Widget for CommandPrompt<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let line = match (&self.0.input, &self.0.error) {
+            (Some(input), _) => {
+                let mut spans = vec![Span::raw(format!(":{}", input)), Span::raw(" ").reversed()];
+                // Show the other ways of finishing the word while cycling through them
+                if let Some(completion) = &self.0.completion {
+                    spans.push(Span::styled(
+                        format!("  {}", completion.candidates.join(" ")),
+                        Style::new().dark_gray(),
+                    ));
+                }
+                Line::from(spans)
+            }
+            (None, Some(error)) => Line::styled(error.clone(), Style::new().red()),
+            (None, None) => return,
+        };
+        buf.set_style(area, Style::reset());
+        for x in area.left()..area.right() {
+            buf.get_mut(x, area.y).set_char(' ');
+        }
+        buf.set_line(area.x, area.y, &line, area.width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+    use parry3d::shape::TriMesh;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::empty())
+    }
+
+    /// Type some text into the command line, returning the action from the last key
+    fn type_keys(command_line: &mut CommandLine, keys: &[KeyCode]) -> NextAction {
+        let scene = Scene::<TriMesh>::default();
+        let mut action = NextAction::Nothing;
+        for &code in keys {
+            action = command_line.next_action(key(code), &scene);
+        }
+        action
+    }
+
+    fn chars(text: &str) -> Vec<KeyCode> {
+        text.chars().map(KeyCode::Char).collect()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert!(matches!(parse_command("q"), Ok(NextAction::Quit)));
+        assert!(matches!(
+            parse_command("load data/my file.pdb"),
+            Ok(NextAction::Load { path }) if path == "data/my file.pdb"
+        ));
+        assert!(matches!(parse_command("save"), Ok(NextAction::Save)));
+        assert!(matches!(
+            parse_command("color chain A red"),
+            Ok(NextAction::Color { selection: Selection::Chain(chain), color: Color::Red }) if chain == "A"
+        ));
+        assert!(matches!(
+            parse_command("hide resn HOH"),
+            Ok(NextAction::SetVisible { visible: false, .. })
+        ));
+        assert!(matches!(
+            parse_command("zoom"),
+            Ok(NextAction::ZoomTo {
+                selection: Selection::All
+            })
+        ));
+        assert!(matches!(
+            parse_command("set fov 30"),
+            Ok(NextAction::SetFieldOfView { degrees }) if degrees == 30.0
+        ));
+
+        assert_eq!(
+            parse_command("colour chain A purple").err(),
+            Some(CommandError::UnknownColor("purple".to_string()))
+        );
+        assert_eq!(
+            parse_command("set fov 180").err(),
+            Some(CommandError::OutOfRange("fov", 1.0, 170.0))
+        );
+        assert_eq!(
+            parse_command("rep cartoon").err(),
+            Some(CommandError::UnsupportedRepresentation(
+                "cartoon".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_command("hide chain").err(),
+            Some(CommandError::Selection(SelectionError::MissingValue(
+                "chain".to_string()
+            )))
+        );
+        assert_eq!(
+            parse_command("frobnicate").err(),
+            Some(CommandError::UnknownCommand("frobnicate".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_completions() {
+        let scene = Scene::<TriMesh>::default();
+        assert_eq!(
+            completions("s", &scene),
+            (0, vec!["save".into(), "set".into(), "show".into()])
+        );
+        assert_eq!(
+            completions("hide re", &scene),
+            (5, vec!["resn".into(), "resi".into()])
+        );
+        assert_eq!(
+            completions("color all lightb", &scene),
+            (10, vec!["lightblue".into()])
+        );
+        assert_eq!(completions("set f", &scene), (4, vec!["fov".into()]));
+        // Only representations that can be drawn are offered
+        assert_eq!(completions("rep ", &scene), (4, vec!["spheres".into()]));
+        assert_eq!(
+            completions("colour all lightb", &scene).1,
+            vec!["lightblue".to_string()]
+        );
+        assert_eq!(completions("q ", &scene), (2, vec![]));
+    }
+
+    #[test]
+    fn test_command_line() {
+        let mut command_line = CommandLine::default();
+        assert!(!command_line.wants(&key(KeyCode::Char('q'))));
+        assert!(command_line.wants(&key(KeyCode::Char(':'))));
+
+        // Completing cycles through the choices, then the next word completes straight away
+        let mut keys = chars(":co");
+        keys.extend([KeyCode::Tab]);
+        keys.extend(chars(" all"));
+        type_keys(&mut command_line, &keys);
+        assert_eq!(command_line.input.as_deref(), Some("color all"));
+        type_keys(&mut command_line, &chars(" bl"));
+        type_keys(&mut command_line, &[KeyCode::Tab]);
+        assert_eq!(command_line.input.as_deref(), Some("color all blue"));
+        type_keys(&mut command_line, &[KeyCode::Tab, KeyCode::Tab]);
+        assert_eq!(command_line.input.as_deref(), Some("color all blue"));
+        let action = type_keys(&mut command_line, &[KeyCode::Enter]);
+        assert!(matches!(
+            action,
+            NextAction::Color {
+                color: Color::Blue,
+                ..
+            }
+        ));
+        assert!(!command_line.is_open());
+
+        // Errors are kept to be shown, and the command still goes into the history
+        let mut keys = chars(":zap");
+        keys.push(KeyCode::Enter);
+        type_keys(&mut command_line, &keys);
+        assert_eq!(
+            command_line.error.as_deref(),
+            Some("Unknown command `zap`.")
+        );
+
+        let mut keys = chars(":se");
+        keys.extend([KeyCode::Up, KeyCode::Up, KeyCode::Up]);
+        type_keys(&mut command_line, &keys);
+        assert_eq!(command_line.input.as_deref(), Some("color all blue"));
+        type_keys(&mut command_line, &[KeyCode::Down]);
+        assert_eq!(command_line.input.as_deref(), Some("zap"));
+        type_keys(&mut command_line, &[KeyCode::Down]);
+        assert_eq!(command_line.input.as_deref(), Some("se"));
+
+        type_keys(&mut command_line, &[KeyCode::Backspace, KeyCode::Backspace]);
+        assert!(command_line.is_open());
+        type_keys(&mut command_line, &[KeyCode::Backspace]);
+        assert!(!command_line.is_open());
+    }
+}
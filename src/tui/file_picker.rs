@@ -0,0 +1,350 @@
+//! Popup for adding files to the scene while it is shown, and for removing files that were loaded.
+
+use crate::{
+    read::{parse_header, PdbHeader},
+    tui::popup::Popup,
+};
+use ratatui::{
+    prelude::{Buffer, Rect, Style, Stylize},
+    text::Line,
+    widgets::Widget,
+};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Rows kept for the preview below the list of files
+const PREVIEW_HEIGHT: u16 = 5;
+
+/// File or directory listed by the picker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickerEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// Which of the two lists keys act on
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PickerFocus {
+    #[default]
+    Browse,
+    Loaded,
+}
+
+/// Where the picker is in the filesystem and in the list of loaded files
+#[derive(Debug)]
+pub struct FilePicker {
+    dir: PathBuf,
+    entries: Vec<PickerEntry>,
+    cursor: usize,
+    loaded_cursor: usize,
+    pub focus: PickerFocus,
+    /// Header of the PDB file under the cursor, kept so the file is only read once
+    preview: Option<(PathBuf, Result<PdbHeader, String>)>,
+    /// Why the current directory couldn't be listed
+    pub message: Option<String>,
+    /// Only files that can be shown alongside the current shapes are listed, see `LoadShapes::can_load`
+    can_load: fn(&Path) -> bool,
+}
+
+impl FilePicker {
+    pub fn new(dir: PathBuf, can_load: fn(&Path) -> bool) -> Self {
+        let mut picker = Self {
+            dir,
+            entries: vec![],
+            cursor: 0,
+            loaded_cursor: 0,
+            focus: PickerFocus::default(),
+            preview: None,
+            message: None,
+            can_load,
+        };
+        picker.read_dir();
+        picker
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    pub fn entries(&self) -> &[PickerEntry] {
+        &self.entries[..]
+    }
+    /// List the current directory, with the parent directory first, then directories and then files
+    /// Hidden files are left out
+    fn read_dir(&mut self) {
+        self.entries.clear();
+        self.message = None;
+        if let Some(parent) = self.dir.parent() {
+            self.entries.push(PickerEntry {
+                name: "..".to_string(),
+                path: parent.to_path_buf(),
+                is_dir: true,
+            });
+        }
+        let read = match std::fs::read_dir(&self.dir) {
+            Ok(read) => read,
+            Err(error) => {
+                self.message = Some(format!("Could not read {}: {}", self.dir.display(), error));
+                return;
+            }
+        };
+        let mut entries: Vec<PickerEntry> = read
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                let path = entry.path();
+                let is_dir = path.is_dir();
+                let shown = !name.starts_with('.') && (is_dir || (self.can_load)(&path));
+                shown.then_some(PickerEntry { name, path, is_dir })
+            })
+            .collect();
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+        self.entries.extend(entries);
+        self.cursor = self.cursor.min(self.entries.len().saturating_sub(1));
+    }
+    /// Move the cursor of whichever list has focus, where the loaded files are counted by the caller
+    pub fn move_cursor(&mut self, delta: isize, loaded: usize) {
+        match self.focus {
+            PickerFocus::Browse => {
+                self.cursor = self
+                    .cursor
+                    .saturating_add_signed(delta)
+                    .min(self.entries.len().saturating_sub(1));
+            }
+            PickerFocus::Loaded => {
+                self.loaded_cursor = self
+                    .loaded_cursor
+                    .saturating_add_signed(delta)
+                    .min(loaded.saturating_sub(1));
+            }
+        }
+    }
+    pub fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            PickerFocus::Browse => PickerFocus::Loaded,
+            PickerFocus::Loaded => PickerFocus::Browse,
+        };
+    }
+    pub fn entry(&self) -> Option<&PickerEntry> {
+        self.entries.get(self.cursor)
+    }
+    /// Enter the directory under the cursor, or return the file under it to be loaded
+    pub fn open(&mut self) -> Option<PathBuf> {
+        let entry = self.entry()?.clone();
+        if !entry.is_dir {
+            return Some(entry.path);
+        }
+        if entry.name == ".." {
+            self.parent();
+        } else {
+            self.dir = entry.path;
+            self.cursor = 0;
+            self.read_dir();
+        }
+        None
+    }
+    /// Go up a directory, leaving the cursor on the directory that was left
+    pub fn parent(&mut self) {
+        // Paths such as `.` have no parent until they are made absolute
+        let dir = self.dir.canonicalize().unwrap_or_else(|_| self.dir.clone());
+        let Some(parent) = dir.parent() else {
+            return;
+        };
+        let left = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        self.dir = parent.to_path_buf();
+        self.read_dir();
+        self.cursor = self
+            .entries
+            .iter()
+            .position(|entry| Some(&entry.name) == left.as_ref())
+            .unwrap_or(0);
+    }
+    /// Index and name of the loaded file under the cursor of the list of loaded files
+    pub fn loaded_file<'a>(&self, files: &[&'a str]) -> Option<(usize, &'a str)> {
+        let index = self.loaded_cursor.min(files.len().saturating_sub(1));
+        Some((index, files.get(index)?))
+    }
+    /// Read the header of the PDB file under the cursor, unless it has already been read
+    pub fn update_preview(&mut self) {
+        let Some(entry) = self.entry().filter(|entry| !entry.is_dir) else {
+            self.preview = None;
+            return;
+        };
+        let is_pdb = entry
+            .path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pdb"));
+        if !is_pdb {
+            self.preview = None;
+        } else if self.preview.as_ref().map(|(path, _)| path) != Some(&entry.path) {
+            let header = File::open(&entry.path)
+                .and_then(|file| parse_header(BufReader::new(file)))
+                .map_err(|error| error.to_string());
+            self.preview = Some((entry.path.clone(), header));
+        }
+    }
+    fn preview_lines(&self) -> Vec<Line<'static>> {
+        match &self.preview {
+            None => vec![],
+            Some((_, Err(error))) => vec![Line::from(format!("Could not read file: {}", error))],
+            Some((_, Ok(header))) => vec![
+                Line::from(format!(
+                    "Title:      {}",
+                    header.title.as_deref().unwrap_or("None")
+                )),
+                Line::from(format!(
+                    "Resolution: {}",
+                    header
+                        .resolution
+                        .map_or("Unknown".to_string(), |r| format!("{:.2} Å", r))
+                )),
+                Line::from(format!("Chains:     {}", header.chains)),
+            ],
+        }
+    }
+}
+
+/// Rows of a list shown in `height` rows, scrolled so that the cursor is shown
+fn list_lines<'a>(
+    items: impl Iterator<Item = Line<'a>>,
+    cursor: usize,
+    height: usize,
+    focused: bool,
+) -> Vec<Line<'a>> {
+    let first = (cursor + 1).saturating_sub(height);
+    items
+        .enumerate()
+        .skip(first)
+        .take(height)
+        .map(|(i, line)| {
+            if i == cursor && focused {
+                line.reversed()
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+/// Popup showing the filesystem on the left, with a preview of the file under the cursor, and the loaded files on
+/// the right
+pub struct FilePickerPopup<'a> {
+    pub picker: &'a FilePicker,
+    /// Files loaded into the scene, see `Scene::files`
+    pub files: Vec<&'a str>,
+    /// Lines explaining the keys, shown at the bottom of the list of loaded files
+    pub hints: Vec<Line<'a>>,
+}
+
+impl Widget for FilePickerPopup<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let picker = self.picker;
+        let browse_area = Rect {
+            width: area.width * 3 / 5,
+            ..area
+        };
+        let loaded_area = Rect {
+            x: browse_area.right(),
+            width: area.width - browse_area.width,
+            ..area
+        };
+
+        let mut footer = vec![Line::from("")];
+        footer.extend(picker.preview_lines());
+        if let Some(message) = &picker.message {
+            footer.extend([Line::from(""), Line::from(message.clone()).bold()]);
+        }
+        let height = browse_area
+            .height
+            .saturating_sub(2 + PREVIEW_HEIGHT.max(footer.len() as u16))
+            .max(1) as usize;
+        let entries = picker.entries.iter().map(|entry| {
+            if entry.is_dir {
+                Line::from(format!("{}/", entry.name)).bold()
+            } else {
+                Line::from(entry.name.clone())
+            }
+        });
+        let focused = picker.focus == PickerFocus::Browse;
+        let mut lines = list_lines(entries, picker.cursor, height, focused);
+        if picker.entries.is_empty() {
+            lines.push(Line::from("No files."));
+        }
+        lines.resize(height, Line::from(""));
+        lines.extend(footer);
+        Popup::default()
+            .content(lines)
+            .style(Style::new().black())
+            .title(picker.dir.display().to_string())
+            .title_style(Style::new().bold())
+            .border_style(Style::new().red())
+            .render(browse_area, buf);
+
+        let height = loaded_area
+            .height
+            .saturating_sub(3 + self.hints.len() as u16)
+            .max(1) as usize;
+        let files = self.files.iter().map(|file| Line::from(file.to_string()));
+        let focused = picker.focus == PickerFocus::Loaded;
+        let cursor = picker.loaded_cursor.min(self.files.len().saturating_sub(1));
+        let mut lines = list_lines(files, cursor, height, focused);
+        if self.files.is_empty() {
+            lines.push(Line::from("No files loaded."));
+        }
+        lines.resize(height, Line::from(""));
+        lines.push(Line::from(""));
+        lines.extend(self.hints);
+        Popup::default()
+            .content(lines)
+            .style(Style::new().black())
+            .title("Loaded")
+            .title_style(Style::new().bold())
+            .border_style(Style::new().red())
+            .render(loaded_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_pdb(path: &Path) -> bool {
+        path.extension().is_some_and(|extension| extension == "pdb")
+    }
+
+    #[test]
+    fn test_browse() {
+        let mut picker = FilePicker::new(PathBuf::from("./data"), is_pdb);
+        let names: Vec<&str> = picker.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["..", "rbd.pdb", "three_chains.pdb"]);
+
+        picker.move_cursor(1, 0);
+        assert_eq!(picker.open(), Some(PathBuf::from("./data/rbd.pdb")));
+        picker.update_preview();
+        assert!(matches!(picker.preview, Some((_, Ok(_)))));
+        picker.move_cursor(5, 0);
+        assert_eq!(picker.entry().unwrap().name, "three_chains.pdb");
+
+        // Going up leaves the cursor on the directory that was left
+        picker.parent();
+        assert_eq!(picker.entry().unwrap().name, "data");
+        assert_eq!(picker.open(), None);
+        assert_eq!(picker.dir().file_name().unwrap(), "data");
+    }
+
+    #[test]
+    fn test_loaded_files() {
+        let mut picker = FilePicker::new(PathBuf::from("./data"), is_pdb);
+        let files = ["a.pdb", "b.pdb"];
+        picker.toggle_focus();
+        picker.move_cursor(3, files.len());
+        assert_eq!(picker.loaded_file(&files), Some((1, "b.pdb")));
+        // The cursor stays on the last file as files are removed
+        assert_eq!(picker.loaded_file(&files[..1]), Some((0, "a.pdb")));
+        assert_eq!(picker.loaded_file(&[]), None);
+        // Moving the loaded cursor leaves the other list alone
+        assert_eq!(picker.entry().unwrap().name, "..");
+    }
+}
@@ -0,0 +1,413 @@
+//! Drawing real pixels in terminals that support the Kitty graphics protocol or Sixel.
+
+use crate::rasterizer::{ColoredChar, ColoredPixel, Rasterizer};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use crossterm::{
+    cursor::{MoveTo, RestorePosition, SavePosition},
+    queue,
+    style::Print,
+    terminal::window_size,
+};
+use image::{
+    imageops::{resize, FilterType},
+    Rgba, RgbaImage,
+};
+use ratatui::{prelude::Rect, style::Color};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Result, Write};
+
+/// Maximum size of each chunk of base64 data sent using the Kitty graphics protocol
+const KITTY_CHUNK_SIZE: usize = 4096;
+/// Fixed image ID so that each new frame replaces the last
+const KITTY_IMAGE_ID: u32 = 1;
+/// Number of levels per channel in the colour cube used as the Sixel palette
+const SIXEL_LEVELS: u8 = 6;
+
+/// How the scene should be output to the terminal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputMode {
+    /// Use a graphics protocol if the terminal seems to support one, otherwise fall back to ASCII
+    #[default]
+    Auto,
+    Ascii,
+    Kitty,
+    Sixel,
+}
+
+impl OutputMode {
+    /// Decide on the graphics protocol, where `None` means falling back to ASCII rasterizers
+    pub fn protocol(self) -> Option<GraphicsProtocol> {
+        match self {
+            Self::Auto => GraphicsProtocol::detect(),
+            Self::Ascii => None,
+            Self::Kitty => Some(GraphicsProtocol::Kitty),
+            Self::Sixel => Some(GraphicsProtocol::Sixel),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Sixel,
+}
+
+impl GraphicsProtocol {
+    /// Guess the supported protocol from environment variables set by terminal emulators
+    // TODO Query the terminal directly, once input is read asynchronously and responses won't get lost
+    pub fn detect() -> Option<Self> {
+        Self::detect_from(|key| std::env::var(key).ok())
+    }
+    fn detect_from<F: Fn(&str) -> Option<String>>(var: F) -> Option<Self> {
+        // Multiplexers swallow the escape codes unless configured to pass them through
+        if var("TMUX").is_some() {
+            return None;
+        }
+        let term = var("TERM").unwrap_or_default();
+        let term_program = var("TERM_PROGRAM").unwrap_or_default();
+        if var("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term_program == "WezTerm"
+            || term_program == "ghostty"
+        {
+            Some(Self::Kitty)
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || term_program == "iTerm.app"
+        {
+            Some(Self::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+/// Rasterizer used when the terminal draws real pixels
+/// Only produces blank characters, so that `ratatui` leaves space for the image
+#[derive(Clone, Copy, Debug)]
+pub struct GraphicsRasterizer {
+    grid_width: usize,
+    grid_height: usize,
+}
+
+impl GraphicsRasterizer {
+    /// Constructor taking the number of pixels rendered per character cell
+    pub fn new(grid_width: usize, grid_height: usize) -> Self {
+        Self {
+            grid_width: grid_width.max(1),
+            grid_height: grid_height.max(1),
+        }
+    }
+}
+
+impl Default for GraphicsRasterizer {
+    fn default() -> Self {
+        Self::new(2, 4)
+    }
+}
+
+impl Rasterizer for GraphicsRasterizer {
+    fn pixels_to_stdout(
+        &self,
+        pixels: Vec<&[ColoredPixel]>,
+        output_width: usize,
+    ) -> Vec<ColoredChar> {
+        let blank = ColoredChar {
+            symbol: ' ',
+            color: Color::Reset,
+        };
+        let newline = ColoredChar {
+            symbol: '\n',
+            color: Color::Reset,
+        };
+        pixels
+            .chunks(output_width)
+            .flat_map(|row| row.iter().map(move |_| blank).chain([newline]))
+            .collect()
+    }
+    fn grid_height(&self) -> usize {
+        self.grid_height
+    }
+    fn grid_width(&self) -> usize {
+        self.grid_width
+    }
+}
+
+/// Encode an image using the Kitty graphics protocol, scaled to fill `columns` by `rows` cells
+pub fn encode_kitty(image: &RgbaImage, columns: u16, rows: u16) -> String {
+    let encoded = STANDARD.encode(image.as_raw());
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // Base64 is always valid ASCII
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if i == 0 {
+            // `q=2` suppresses responses, which would otherwise be read as key presses
+            // `C=1` stops the cursor from moving
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=32,s={},v={},c={},r={},i={},q=2,C=1,m={};{}\x1b\\",
+                image.width(),
+                image.height(),
+                columns,
+                rows,
+                KITTY_IMAGE_ID,
+                more,
+                chunk
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    out
+}
+
+/// Encode an image as Sixel data, using a fixed colour cube as the palette
+/// Transparent pixels are left unset, so that the terminal background shows through
+pub fn encode_sixel(image: &RgbaImage) -> String {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let registers: Vec<Option<u16>> = image
+        .pixels()
+        .map(|p| (p[3] > 0).then(|| sixel_register(p)))
+        .collect();
+
+    // `P2=1` keeps unset pixels transparent
+    let mut out = String::from("\x1bP0;1;0q");
+    let _ = write!(out, "\"1;1;{};{}", width, height);
+
+    let mut used = vec![false; (SIXEL_LEVELS as usize).pow(3)];
+    for register in registers.iter().flatten() {
+        used[*register as usize] = true;
+    }
+    for (register, _) in used.iter().enumerate().filter(|(_, u)| **u) {
+        let [r, g, b] = sixel_register_rgb(register as u16);
+        let _ = write!(out, "#{};2;{};{};{}", register, r, g, b);
+    }
+
+    // Each sixel covers six rows of pixels, with one bit per row
+    for band_top in (0..height).step_by(6) {
+        let mut band: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+        for dy in 0..6.min(height - band_top) {
+            let y = band_top + dy;
+            for x in 0..width {
+                if let Some(register) = registers[y * width + x] {
+                    band.entry(register).or_insert_with(|| vec![0u8; width])[x] |= 1 << dy;
+                }
+            }
+        }
+        for (register, sixels) in band.iter() {
+            let _ = write!(out, "#{}", register);
+            push_run_length(&mut out, sixels);
+            // Carriage return to overlay the next colour on the same band
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Find the palette register for a pixel
+fn sixel_register(pixel: &Rgba<u8>) -> u16 {
+    let level = |c: u8| (c as u16 * (SIXEL_LEVELS as u16 - 1) + 127) / 255;
+    let n = SIXEL_LEVELS as u16;
+    level(pixel[0]) * n * n + level(pixel[1]) * n + level(pixel[2])
+}
+
+/// Colour of a palette register, as percentages
+fn sixel_register_rgb(register: u16) -> [u16; 3] {
+    let n = SIXEL_LEVELS as u16;
+    let percent = |level: u16| level * 100 / (n - 1);
+    [
+        percent(register / (n * n)),
+        percent((register / n) % n),
+        percent(register % n),
+    ]
+}
+
+/// Write sixels with run-length encoding of repeated characters
+fn push_run_length(out: &mut String, sixels: &[u8]) {
+    let mut i = 0;
+    while i < sixels.len() {
+        let run = sixels[i..].iter().take_while(|s| **s == sixels[i]).count();
+        let symbol = (63 + sixels[i]) as char;
+        if run > 3 {
+            let _ = write!(out, "!{}{}", run, symbol);
+        } else {
+            (0..run).for_each(|_| out.push(symbol));
+        }
+        i += run;
+    }
+}
+
+/// Keeps track of the image shown in the terminal
+#[derive(Debug)]
+pub struct GraphicsBackend {
+    protocol: GraphicsProtocol,
+    /// Image and area last sent to the terminal, to avoid resending identical frames
+    last_sent: Option<(RgbaImage, Rect)>,
+}
+
+impl GraphicsBackend {
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        Self {
+            protocol,
+            last_sent: None,
+        }
+    }
+    pub fn protocol(&self) -> GraphicsProtocol {
+        self.protocol
+    }
+    /// Draw the image so that it covers the area of the terminal
+    /// Will do nothing if the same image was already drawn in the same place
+    pub fn draw<W: Write>(&mut self, out: &mut W, image: RgbaImage, area: Rect) -> Result<()> {
+        if self
+            .last_sent
+            .as_ref()
+            .is_some_and(|(last_image, last_area)| *last_image == image && *last_area == area)
+        {
+            return Ok(());
+        }
+        if image.width() == 0 || image.height() == 0 {
+            return Ok(());
+        }
+
+        let payload = match self.protocol {
+            GraphicsProtocol::Kitty => encode_kitty(&image, area.width, area.height),
+            GraphicsProtocol::Sixel => {
+                // Sixel can't scale images, so resize to the size of the area in pixels when known
+                match window_size() {
+                    Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 => {
+                        let cell_width = size.width as u32 / size.columns as u32;
+                        let cell_height = size.height as u32 / size.rows.max(1) as u32;
+                        encode_sixel(&resize(
+                            &image,
+                            area.width as u32 * cell_width,
+                            area.height as u32 * cell_height,
+                            FilterType::Nearest,
+                        ))
+                    }
+                    _ => encode_sixel(&image),
+                }
+            }
+        };
+        queue!(
+            out,
+            SavePosition,
+            MoveTo(area.x, area.y),
+            Print(payload),
+            RestorePosition
+        )?;
+        out.flush()?;
+
+        self.last_sent = Some((image, area));
+        Ok(())
+    }
+    /// Remove the image, e.g. so that popups aren't hidden underneath it
+    /// Sixel images can only be removed by drawing over them with blank cells, so this must be done before anything
+    /// else is drawn in the area of the image
+    pub fn clear<W: Write>(&mut self, out: &mut W) -> Result<()> {
+        let Some((_, area)) = self.last_sent.take() else {
+            return Ok(());
+        };
+        match self.protocol {
+            GraphicsProtocol::Kitty => queue!(
+                out,
+                Print(format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", KITTY_IMAGE_ID))
+            )?,
+            GraphicsProtocol::Sixel => {
+                let blank = " ".repeat(area.width as usize);
+                queue!(out, SavePosition)?;
+                for y in area.top()..area.bottom() {
+                    queue!(out, MoveTo(area.x, y), Print(&blank))?;
+                }
+                queue!(out, RestorePosition)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_protocol() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        assert_eq!(
+            GraphicsProtocol::detect_from(env(&[("TERM", "xterm-kitty")])),
+            Some(GraphicsProtocol::Kitty)
+        );
+        assert_eq!(
+            GraphicsProtocol::detect_from(env(&[("TERM", "foot")])),
+            Some(GraphicsProtocol::Sixel)
+        );
+        assert_eq!(
+            GraphicsProtocol::detect_from(env(&[("TERM", "xterm-kitty"), ("TMUX", "1")])),
+            None
+        );
+        assert_eq!(
+            GraphicsProtocol::detect_from(env(&[("TERM", "xterm-256color")])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_kitty_chunks() {
+        let image = RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255]));
+        let encoded = encode_kitty(&image, 10, 5);
+
+        assert!(encoded.starts_with("\x1b_Ga=T,f=32,s=64,v=64,c=10,r=5"));
+        // 64 * 64 * 4 bytes is 21848 characters of base64, so needs six chunks
+        assert_eq!(encoded.matches("\x1b_G").count(), 6);
+        assert_eq!(encoded.matches("m=1;").count(), 5);
+        assert!(encoded.ends_with("\x1b\\"));
+    }
+
+    #[test]
+    fn test_sixel_encoding() {
+        let mut image = RgbaImage::from_pixel(8, 6, Rgba([255, 255, 255, 255]));
+        image.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        let encoded = encode_sixel(&image);
+
+        assert!(encoded.starts_with("\x1bP0;1;0q\"1;1;8;6"));
+        assert!(encoded.ends_with("-\x1b\\"));
+        // Only white is used, in the last register of the colour cube
+        assert!(encoded.contains("#215;2;100;100;100"));
+        // First column is missing the top pixel, then seven full columns
+        assert!(encoded.contains("#215}!7~$"));
+    }
+
+    #[test]
+    fn test_sixel_clear() {
+        let mut backend = GraphicsBackend::new(GraphicsProtocol::Sixel);
+        let image = RgbaImage::from_pixel(8, 6, Rgba([255, 255, 255, 255]));
+        backend
+            .draw(&mut Vec::<u8>::new(), image, Rect::new(1, 2, 4, 3))
+            .unwrap();
+
+        let mut out = vec![];
+        backend.clear(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        // Every row of the image is drawn over with blank cells
+        for row in 3..=5 {
+            assert!(out.contains(&format!("\x1b[{};2H    ", row)));
+        }
+        assert!(!out.contains("\x1b[6;2H"));
+
+        // Nothing is left to clear
+        let mut out = vec![];
+        backend.clear(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
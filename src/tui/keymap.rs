@@ -0,0 +1,654 @@
+//! Keys bound to each action, which can be changed in `~/.config/pdb-tui/config.toml` to suit any keyboard layout.
+//!
+//! The config file lists the keys for any actions that should differ from the defaults, such as
+//!
+//! ```toml
+//! [keys]
+//! move_left = ["n", "<Left>"]
+//! move_down = "e"
+//! ```
+//!
+//! Keys are either single characters or names in angle brackets, such as `<Space>` or `<Esc>`.
+
+use crate::lighting::LightingEdit;
+use crossterm::event::KeyCode;
+use ratatui::text::Line;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const MINOR_LIGHTING_CHANGE: f32 = 0.05;
+
+/// Names of keys that aren't single characters, as written in the config file and help
+const KEY_NAMES: [(KeyCode, &str); 14] = [
+    (KeyCode::Char(' '), "<Space>"),
+    (KeyCode::Esc, "<Esc>"),
+    (KeyCode::Tab, "<Tab>"),
+    (KeyCode::BackTab, "<S-Tab>"),
+    (KeyCode::Enter, "<Enter>"),
+    (KeyCode::Backspace, "<BS>"),
+    (KeyCode::Delete, "<Del>"),
+    (KeyCode::Up, "<Up>"),
+    (KeyCode::Down, "<Down>"),
+    (KeyCode::Left, "<Left>"),
+    (KeyCode::Right, "<Right>"),
+    (KeyCode::PageUp, "<PageUp>"),
+    (KeyCode::PageDown, "<PageDown>"),
+    (KeyCode::Home, "<Home>"),
+];
+
+#[derive(Error, Debug)]
+pub enum KeymapError {
+    #[error("Could not read {0}.")]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("Could not parse {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("Unknown action `{0}` in key bindings.")]
+    UnknownAction(String),
+    #[error("Unknown key `{0}` in key bindings.")]
+    UnknownKey(String),
+    #[error("Key `{0}` is bound to both `{1}` and `{2}`.")]
+    Conflict(String, &'static str, &'static str),
+}
+
+/// Where the help shows each action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyGroup {
+    General,
+    Movement,
+    Rotation,
+    /// Only shown in the popup of the state that uses them
+    Contextual,
+}
+
+/// Anything a key can be bound to, in either the terminal or the GPU viewers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyAction {
+    Quit,
+    Help,
+    Benchmark,
+    Save,
+    CycleDithering,
+    CycleSupersampling,
+    CycleSamplePattern,
+    ToggleAmbientOcclusion,
+    ToggleDepthCueing,
+    CycleShadows,
+    ToggleToon,
+    ToggleOutlines,
+    Calibrate,
+    EditLighting,
+    Measure,
+    ToggleSequence,
+    BrowseStructure,
+    OpenFiles,
+    ShowLog,
+    Back,
+    ZoomOut,
+    ZoomIn,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    RotateLeft,
+    RotateRight,
+    RotateUp,
+    RotateDown,
+    IncreaseCellAspect,
+    DecreaseCellAspect,
+    SelectNext,
+    AddLight,
+    Remove,
+    CycleLightKind,
+    CycleColor,
+    IncreaseLightIntensity,
+    DecreaseLightIntensity,
+    IncreaseAmbient,
+    DecreaseAmbient,
+    IncreaseSpecular,
+    DecreaseSpecular,
+    IncreaseShininess,
+    DecreaseShininess,
+    MeasureDistance,
+    MeasureAngle,
+    MeasureDihedral,
+    ExportMeasurements,
+    ToggleVisibility,
+    ToggleExpanded,
+}
+
+impl KeyAction {
+    /// Every action, in the order shown in the help
+    pub const ALL: [KeyAction; 51] = [
+        Self::Quit,
+        Self::Help,
+        Self::Benchmark,
+        Self::Save,
+        Self::CycleDithering,
+        Self::CycleSupersampling,
+        Self::CycleSamplePattern,
+        Self::ToggleAmbientOcclusion,
+        Self::ToggleDepthCueing,
+        Self::CycleShadows,
+        Self::ToggleToon,
+        Self::ToggleOutlines,
+        Self::Calibrate,
+        Self::EditLighting,
+        Self::Measure,
+        Self::ToggleSequence,
+        Self::BrowseStructure,
+        Self::OpenFiles,
+        Self::ShowLog,
+        Self::Back,
+        Self::ZoomOut,
+        Self::ZoomIn,
+        Self::MoveLeft,
+        Self::MoveRight,
+        Self::MoveUp,
+        Self::MoveDown,
+        Self::RotateLeft,
+        Self::RotateRight,
+        Self::RotateUp,
+        Self::RotateDown,
+        Self::IncreaseCellAspect,
+        Self::DecreaseCellAspect,
+        Self::SelectNext,
+        Self::AddLight,
+        Self::Remove,
+        Self::CycleLightKind,
+        Self::CycleColor,
+        Self::IncreaseLightIntensity,
+        Self::DecreaseLightIntensity,
+        Self::IncreaseAmbient,
+        Self::DecreaseAmbient,
+        Self::IncreaseSpecular,
+        Self::DecreaseSpecular,
+        Self::IncreaseShininess,
+        Self::DecreaseShininess,
+        Self::MeasureDistance,
+        Self::MeasureAngle,
+        Self::MeasureDihedral,
+        Self::ExportMeasurements,
+        Self::ToggleVisibility,
+        Self::ToggleExpanded,
+    ];
+
+    /// Name of the action in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Quit => "quit",
+            Self::Help => "help",
+            Self::Benchmark => "benchmark",
+            Self::Save => "save",
+            Self::CycleDithering => "cycle_dithering",
+            Self::CycleSupersampling => "cycle_supersampling",
+            Self::CycleSamplePattern => "cycle_sample_pattern",
+            Self::ToggleAmbientOcclusion => "toggle_ambient_occlusion",
+            Self::ToggleDepthCueing => "toggle_depth_cueing",
+            Self::CycleShadows => "cycle_shadows",
+            Self::ToggleToon => "toggle_toon",
+            Self::ToggleOutlines => "toggle_outlines",
+            Self::Calibrate => "calibrate",
+            Self::EditLighting => "edit_lighting",
+            Self::Measure => "measure",
+            Self::ToggleSequence => "toggle_sequence",
+            Self::BrowseStructure => "browse_structure",
+            Self::OpenFiles => "open_files",
+            Self::ShowLog => "show_log",
+            Self::Back => "back",
+            Self::ZoomOut => "zoom_out",
+            Self::ZoomIn => "zoom_in",
+            Self::MoveLeft => "move_left",
+            Self::MoveRight => "move_right",
+            Self::MoveUp => "move_up",
+            Self::MoveDown => "move_down",
+            Self::RotateLeft => "rotate_left",
+            Self::RotateRight => "rotate_right",
+            Self::RotateUp => "rotate_up",
+            Self::RotateDown => "rotate_down",
+            Self::IncreaseCellAspect => "increase_cell_aspect",
+            Self::DecreaseCellAspect => "decrease_cell_aspect",
+            Self::SelectNext => "select_next",
+            Self::AddLight => "add_light",
+            Self::Remove => "remove",
+            Self::CycleLightKind => "cycle_light_kind",
+            Self::CycleColor => "cycle_color",
+            Self::IncreaseLightIntensity => "increase_light_intensity",
+            Self::DecreaseLightIntensity => "decrease_light_intensity",
+            Self::IncreaseAmbient => "increase_ambient",
+            Self::DecreaseAmbient => "decrease_ambient",
+            Self::IncreaseSpecular => "increase_specular",
+            Self::DecreaseSpecular => "decrease_specular",
+            Self::IncreaseShininess => "increase_shininess",
+            Self::DecreaseShininess => "decrease_shininess",
+            Self::MeasureDistance => "measure_distance",
+            Self::MeasureAngle => "measure_angle",
+            Self::MeasureDihedral => "measure_dihedral",
+            Self::ExportMeasurements => "export_measurements",
+            Self::ToggleVisibility => "toggle_visibility",
+            Self::ToggleExpanded => "toggle_expanded",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Quit => "Quit the application.",
+            Self::Help => "Show this help.",
+            Self::Benchmark => "Benchmark rendering.",
+            Self::Save => "Save screenshot.",
+            Self::CycleDithering => "Cycle dithering.",
+            Self::CycleSupersampling => "Cycle anti-aliasing samples.",
+            Self::CycleSamplePattern => "Toggle rotated grid anti-aliasing.",
+            Self::ToggleAmbientOcclusion => "Toggle ambient occlusion.",
+            Self::ToggleDepthCueing => "Toggle depth cueing.",
+            Self::CycleShadows => "Cycle shadows (off, hard, soft).",
+            Self::ToggleToon => "Toggle toon shading.",
+            Self::ToggleOutlines => "Toggle outlines.",
+            Self::Calibrate => "Calibrate character aspect ratio.",
+            Self::EditLighting => "Edit lighting.",
+            Self::Measure => "Measure between atoms.",
+            Self::ToggleSequence => "Toggle sequence.",
+            Self::BrowseStructure => "Browse structure tree.",
+            Self::OpenFiles => "Load or remove files.",
+            Self::ShowLog => "Show past messages.",
+            Self::Back => "Back.",
+            Self::ZoomOut => "Zoom out.",
+            Self::ZoomIn => "Zoom in.",
+            Self::MoveLeft => "Move left.",
+            Self::MoveRight => "Move right.",
+            Self::MoveUp => "Move up.",
+            Self::MoveDown => "Move down.",
+            Self::RotateLeft => "Rotate left.",
+            Self::RotateRight => "Rotate right.",
+            Self::RotateUp => "Rotate up.",
+            Self::RotateDown => "Rotate down.",
+            Self::IncreaseCellAspect => "Make characters taller.",
+            Self::DecreaseCellAspect => "Make characters shorter.",
+            Self::SelectNext => "Select next light or list.",
+            Self::AddLight => "Add light.",
+            Self::Remove => "Remove light, pick or measurement.",
+            Self::CycleLightKind => "Cycle light type.",
+            Self::CycleColor => "Cycle colour.",
+            Self::IncreaseLightIntensity => "Brighten light.",
+            Self::DecreaseLightIntensity => "Dim light.",
+            Self::IncreaseAmbient => "Increase ambient.",
+            Self::DecreaseAmbient => "Decrease ambient.",
+            Self::IncreaseSpecular => "Increase specular strength.",
+            Self::DecreaseSpecular => "Decrease specular strength.",
+            Self::IncreaseShininess => "Increase shininess.",
+            Self::DecreaseShininess => "Decrease shininess.",
+            Self::MeasureDistance => "Measure distances.",
+            Self::MeasureAngle => "Measure angles.",
+            Self::MeasureDihedral => "Measure dihedrals.",
+            Self::ExportMeasurements => "Export measurements as CSV.",
+            Self::ToggleVisibility => "Show or hide.",
+            Self::ToggleExpanded => "Expand or collapse.",
+        }
+    }
+    pub fn group(&self) -> KeyGroup {
+        match self {
+            Self::ZoomOut
+            | Self::ZoomIn
+            | Self::MoveLeft
+            | Self::MoveRight
+            | Self::MoveUp
+            | Self::MoveDown => KeyGroup::Movement,
+            Self::RotateLeft | Self::RotateRight | Self::RotateUp | Self::RotateDown => {
+                KeyGroup::Rotation
+            }
+            Self::IncreaseCellAspect
+            | Self::DecreaseCellAspect
+            | Self::SelectNext
+            | Self::AddLight
+            | Self::Remove
+            | Self::CycleLightKind
+            | Self::CycleColor
+            | Self::IncreaseLightIntensity
+            | Self::DecreaseLightIntensity
+            | Self::IncreaseAmbient
+            | Self::DecreaseAmbient
+            | Self::IncreaseSpecular
+            | Self::DecreaseSpecular
+            | Self::IncreaseShininess
+            | Self::DecreaseShininess
+            | Self::MeasureDistance
+            | Self::MeasureAngle
+            | Self::MeasureDihedral
+            | Self::ExportMeasurements
+            | Self::ToggleVisibility
+            | Self::ToggleExpanded => KeyGroup::Contextual,
+            _ => KeyGroup::General,
+        }
+    }
+    /// Change to the lighting made by this action, for viewers that edit lights without a separate popup
+    pub fn lighting_edit(&self) -> Option<LightingEdit> {
+        Some(match self {
+            Self::SelectNext => LightingEdit::SelectNext,
+            Self::AddLight => LightingEdit::Add,
+            Self::Remove => LightingEdit::Remove,
+            Self::CycleLightKind => LightingEdit::CycleKind,
+            Self::CycleColor => LightingEdit::CycleColor,
+            Self::IncreaseLightIntensity => LightingEdit::Intensity(MINOR_LIGHTING_CHANGE),
+            Self::DecreaseLightIntensity => LightingEdit::Intensity(-MINOR_LIGHTING_CHANGE),
+            Self::IncreaseAmbient => LightingEdit::Ambient(MINOR_LIGHTING_CHANGE),
+            Self::DecreaseAmbient => LightingEdit::Ambient(-MINOR_LIGHTING_CHANGE),
+            Self::IncreaseSpecular => LightingEdit::Specular(MINOR_LIGHTING_CHANGE),
+            Self::DecreaseSpecular => LightingEdit::Specular(-MINOR_LIGHTING_CHANGE),
+            Self::IncreaseShininess => LightingEdit::Shininess(2.0),
+            Self::DecreaseShininess => LightingEdit::Shininess(0.5),
+            _ => return None,
+        })
+    }
+    /// Keys bound to the action when the config file doesn't say otherwise
+    fn default_keys(&self) -> Vec<KeyCode> {
+        let c = KeyCode::Char;
+        match self {
+            Self::Quit => vec![c('q')],
+            Self::Help => vec![c('?')],
+            Self::Benchmark => vec![c('b')],
+            Self::Save => vec![c('s')],
+            Self::CycleDithering => vec![c('g')],
+            Self::CycleSupersampling => vec![c('a')],
+            Self::CycleSamplePattern => vec![c('A')],
+            Self::ToggleAmbientOcclusion => vec![c('o')],
+            Self::ToggleDepthCueing => vec![c('f')],
+            Self::CycleShadows => vec![c('x')],
+            Self::ToggleToon => vec![c('T')],
+            Self::ToggleOutlines => vec![c('O')],
+            Self::Calibrate => vec![c('c')],
+            Self::EditLighting => vec![c('e')],
+            Self::Measure => vec![c('m')],
+            Self::ToggleSequence => vec![c('S')],
+            Self::BrowseStructure => vec![c('B')],
+            Self::OpenFiles => vec![c('F')],
+            Self::ShowLog => vec![c('N')],
+            Self::Back => vec![KeyCode::Esc],
+            Self::ZoomOut => vec![c('d')],
+            Self::ZoomIn => vec![c('u')],
+            Self::MoveLeft => vec![c('h'), KeyCode::Left],
+            Self::MoveRight => vec![c('l'), KeyCode::Right],
+            Self::MoveUp => vec![c('k'), KeyCode::Up],
+            Self::MoveDown => vec![c('j'), KeyCode::Down],
+            Self::RotateLeft => vec![c('H')],
+            Self::RotateRight => vec![c('L')],
+            Self::RotateUp => vec![c('K')],
+            Self::RotateDown => vec![c('J')],
+            Self::IncreaseCellAspect => vec![c('+'), c('=')],
+            Self::DecreaseCellAspect => vec![c('-')],
+            Self::SelectNext => vec![KeyCode::Tab],
+            Self::AddLight => vec![c('n')],
+            Self::Remove => vec![KeyCode::Backspace, KeyCode::Delete],
+            Self::CycleLightKind => vec![c('t')],
+            Self::CycleColor => vec![c('C')],
+            Self::IncreaseLightIntensity => vec![c(']')],
+            Self::DecreaseLightIntensity => vec![c('[')],
+            Self::IncreaseAmbient => vec![c('}')],
+            Self::DecreaseAmbient => vec![c('{')],
+            Self::IncreaseSpecular => vec![c('.')],
+            Self::DecreaseSpecular => vec![c(',')],
+            Self::IncreaseShininess => vec![c('>')],
+            Self::DecreaseShininess => vec![c('<')],
+            Self::MeasureDistance => vec![c('2')],
+            Self::MeasureAngle => vec![c('3')],
+            Self::MeasureDihedral => vec![c('4')],
+            Self::ExportMeasurements => vec![c('w')],
+            Self::ToggleVisibility => vec![c(' ')],
+            Self::ToggleExpanded => vec![KeyCode::Enter],
+        }
+    }
+}
+
+/// Read a key as written in the config file
+pub fn parse_key(text: &str) -> Option<KeyCode> {
+    let mut chars = text.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    KEY_NAMES
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(text))
+        .map(|(key, _)| *key)
+}
+
+/// Name of a key as written in the config file and help
+pub fn key_name(key: KeyCode) -> String {
+    match KEY_NAMES.iter().find(|(named, _)| *named == key) {
+        Some((_, name)) => name.to_string(),
+        None => match key {
+            KeyCode::Char(c) => c.to_string(),
+            _ => format!("{:?}", key),
+        },
+    }
+}
+
+/// Contents of the config file, where only key bindings are read so far
+#[derive(Deserialize, Debug, Default)]
+struct Config {
+    #[serde(default)]
+    keys: HashMap<String, Keys>,
+}
+
+/// One or more keys for an action
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Which action each key is bound to
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    actions: HashMap<KeyCode, KeyAction>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let actions = KeyAction::ALL
+            .iter()
+            .flat_map(|action| action.default_keys().into_iter().map(|key| (key, *action)))
+            .collect();
+        Self { actions }
+    }
+}
+
+impl Keymap {
+    /// Read the keymap from the config file, or use the defaults if there isn't one
+    pub fn from_config() -> Result<Self, KeymapError> {
+        match config_path() {
+            Some(path) if path.is_file() => Self::from_path(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+    pub fn from_path(path: &Path) -> Result<Self, KeymapError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| KeymapError::Read(path.to_path_buf(), error))?;
+        let config: Config =
+            toml::from_str(&text).map_err(|error| KeymapError::Parse(path.to_path_buf(), error))?;
+        Self::from_config_keys(config.keys)
+    }
+    /// Bind the keys given for each action in place of its default keys
+    /// Keys taken from other actions are no longer bound to them, but no key can be given to two actions
+    fn from_config_keys(keys: HashMap<String, Keys>) -> Result<Self, KeymapError> {
+        let mut keymap = Self::default();
+        // Sorted so that the same config is always read the same way
+        let mut keys: Vec<(String, Keys)> = keys.into_iter().collect();
+        keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut configured: HashMap<KeyCode, KeyAction> = HashMap::new();
+        for (name, keys) in keys {
+            let action =
+                KeyAction::from_name(&name).ok_or(KeymapError::UnknownAction(name.clone()))?;
+            let keys = match keys {
+                Keys::One(key) => vec![key],
+                Keys::Many(keys) => keys,
+            };
+            let keys = keys
+                .iter()
+                .map(|key| parse_key(key).ok_or(KeymapError::UnknownKey(key.clone())))
+                .collect::<Result<Vec<_>, _>>()?;
+            for key in keys.iter() {
+                match configured.insert(*key, action) {
+                    Some(other) if other != action => {
+                        return Err(KeymapError::Conflict(
+                            key_name(*key),
+                            other.name(),
+                            action.name(),
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            keymap.actions.retain(|_, bound| *bound != action);
+            keymap
+                .actions
+                .extend(keys.into_iter().map(|key| (key, action)));
+        }
+        Ok(keymap)
+    }
+    pub fn action(&self, key: KeyCode) -> Option<KeyAction> {
+        self.actions.get(&key).copied()
+    }
+    /// Keys bound to an action, in the order of `KEY_NAMES` then by character
+    pub fn keys(&self, action: KeyAction) -> Vec<KeyCode> {
+        let mut keys: Vec<KeyCode> = self
+            .actions
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| *key)
+            .collect();
+        keys.sort_by_key(|key| match key {
+            KeyCode::Char(c) if *c != ' ' => (0, *c as u32),
+            _ => (
+                1,
+                KEY_NAMES
+                    .iter()
+                    .position(|(named, _)| named == key)
+                    .unwrap_or(0) as u32,
+            ),
+        });
+        keys
+    }
+    /// Keys bound to any of some actions, such as `[ ]` for dimming and brightening a light
+    pub fn keys_text(&self, actions: &[KeyAction]) -> String {
+        let keys: Vec<String> = actions
+            .iter()
+            .flat_map(|action| self.keys(*action))
+            .map(key_name)
+            .collect();
+        keys.join(" ")
+    }
+    /// Line of help for some actions, with the keys bound to them before the description
+    pub fn help_line(&self, actions: &[KeyAction], description: &str) -> Line<'static> {
+        Line::from(format!(
+            "{:<7} {}",
+            format!("{}:", self.keys_text(actions)),
+            description
+        ))
+    }
+    /// Help for every action outside of the popups of particular states, with a gap between groups
+    pub fn help_lines(&self) -> Vec<Line<'static>> {
+        let mut lines = vec![];
+        let mut last_group = None;
+        for action in KeyAction::ALL {
+            let group = action.group();
+            if group == KeyGroup::Contextual || self.keys(action).is_empty() {
+                continue;
+            }
+            if last_group.is_some_and(|last| last != group) {
+                lines.push(Line::from(""));
+            }
+            last_group = Some(group);
+            lines.push(self.help_line(&[action], action.description()));
+        }
+        lines
+    }
+}
+
+/// `pdb-tui/config.toml` in the user's config directory, which is `~/.config` unless `XDG_CONFIG_HOME` is set
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+    Some(config_home.join("pdb-tui").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(parse_key("h"), Some(KeyCode::Char('h')));
+        assert_eq!(parse_key("<space>"), Some(KeyCode::Char(' ')));
+        assert_eq!(parse_key("<Esc>"), Some(KeyCode::Esc));
+        assert_eq!(parse_key("hj"), None);
+        for (key, _) in KEY_NAMES {
+            assert_eq!(parse_key(&key_name(key)), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = Keymap::default();
+        // Every action can be reached
+        for action in KeyAction::ALL {
+            assert!(!keymap.keys(action).is_empty(), "{}", action.name());
+            assert_eq!(KeyAction::from_name(action.name()), Some(action));
+        }
+        assert_eq!(keymap.action(KeyCode::Char('h')), Some(KeyAction::MoveLeft));
+        assert_eq!(keymap.keys_text(&[KeyAction::MoveLeft]), "h <Left>");
+        assert_eq!(
+            keymap.help_line(&[KeyAction::Quit], "Quit."),
+            Line::from("q:      Quit.")
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let text = r#"
+            [keys]
+            move_left = ["n", "<Left>"]
+            move_down = "e"
+        "#;
+        let config: Config = toml::from_str(text).unwrap();
+        let keymap = Keymap::from_config_keys(config.keys).unwrap();
+        assert_eq!(keymap.action(KeyCode::Char('n')), Some(KeyAction::MoveLeft));
+        assert_eq!(keymap.action(KeyCode::Char('h')), None);
+        assert_eq!(keymap.action(KeyCode::Char('e')), Some(KeyAction::MoveDown));
+        // `n` was taken from adding lights, and `e` from editing lighting
+        assert!(keymap.keys(KeyAction::AddLight).is_empty());
+        assert!(keymap.keys(KeyAction::EditLighting).is_empty());
+        assert!(!keymap
+            .help_lines()
+            .contains(&Line::from("e:      Edit lighting.")));
+
+        let config: Config = toml::from_str("[keys]\nfly = \"f\"").unwrap();
+        assert!(matches!(
+            Keymap::from_config_keys(config.keys),
+            Err(KeymapError::UnknownAction(name)) if name == "fly"
+        ));
+        let config: Config = toml::from_str("[keys]\nquit = \"<Hyper>\"").unwrap();
+        assert!(matches!(
+            Keymap::from_config_keys(config.keys),
+            Err(KeymapError::UnknownKey(key)) if key == "<Hyper>"
+        ));
+    }
+
+    #[test]
+    fn test_conflict() {
+        let text = r#"
+            [keys]
+            quit = "x"
+            move_left = ["h", "x"]
+        "#;
+        let config: Config = toml::from_str(text).unwrap();
+        assert!(matches!(
+            Keymap::from_config_keys(config.keys),
+            Err(KeymapError::Conflict(key, "move_left", "quit")) if key == "x"
+        ));
+    }
+}
@@ -1,3 +1,12 @@
+pub mod cell_size;
+pub mod command;
+pub mod file_picker;
+pub mod graphics;
+pub mod keymap;
+pub mod mouse;
+pub mod notification;
 pub mod popup;
+pub mod sequence_view;
 pub mod state;
+pub mod tree;
 pub mod ui;
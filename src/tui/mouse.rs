@@ -0,0 +1,264 @@
+//! Turning mouse drags and scrolling over the canvas into actions.
+//!
+//! Dragging with the left button rotates the shapes with an arcball, dragging with the middle button or with shift held
+//! pans the view, and scrolling zooms towards the shapes. Clicking without dragging picks whatever is under the mouse.
+
+use crate::{arcball, scene::Scene, surface::ValidShape, tui::ui::NextAction};
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use nalgebra::Point3;
+use parry3d::query::RayCast;
+use ratatui::{layout::Position, prelude::Rect};
+
+/// Fraction of the distance to the shapes moved by each step of the scroll wheel
+const ZOOM_FRACTION: f32 = 0.1;
+
+/// What the current drag does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DragKind {
+    Rotate,
+    Pan,
+}
+
+/// Tracks the last position of the mouse during a drag, since terminals only report where the mouse is now
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MouseDrag {
+    last: Option<(DragKind, u16, u16)>,
+    /// Whether the mouse has moved since the button was pressed, which tells drags apart from clicks
+    moved: bool,
+}
+
+impl MouseDrag {
+    /// Return the next action depending on the latest `MouseEvent`
+    /// The render area is where the canvas is drawn, so that the arcball is centred on it
+    pub fn next_action<S: RayCast + ValidShape>(
+        &mut self,
+        event: MouseEvent,
+        render_area: Rect,
+        scene: &Scene<S>,
+    ) -> NextAction {
+        let (column, row) = (event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(button) => {
+                let pan =
+                    button == MouseButton::Middle || event.modifiers.contains(KeyModifiers::SHIFT);
+                self.last = match button {
+                    MouseButton::Left | MouseButton::Middle if pan => {
+                        Some((DragKind::Pan, column, row))
+                    }
+                    MouseButton::Left => Some((DragKind::Rotate, column, row)),
+                    _ => None,
+                };
+                self.moved = false;
+                NextAction::Nothing
+            }
+            MouseEventKind::Drag(_) => {
+                let Some((kind, last_column, last_row)) = self.last else {
+                    return NextAction::Nothing;
+                };
+                if (last_column, last_row) == (column, row) {
+                    return NextAction::Nothing;
+                }
+                self.last = Some((kind, column, row));
+                self.moved = true;
+                let from = cell_to_clip(last_column, last_row, render_area);
+                let to = cell_to_clip(column, row, render_area);
+                match kind {
+                    DragKind::Rotate => rotate_action(from, to, scene),
+                    DragKind::Pan => pan_action(from, to, scene),
+                }
+            }
+            MouseEventKind::Up(_) => {
+                let clicked = matches!(self.last, Some((DragKind::Rotate, ..))) && !self.moved;
+                self.last = None;
+                let inside = render_area.contains(Position { x: column, y: row });
+                if clicked && inside {
+                    NextAction::Pick {
+                        column: (column - render_area.x) as usize,
+                        row: (row - render_area.y) as usize,
+                    }
+                } else {
+                    NextAction::Nothing
+                }
+            }
+            MouseEventKind::ScrollUp => zoom_action(ZOOM_FRACTION, scene),
+            MouseEventKind::ScrollDown => zoom_action(-ZOOM_FRACTION, scene),
+            _ => NextAction::Nothing,
+        }
+    }
+}
+
+/// Centre of a terminal cell in clip space, relative to the render area
+fn cell_to_clip(column: u16, row: u16, render_area: Rect) -> (f32, f32) {
+    let x = (column.saturating_sub(render_area.x) as f32 + 0.5) / render_area.width.max(1) as f32;
+    let y = (row.saturating_sub(render_area.y) as f32 + 0.5) / render_area.height.max(1) as f32;
+    (2.0 * x - 1.0, 1.0 - 2.0 * y)
+}
+
+/// Distance along the viewing direction to the centre of the shapes, which are kept at the origin
+fn target_depth<S: RayCast + ValidShape>(scene: &Scene<S>) -> f32 {
+    scene
+        .view_depth(&Point3::origin())
+        .max(scene.scene_projection.perspective.znear())
+}
+
+/// Roll the shapes with an arcball, converting the rotation from view space to world space
+fn rotate_action<S: RayCast + ValidShape>(
+    from: (f32, f32),
+    to: (f32, f32),
+    scene: &Scene<S>,
+) -> NextAction {
+    let aspect = scene.scene_projection.perspective.aspect();
+    let rotation = arcball::rotation(
+        arcball::clip_to_ball(from.0, from.1, aspect),
+        arcball::clip_to_ball(to.0, to.1, aspect),
+    );
+    let view_rotation = scene.view.rotation;
+    match (view_rotation.inverse() * rotation * view_rotation).axis_angle() {
+        Some((axis, angle)) => NextAction::Rotate {
+            axis: axis.into_inner(),
+            angle,
+        },
+        None => NextAction::Nothing,
+    }
+}
+
+/// Move the view so that the point under the mouse at the depth of the shapes follows the mouse
+fn pan_action<S: RayCast + ValidShape>(
+    from: (f32, f32),
+    to: (f32, f32),
+    scene: &Scene<S>,
+) -> NextAction {
+    let projection = &scene.scene_projection.perspective;
+    let half_height = target_depth(scene) * (projection.fovy() / 2.0).tan();
+    NextAction::Translate {
+        x: (to.0 - from.0) * half_height * projection.aspect(),
+        y: (to.1 - from.1) * half_height,
+        z: 0.0,
+    }
+}
+
+/// Move the shapes towards the camera by a fraction of their distance, so that zooming never passes through them
+fn zoom_action<S: RayCast + ValidShape>(fraction: f32, scene: &Scene<S>) -> NextAction {
+    NextAction::Translate {
+        x: 0.0,
+        y: 0.0,
+        z: fraction * target_depth(scene),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+    use parry3d::shape::TriMesh;
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16, modifiers: KeyModifiers) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn test_drags() {
+        let scene = Scene::<TriMesh>::default();
+        let area = Rect::new(0, 0, 80, 40);
+        let mut drag = MouseDrag::default();
+        let none = KeyModifiers::empty();
+
+        // Dragging without pressing first does nothing
+        let action = drag.next_action(
+            mouse(MouseEventKind::Drag(MouseButton::Left), 41, 20, none),
+            area,
+            &scene,
+        );
+        assert!(matches!(action, NextAction::Nothing));
+
+        drag.next_action(
+            mouse(MouseEventKind::Down(MouseButton::Left), 40, 20, none),
+            area,
+            &scene,
+        );
+        let action = drag.next_action(
+            mouse(MouseEventKind::Drag(MouseButton::Left), 45, 20, none),
+            area,
+            &scene,
+        );
+        // Horizontal drags rotate about the vertical axis
+        let NextAction::Rotate { axis, angle } = action else {
+            panic!("expected a rotation");
+        };
+        assert!(angle > 0.0);
+        assert!(axis.y.abs() > 0.99);
+
+        drag.next_action(
+            mouse(MouseEventKind::Up(MouseButton::Left), 45, 20, none),
+            area,
+            &scene,
+        );
+        drag.next_action(
+            mouse(
+                MouseEventKind::Down(MouseButton::Left),
+                40,
+                20,
+                KeyModifiers::SHIFT,
+            ),
+            area,
+            &scene,
+        );
+        let action = drag.next_action(
+            mouse(
+                MouseEventKind::Drag(MouseButton::Left),
+                45,
+                18,
+                KeyModifiers::SHIFT,
+            ),
+            area,
+            &scene,
+        );
+        // Panning follows the mouse
+        let NextAction::Translate { x, y, z } = action else {
+            panic!("expected a translation");
+        };
+        assert!(x > 0.0 && y > 0.0 && z == 0.0);
+
+        // Clicking without moving picks the cell under the mouse
+        let area = Rect::new(5, 2, 80, 40);
+        drag.next_action(
+            mouse(MouseEventKind::Down(MouseButton::Left), 40, 20, none),
+            area,
+            &scene,
+        );
+        let action = drag.next_action(
+            mouse(MouseEventKind::Up(MouseButton::Left), 40, 20, none),
+            area,
+            &scene,
+        );
+        assert!(matches!(
+            action,
+            NextAction::Pick {
+                column: 35,
+                row: 18
+            }
+        ));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let scene = Scene::<TriMesh>::default();
+        let area = Rect::new(0, 0, 80, 40);
+        let mut drag = MouseDrag::default();
+        let zoom = |kind, drag: &mut MouseDrag| match drag.next_action(
+            mouse(kind, 0, 0, KeyModifiers::empty()),
+            area,
+            &scene,
+        ) {
+            NextAction::Translate { z, .. } => z,
+            _ => panic!("expected a translation"),
+        };
+        assert!(zoom(MouseEventKind::ScrollUp, &mut drag) > 0.0);
+        assert!(zoom(MouseEventKind::ScrollDown, &mut drag) < 0.0);
+    }
+}
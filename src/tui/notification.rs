@@ -0,0 +1,196 @@
+//! Messages about things that happened in the background, such as saving a screenshot or problems reading a file.
+//!
+//! The latest messages are shown briefly on the bottom row, and every message is kept in a log that can be opened.
+
+use chrono::{DateTime, Local};
+use ratatui::{
+    prelude::{Buffer, Rect, Style, Stylize},
+    style::Color,
+    text::{Line, Span},
+    widgets::Widget,
+};
+use std::time::{Duration, Instant};
+
+/// How long messages stay on the bottom row
+pub const TOAST_DURATION: Duration = Duration::from_secs(4);
+/// Oldest messages are dropped beyond this, so that a file with many warnings can't fill up memory
+const MAX_LOG_LENGTH: usize = 1000;
+
+/// Ordered from least to most serious
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Info => Color::Green,
+            Self::Warning => Color::Yellow,
+            Self::Error => Color::Red,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+    pub time: DateTime<Local>,
+}
+
+impl Notification {
+    /// Line of the log, such as `12:30:05 error   Could not find 1abc.pdb.`
+    pub fn log_line(&self) -> Line<'static> {
+        Line::from(vec![
+            Span::raw(format!("{} ", self.time.format("%H:%M:%S"))),
+            Span::styled(
+                format!("{:<8}", self.level.name()),
+                Style::new().fg(self.level.color()),
+            ),
+            Span::raw(self.message.clone()),
+        ])
+    }
+}
+
+/// Every message so far, along with which of them are still being shown
+#[derive(Debug, Default)]
+pub struct Notifications {
+    log: Vec<Notification>,
+    /// Index of the first message shown on the bottom row, since messages arriving together are shown together
+    toast_start: usize,
+    /// When the latest message arrived, after which the bottom row is cleared once `TOAST_DURATION` has passed
+    last_shown: Option<Instant>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, level: Level, message: impl Into<String>) {
+        self.push_at(level, message.into(), Instant::now());
+    }
+    fn push_at(&mut self, level: Level, message: String, now: Instant) {
+        if self.toast_at(now).is_empty() {
+            self.toast_start = self.log.len();
+        }
+        self.log.push(Notification {
+            level,
+            message,
+            time: Local::now(),
+        });
+        self.last_shown = Some(now);
+        if self.log.len() > MAX_LOG_LENGTH {
+            let excess = self.log.len() - MAX_LOG_LENGTH;
+            self.log.drain(..excess);
+            self.toast_start = self.toast_start.saturating_sub(excess);
+        }
+    }
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(Level::Info, message);
+    }
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.push(Level::Warning, message);
+    }
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.push(Level::Error, message);
+    }
+    pub fn log(&self) -> &[Notification] {
+        &self.log[..]
+    }
+    /// Messages that arrived together and are still being shown, oldest first
+    pub fn toast(&self) -> &[Notification] {
+        self.toast_at(Instant::now())
+    }
+    fn toast_at(&self, now: Instant) -> &[Notification] {
+        match self.last_shown {
+            Some(shown) if now.duration_since(shown) < TOAST_DURATION => {
+                &self.log[self.toast_start..]
+            }
+            _ => &[],
+        }
+    }
+    /// How long until the bottom row should be cleared, or `None` if nothing is shown
+    pub fn time_left(&self) -> Option<Duration> {
+        let time_left = TOAST_DURATION.checked_sub(self.last_shown?.elapsed())?;
+        (!self.toast().is_empty()).then_some(time_left)
+    }
+    /// Stop showing the latest messages, keeping them in the log
+    pub fn dismiss(&mut self) {
+        self.last_shown = None;
+    }
+}
+
+/// Latest messages on the bottom row, where the worst of them decides the colour
+pub struct Toast<'a>(pub &'a Notifications);
+
+impl Widget for Toast<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let toast = self.0.toast();
+        let Some(latest) = toast.last() else {
+            return;
+        };
+        let worst = toast
+            .iter()
+            .map(|notification| notification.level)
+            .max()
+            .unwrap_or(latest.level);
+        let mut spans = vec![Span::raw(latest.message.clone())];
+        if toast.len() > 1 {
+            spans.push(Span::raw(format!(" (and {} more)", toast.len() - 1)).italic());
+        }
+        let line = Line::from(spans).style(Style::new().fg(worst.color()));
+        let width = (line.width() as u16).min(area.width);
+        buf.set_style(Rect { width, ..area }, Style::reset());
+        buf.set_line(area.x, area.y, &line, width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toast() {
+        let mut notifications = Notifications::default();
+        let start = Instant::now();
+        notifications.push_at(Level::Info, "first".to_string(), start);
+        assert_eq!(notifications.toast_at(start).len(), 1);
+
+        // Messages arriving while one is shown are shown along with it
+        let soon = start + TOAST_DURATION / 2;
+        notifications.push_at(Level::Warning, "second".to_string(), soon);
+        assert_eq!(notifications.toast_at(soon).len(), 2);
+        assert!(!notifications.toast_at(soon + TOAST_DURATION / 2).is_empty());
+
+        // Once they have gone, the next message is shown by itself but the log keeps everything
+        let later = soon + TOAST_DURATION;
+        assert!(notifications.toast_at(later).is_empty());
+        notifications.push_at(Level::Error, "third".to_string(), later);
+        let toast = notifications.toast_at(later);
+        assert_eq!(toast.len(), 1);
+        assert_eq!(toast[0].message, "third");
+        assert_eq!(notifications.log().len(), 3);
+
+        notifications.dismiss();
+        assert!(notifications.toast().is_empty());
+        assert_eq!(notifications.time_left(), None);
+    }
+
+    #[test]
+    fn test_log_length() {
+        let mut notifications = Notifications::default();
+        for i in 0..MAX_LOG_LENGTH + 10 {
+            notifications.info(i.to_string());
+        }
+        assert_eq!(notifications.log().len(), MAX_LOG_LENGTH);
+        assert_eq!(notifications.log()[0].message, "10");
+        assert_eq!(notifications.toast().len(), MAX_LOG_LENGTH);
+    }
+}
@@ -0,0 +1,412 @@
+//! Strip above the canvas showing the sequence of each chain, with its secondary structure underneath.
+//!
+//! Clicking or dragging along a sequence selects residues, which are highlighted in the canvas, and picking an atom in
+//! the canvas scrolls the strip to its residue.
+
+use crate::{
+    read::SecondaryStructure, scene::ObjectId, sequence::ChainSequence, tui::ui::NextAction,
+};
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{
+    layout::Position,
+    prelude::{Buffer, Rect, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+/// Most chains shown at once, each taking two rows
+const MAX_VISIBLE_CHAINS: usize = 3;
+/// Width of the chain name and residue number shown before each sequence
+const LABEL_WIDTH: u16 = 10;
+/// Residues left visible before a residue that is scrolled to
+const SCROLL_MARGIN: usize = 10;
+/// Residues moved by each step of the scroll wheel
+const SCROLL_STEP: isize = 10;
+
+/// Residues selected in a single chain, as indices into its sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceSelection {
+    pub chain: usize,
+    pub start: usize,
+    /// Last selected residue, which is never before `start`
+    pub end: usize,
+}
+
+impl SequenceSelection {
+    /// Select the residues between two residues of a chain, in either order
+    pub fn new(chain: usize, from: usize, to: usize) -> Self {
+        Self {
+            chain,
+            start: from.min(to),
+            end: from.max(to),
+        }
+    }
+    pub fn contains(&self, chain: usize, residue: usize) -> bool {
+        self.chain == chain && (self.start..=self.end).contains(&residue)
+    }
+}
+
+/// Where the strip is scrolled to and which residues are selected, kept when switching between states
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceCursor {
+    pub hidden: bool,
+    /// Index of the first chain shown
+    first_chain: usize,
+    /// Index of the first residue shown in every chain
+    offset: usize,
+    pub selection: Option<SequenceSelection>,
+}
+
+impl SequenceCursor {
+    /// Rows taken by the strip including its border, which is none if it is hidden or there are no sequences
+    pub fn height(&self, sequences: &[ChainSequence]) -> u16 {
+        if self.hidden || sequences.is_empty() {
+            0
+        } else {
+            2 * sequences.len().min(MAX_VISIBLE_CHAINS) as u16 + 2
+        }
+    }
+    /// Scroll along the sequences by a number of residues and down through them by a number of chains
+    pub fn scroll(&mut self, residues: isize, chains: isize, sequences: &[ChainSequence]) {
+        let longest = sequences
+            .iter()
+            .map(|s| s.residues.len())
+            .max()
+            .unwrap_or(0);
+        self.offset = self
+            .offset
+            .saturating_add_signed(residues)
+            .min(longest.saturating_sub(1));
+        self.first_chain = self
+            .first_chain
+            .saturating_add_signed(chains)
+            .min(sequences.len().saturating_sub(MAX_VISIBLE_CHAINS));
+    }
+    /// Scroll so that the residue an atom belongs to is shown, if it belongs to a sequence
+    pub fn scroll_to(&mut self, id: ObjectId, sequences: &[ChainSequence]) {
+        let Some((chain, residue)) = find_residue(id, sequences) else {
+            return;
+        };
+        if chain < self.first_chain {
+            self.first_chain = chain;
+        } else if chain >= self.first_chain + MAX_VISIBLE_CHAINS {
+            self.first_chain = chain + 1 - MAX_VISIBLE_CHAINS;
+        }
+        self.offset = residue.saturating_sub(SCROLL_MARGIN);
+    }
+    /// Chain shown in a row of the strip, which is drawn in `area`
+    fn chain_at(&self, row: u16, area: Rect, sequences: &[ChainSequence]) -> Option<usize> {
+        let chain = self.first_chain + row.checked_sub(area.y + 1)? as usize / 2;
+        (chain < sequences.len().min(self.first_chain + MAX_VISIBLE_CHAINS)).then_some(chain)
+    }
+    /// Residue of a chain shown in a column of the strip, where columns past either end of the sequence give the
+    /// residue at that end
+    fn residue_at(
+        &self,
+        column: u16,
+        chain: usize,
+        area: Rect,
+        sequences: &[ChainSequence],
+    ) -> Option<usize> {
+        let start = area.x + 1 + LABEL_WIDTH;
+        let last = sequences.get(chain)?.residues.len().checked_sub(1)?;
+        Some((self.offset + column.saturating_sub(start) as usize).min(last))
+    }
+}
+
+/// Chain and residue that an atom belongs to
+fn find_residue(id: ObjectId, sequences: &[ChainSequence]) -> Option<(usize, usize)> {
+    sequences
+        .iter()
+        .enumerate()
+        .find_map(|(chain, sequence)| Some((chain, sequence.residue_of(id)?)))
+}
+
+/// Tracks where a selection was started while dragging along the strip
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequenceDrag {
+    anchor: Option<(usize, usize)>,
+}
+
+impl SequenceDrag {
+    /// Whether a selection is being dragged out, in which case mouse events go to the strip wherever they are
+    pub fn is_dragging(&self) -> bool {
+        self.anchor.is_some()
+    }
+    /// Return the next action depending on the latest `MouseEvent` over the strip, which is drawn in `area`
+    pub fn next_action(
+        &mut self,
+        event: MouseEvent,
+        area: Rect,
+        cursor: &SequenceCursor,
+        sequences: &[ChainSequence],
+    ) -> NextAction {
+        let shift = event.modifiers.contains(KeyModifiers::SHIFT);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if event.column < area.x + 1 + LABEL_WIDTH || event.column + 1 >= area.right() {
+                    return NextAction::Nothing;
+                }
+                let Some(chain) = cursor.chain_at(event.row, area, sequences) else {
+                    return NextAction::Nothing;
+                };
+                let Some(residue) = cursor.residue_at(event.column, chain, area, sequences) else {
+                    return NextAction::Nothing;
+                };
+                // Shift-clicking extends the current selection
+                let anchor = match cursor.selection {
+                    Some(selection) if shift && selection.chain == chain => selection.start,
+                    _ => residue,
+                };
+                self.anchor = Some((chain, anchor));
+                NextAction::SelectResidues {
+                    chain,
+                    start: anchor,
+                    end: residue,
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some((chain, anchor)) = self.anchor else {
+                    return NextAction::Nothing;
+                };
+                // Dragging stays in the chain it started in
+                match cursor.residue_at(event.column, chain, area, sequences) {
+                    Some(end) => NextAction::SelectResidues {
+                        chain,
+                        start: anchor,
+                        end,
+                    },
+                    None => NextAction::Nothing,
+                }
+            }
+            MouseEventKind::Up(_) => {
+                self.anchor = None;
+                NextAction::Nothing
+            }
+            MouseEventKind::ScrollDown if shift => NextAction::ScrollSequence {
+                residues: 0,
+                chains: 1,
+            },
+            MouseEventKind::ScrollUp if shift => NextAction::ScrollSequence {
+                residues: 0,
+                chains: -1,
+            },
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollRight => {
+                NextAction::ScrollSequence {
+                    residues: SCROLL_STEP,
+                    chains: 0,
+                }
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollLeft => NextAction::ScrollSequence {
+                residues: -SCROLL_STEP,
+                chains: 0,
+            },
+            _ => NextAction::Nothing,
+        }
+    }
+    /// Whether an event should go to the strip rather than the canvas
+    pub fn wants(&self, event: &MouseEvent, area: Rect) -> bool {
+        self.is_dragging()
+            || area.contains(Position {
+                x: event.column,
+                y: event.row,
+            })
+    }
+}
+
+/// Widget drawing the strip, marking the selected residues and the residue of the picked atom
+pub struct SequencePanel<'a> {
+    pub sequences: &'a [ChainSequence],
+    pub cursor: SequenceCursor,
+    pub picked: Option<ObjectId>,
+}
+
+impl SequencePanel<'_> {
+    fn lines(&self, width: usize) -> Vec<Line<'static>> {
+        let picked = self.picked.and_then(|id| find_residue(id, self.sequences));
+        let visible = width.saturating_sub(LABEL_WIDTH as usize);
+        self.sequences
+            .iter()
+            .enumerate()
+            .skip(self.cursor.first_chain)
+            .take(MAX_VISIBLE_CHAINS)
+            .flat_map(|(chain, sequence)| {
+                let shown = sequence
+                    .residues
+                    .iter()
+                    .enumerate()
+                    .skip(self.cursor.offset)
+                    .take(visible);
+                let number = sequence
+                    .residues
+                    .get(self.cursor.offset)
+                    .map_or_else(String::new, |residue| residue.number.to_string());
+                // Matches `LABEL_WIDTH`
+                let label = format!("{:<3.3}{:>6} ", sequence.chain, number);
+                let mut codes = vec![Span::styled(label, Style::new().bold())];
+                let mut structure = vec![Span::raw(" ".repeat(LABEL_WIDTH as usize))];
+                for (i, residue) in shown {
+                    let mut style = Style::new();
+                    if self
+                        .cursor
+                        .selection
+                        .is_some_and(|selection| selection.contains(chain, i))
+                    {
+                        style = style.black().on_yellow();
+                    }
+                    if picked == Some((chain, i)) {
+                        style = style.bold().underlined();
+                    }
+                    codes.push(Span::styled(residue.code.to_string(), style));
+                    let structure_style = match residue.secondary_structure {
+                        SecondaryStructure::Helix => Style::new().red(),
+                        SecondaryStructure::Strand => Style::new().yellow(),
+                        SecondaryStructure::Coil => Style::new().dark_gray(),
+                    };
+                    structure.push(Span::styled(
+                        residue.secondary_structure.symbol().to_string(),
+                        structure_style,
+                    ));
+                }
+                [Line::from(codes), Line::from(structure)]
+            })
+            .collect()
+    }
+    /// Title naming the selected residues, if there are any
+    fn title(&self) -> String {
+        let selected = self.cursor.selection.and_then(|selection| {
+            let sequence = self.sequences.get(selection.chain)?;
+            let start = sequence.residues.get(selection.start)?;
+            let end = sequence.residues.get(selection.end)?;
+            Some(format!(
+                "Sequence: {} {}-{}",
+                sequence.chain, start.number, end.number
+            ))
+        });
+        selected.unwrap_or_else(|| "Sequence".to_string())
+    }
+}
+
+impl Widget for SequencePanel<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Clear.render(area, buf);
+        let block = Block::new()
+            .title(self.title())
+            .title_style(Style::new().bold())
+            .borders(Borders::ALL)
+            .border_style(Style::new().red());
+        Paragraph::new(self.lines(area.width.saturating_sub(2) as usize))
+            .block(block)
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::SequenceResidue;
+
+    fn sequences(lengths: &[usize]) -> Vec<ChainSequence> {
+        lengths
+            .iter()
+            .enumerate()
+            .map(|(shape, &length)| ChainSequence {
+                shape,
+                chain: ((b'A' + shape as u8) as char).to_string(),
+                residues: (0..length)
+                    .map(|i| SequenceResidue {
+                        number: i as isize + 1,
+                        name: "GLY".to_string(),
+                        code: 'G',
+                        secondary_structure: SecondaryStructure::Coil,
+                        atoms: vec![i as u32],
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::empty(),
+        }
+    }
+
+    #[test]
+    fn test_scroll_to() {
+        let sequences = sequences(&[50, 50, 50, 50, 50]);
+        let mut cursor = SequenceCursor::default();
+        assert_eq!(cursor.height(&sequences), 8);
+        assert_eq!(cursor.height(&[]), 0);
+
+        let id = ObjectId {
+            shape: 4,
+            sub_shape: Some(30),
+        };
+        cursor.scroll_to(id, &sequences);
+        assert_eq!((cursor.first_chain, cursor.offset), (2, 20));
+        cursor.scroll(100, -5, &sequences);
+        assert_eq!((cursor.first_chain, cursor.offset), (0, 49));
+    }
+
+    #[test]
+    fn test_drag_selection() {
+        let sequences = sequences(&[50, 20]);
+        let cursor = SequenceCursor::default();
+        let area = Rect::new(0, 0, 80, cursor.height(&sequences));
+        let mut drag = SequenceDrag::default();
+        let start = 1 + LABEL_WIDTH;
+
+        // Clicking on the labels or border selects nothing
+        let action = drag.next_action(
+            mouse(MouseEventKind::Down(MouseButton::Left), 5, 0),
+            area,
+            &cursor,
+            &sequences,
+        );
+        assert!(matches!(action, NextAction::Nothing));
+
+        // The second chain starts on the third row inside the border
+        let action = drag.next_action(
+            mouse(MouseEventKind::Down(MouseButton::Left), start + 3, 3),
+            area,
+            &cursor,
+            &sequences,
+        );
+        assert!(matches!(
+            action,
+            NextAction::SelectResidues {
+                chain: 1,
+                start: 3,
+                end: 3
+            }
+        ));
+        assert!(drag.is_dragging());
+
+        // Dragging past the end of the chain stops at its last residue
+        let action = drag.next_action(
+            mouse(MouseEventKind::Drag(MouseButton::Left), start + 40, 10),
+            area,
+            &cursor,
+            &sequences,
+        );
+        assert!(matches!(
+            action,
+            NextAction::SelectResidues {
+                chain: 1,
+                start: 3,
+                end: 19
+            }
+        ));
+        drag.next_action(
+            mouse(MouseEventKind::Up(MouseButton::Left), 0, 0),
+            area,
+            &cursor,
+            &sequences,
+        );
+        assert!(!drag.is_dragging());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::{scene::ObjectId, tui::sequence_view::SequenceCursor};
+
 /// Marker trait used for managing valid state of UI
 pub trait StateMarker {}
 
@@ -10,13 +12,41 @@ pub struct HelpState;
 #[derive(Default, Debug, Clone, Copy)]
 pub struct BenchmarkState;
 
+#[derive(Default, Debug, Clone, Copy)]
+pub struct CalibrationState;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct LightingState;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MeasuringState;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct BrowsingState;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct FilesState;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct LogState;
+
 impl StateMarker for HelpState {}
 impl StateMarker for RenderState {}
 impl StateMarker for BenchmarkState {}
+impl StateMarker for CalibrationState {}
+impl StateMarker for LightingState {}
+impl StateMarker for MeasuringState {}
+impl StateMarker for BrowsingState {}
+impl StateMarker for FilesState {}
+impl StateMarker for LogState {}
 
 #[derive(Default, Debug, Clone, Copy)]
 pub struct App<S: StateMarker> {
     pub should_quit: bool,
+    /// Object picked by clicking on it, kept when switching between states
+    pub selected: Option<ObjectId>,
+    /// Where the sequence strip is scrolled to and which residues are selected
+    pub sequence: SequenceCursor,
 
     state: std::marker::PhantomData<S>,
 }
@@ -25,6 +55,8 @@ impl From<App<HelpState>> for App<RenderState> {
     fn from(value: App<HelpState>) -> Self {
         Self {
             should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
             state: std::marker::PhantomData::<RenderState>,
         }
     }
@@ -34,6 +66,8 @@ impl From<App<RenderState>> for App<HelpState> {
     fn from(value: App<RenderState>) -> Self {
         Self {
             should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
             state: std::marker::PhantomData::<HelpState>,
         }
     }
@@ -43,6 +77,8 @@ impl From<App<BenchmarkState>> for App<RenderState> {
     fn from(value: App<BenchmarkState>) -> Self {
         Self {
             should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
             state: std::marker::PhantomData::<RenderState>,
         }
     }
@@ -52,7 +88,141 @@ impl From<App<RenderState>> for App<BenchmarkState> {
     fn from(value: App<RenderState>) -> Self {
         Self {
             should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
             state: std::marker::PhantomData::<BenchmarkState>,
         }
     }
 }
+
+impl From<App<CalibrationState>> for App<RenderState> {
+    fn from(value: App<CalibrationState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<RenderState>,
+        }
+    }
+}
+
+impl From<App<RenderState>> for App<CalibrationState> {
+    fn from(value: App<RenderState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<CalibrationState>,
+        }
+    }
+}
+
+impl From<App<LightingState>> for App<RenderState> {
+    fn from(value: App<LightingState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<RenderState>,
+        }
+    }
+}
+
+impl From<App<RenderState>> for App<LightingState> {
+    fn from(value: App<RenderState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<LightingState>,
+        }
+    }
+}
+
+impl From<App<MeasuringState>> for App<RenderState> {
+    fn from(value: App<MeasuringState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<RenderState>,
+        }
+    }
+}
+
+impl From<App<RenderState>> for App<MeasuringState> {
+    fn from(value: App<RenderState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<MeasuringState>,
+        }
+    }
+}
+
+impl From<App<BrowsingState>> for App<RenderState> {
+    fn from(value: App<BrowsingState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<RenderState>,
+        }
+    }
+}
+
+impl From<App<RenderState>> for App<BrowsingState> {
+    fn from(value: App<RenderState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<BrowsingState>,
+        }
+    }
+}
+
+impl From<App<FilesState>> for App<RenderState> {
+    fn from(value: App<FilesState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<RenderState>,
+        }
+    }
+}
+
+impl From<App<RenderState>> for App<FilesState> {
+    fn from(value: App<RenderState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<FilesState>,
+        }
+    }
+}
+
+impl From<App<LogState>> for App<RenderState> {
+    fn from(value: App<LogState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<RenderState>,
+        }
+    }
+}
+
+impl From<App<RenderState>> for App<LogState> {
+    fn from(value: App<RenderState>) -> Self {
+        Self {
+            should_quit: value.should_quit,
+            selected: value.selected,
+            sequence: value.sequence,
+            state: std::marker::PhantomData::<LogState>,
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! Collapsible tree of the structures in the scene, going from file to model to chain to residue.
+//!
+//! Every node can be shown or hidden and recoloured, acting on the shape made for each chain or on the atoms of a
+//! residue.
+
+use crate::{
+    scene::{next_palette_color, ObjectId, Scene},
+    surface::ValidShape,
+};
+use parry3d::query::RayCast;
+use ratatui::{
+    prelude::{Buffer, Rect, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Node of the tree, identified by what it stands for rather than where it is in the tree
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TreeNode {
+    File(String),
+    Model {
+        file: String,
+        model: usize,
+    },
+    /// Chain as an index into the sequences of the scene
+    Chain(usize),
+    /// Residue as an index into the sequence of its chain
+    Residue {
+        chain: usize,
+        residue: usize,
+    },
+}
+
+/// Node as shown in the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub node: TreeNode,
+    pub depth: usize,
+    pub expanded: bool,
+}
+
+impl TreeRow {
+    fn expandable(&self) -> bool {
+        !matches!(self.node, TreeNode::Residue { .. })
+    }
+}
+
+/// Which nodes are expanded and where the cursor is
+#[derive(Debug, Clone)]
+pub struct TreeView {
+    expanded: HashSet<TreeNode>,
+    /// Index of the row under the cursor
+    cursor: usize,
+}
+
+impl TreeView {
+    /// Start with the files and models expanded, so that every chain is shown
+    pub fn new<S: RayCast + ValidShape>(scene: &Scene<S>) -> Self {
+        let mut expanded = HashSet::new();
+        for chain in 0..scene.sequences().len() {
+            if let Some((file, model)) = parents(scene, chain) {
+                expanded.insert(file);
+                expanded.insert(model);
+            }
+        }
+        Self {
+            expanded,
+            cursor: 0,
+        }
+    }
+    /// Every row of the tree that isn't inside a collapsed node, in order
+    pub fn rows<S: RayCast + ValidShape>(&self, scene: &Scene<S>) -> Vec<TreeRow> {
+        let mut rows: Vec<TreeRow> = vec![];
+        let push = |rows: &mut Vec<TreeRow>, node: TreeNode, depth: usize| {
+            let expanded = self.expanded.contains(&node);
+            rows.push(TreeRow {
+                node,
+                depth,
+                expanded,
+            });
+        };
+        let is_open = |rows: &[TreeRow], node: &TreeNode| {
+            rows.iter().any(|row| row.node == *node && row.expanded)
+        };
+        // Chains are grouped by file and model in the order they were read
+        let mut seen = HashSet::new();
+        for chain in 0..scene.sequences().len() {
+            let Some((file, model)) = parents(scene, chain) else {
+                continue;
+            };
+            if seen.insert(file.clone()) {
+                push(&mut rows, file.clone(), 0);
+            }
+            if !is_open(&rows, &file) {
+                continue;
+            }
+            if seen.insert(model.clone()) {
+                push(&mut rows, model.clone(), 1);
+            }
+            if !is_open(&rows, &model) {
+                continue;
+            }
+            push(&mut rows, TreeNode::Chain(chain), 2);
+            if self.expanded.contains(&TreeNode::Chain(chain)) {
+                for residue in 0..scene.sequences()[chain].residues.len() {
+                    push(&mut rows, TreeNode::Residue { chain, residue }, 3);
+                }
+            }
+        }
+        rows
+    }
+    fn current<S: RayCast + ValidShape>(&self, scene: &Scene<S>) -> Option<TreeRow> {
+        self.rows(scene).into_iter().nth(self.cursor)
+    }
+    /// Move the cursor up or down by a number of rows
+    pub fn move_cursor<S: RayCast + ValidShape>(&mut self, delta: isize, scene: &Scene<S>) {
+        let last = self.rows(scene).len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+    /// Expand the node under the cursor, or collapse it if it is already expanded
+    pub fn toggle_expanded<S: RayCast + ValidShape>(&mut self, scene: &Scene<S>) {
+        if let Some(row) = self.current(scene).filter(TreeRow::expandable) {
+            if !self.expanded.remove(&row.node) {
+                self.expanded.insert(row.node);
+            }
+        }
+    }
+    pub fn expand<S: RayCast + ValidShape>(&mut self, scene: &Scene<S>) {
+        if let Some(row) = self.current(scene).filter(TreeRow::expandable) {
+            self.expanded.insert(row.node);
+        }
+    }
+    /// Collapse the node under the cursor, or move up to its parent if it isn't expanded
+    pub fn collapse<S: RayCast + ValidShape>(&mut self, scene: &Scene<S>) {
+        let rows = self.rows(scene);
+        let Some(row) = rows.get(self.cursor) else {
+            return;
+        };
+        if row.expanded {
+            self.expanded.remove(&row.node);
+        } else if let Some(parent) = rows[..self.cursor]
+            .iter()
+            .rposition(|parent| parent.depth < row.depth)
+        {
+            self.cursor = parent;
+        }
+    }
+    /// Expand the nodes down to the residue that an atom belongs to, and move the cursor to it
+    pub fn reveal<S: RayCast + ValidShape>(&mut self, id: ObjectId, scene: &Scene<S>) {
+        let found = scene
+            .sequences()
+            .iter()
+            .enumerate()
+            .find_map(|(chain, sequence)| Some((chain, sequence.residue_of(id)?)));
+        let Some((chain, residue)) = found else {
+            return;
+        };
+        if let Some((file, model)) = parents(scene, chain) {
+            self.expanded.extend([file, model, TreeNode::Chain(chain)]);
+        }
+        let target = TreeNode::Residue { chain, residue };
+        if let Some(row) = self.rows(scene).iter().position(|row| row.node == target) {
+            self.cursor = row;
+        }
+    }
+    /// Show the node under the cursor if any of it is hidden, otherwise hide it
+    pub fn toggle_visibility<S: RayCast + ValidShape>(&self, scene: &mut Scene<S>) {
+        let Some(row) = self.current(scene) else {
+            return;
+        };
+        let atoms = atoms(scene, &row.node);
+        let show = !atoms.iter().all(|id| scene.visibility().is_visible(*id));
+        if show {
+            for chain in chains(scene, &row.node) {
+                scene.set_visible(chain_id(scene, chain), true);
+            }
+            for id in atoms {
+                scene.set_visible(id, true);
+            }
+        } else if matches!(row.node, TreeNode::Residue { .. }) {
+            for id in atoms {
+                scene.set_visible(id, false);
+            }
+        } else {
+            // Hiding whole shapes means rays skip them without looking at their atoms
+            for chain in chains(scene, &row.node) {
+                scene.set_visible(chain_id(scene, chain), false);
+            }
+        }
+    }
+    /// Give the node under the cursor the next colour in the palette
+    pub fn cycle_color<S: RayCast + ValidShape>(&self, scene: &mut Scene<S>) {
+        let Some(row) = self.current(scene) else {
+            return;
+        };
+        let atoms = atoms(scene, &row.node);
+        let Some(first) = atoms.first() else {
+            return;
+        };
+        let color = next_palette_color(scene.color_of(*first));
+        match row.node {
+            TreeNode::Residue { .. } => {
+                for id in atoms {
+                    scene.set_color(id, color);
+                }
+            }
+            _ => {
+                for chain in chains(scene, &row.node) {
+                    scene.set_color(chain_id(scene, chain), color);
+                }
+            }
+        }
+    }
+}
+
+/// File and model nodes that a chain is inside, if its shape was read from a file
+fn parents<S: RayCast + ValidShape>(
+    scene: &Scene<S>,
+    chain: usize,
+) -> Option<(TreeNode, TreeNode)> {
+    let source = scene.source(scene.sequences().get(chain)?.shape)?;
+    Some((
+        TreeNode::File(source.file.clone()),
+        TreeNode::Model {
+            file: source.file.clone(),
+            model: source.model,
+        },
+    ))
+}
+
+/// Chains inside a node, or the chain of a residue
+fn chains<S: RayCast + ValidShape>(scene: &Scene<S>, node: &TreeNode) -> Vec<usize> {
+    (0..scene.sequences().len())
+        .filter(|&chain| match node {
+            TreeNode::Chain(c) | TreeNode::Residue { chain: c, .. } => *c == chain,
+            TreeNode::File(_) | TreeNode::Model { .. } => {
+                parents(scene, chain).is_some_and(|(file, model)| file == *node || model == *node)
+            }
+        })
+        .collect()
+}
+
+/// Whole shape of a chain
+fn chain_id<S: RayCast + ValidShape>(scene: &Scene<S>, chain: usize) -> ObjectId {
+    ObjectId {
+        shape: scene.sequences()[chain].shape,
+        sub_shape: None,
+    }
+}
+
+/// Every atom inside a node
+fn atoms<S: RayCast + ValidShape>(scene: &Scene<S>, node: &TreeNode) -> Vec<ObjectId> {
+    match node {
+        TreeNode::Residue { chain, residue } => {
+            scene.sequences()[*chain].object_ids(*residue..=*residue)
+        }
+        _ => chains(scene, node)
+            .into_iter()
+            .flat_map(|chain| {
+                let sequence = &scene.sequences()[chain];
+                sequence.object_ids(0..=sequence.residues.len().saturating_sub(1))
+            })
+            .collect(),
+    }
+}
+
+/// Widget drawing the rows of the tree that fit, scrolled to keep the cursor in view
+pub struct TreePanel<'a, S: RayCast + ValidShape> {
+    pub view: &'a TreeView,
+    pub scene: &'a Scene<S>,
+}
+
+impl<S: RayCast + ValidShape> TreePanel<'_, S> {
+    fn line(&self, row: &TreeRow, selected: bool) -> Line<'static> {
+        let scene = self.scene;
+        let marker = match (row.expandable(), row.expanded) {
+            (false, _) => ' ',
+            (true, false) => '▸',
+            (true, true) => '▾',
+        };
+        let atoms = atoms(scene, &row.node);
+        let visible = atoms
+            .iter()
+            .filter(|id| scene.visibility().is_visible(**id))
+            .count();
+        let visibility = match visible {
+            0 => '○',
+            n if n == atoms.len() => '●',
+            _ => '◐',
+        };
+        let color = atoms
+            .first()
+            .map_or(ratatui::style::Color::Reset, |id| scene.color_of(*id));
+        let name = match &row.node {
+            TreeNode::File(file) => Path::new(file)
+                .file_name()
+                .map_or_else(|| file.clone(), |name| name.to_string_lossy().into_owned()),
+            TreeNode::Model { model, .. } => format!("Model {}", model),
+            TreeNode::Chain(chain) => {
+                let sequence = &scene.sequences()[*chain];
+                format!("Chain {} ({})", sequence.chain, sequence.residues.len())
+            }
+            TreeNode::Residue { chain, residue } => {
+                let residue = &scene.sequences()[*chain].residues[*residue];
+                format!("{} {}", residue.name, residue.number)
+            }
+        };
+        let style = if selected {
+            Style::new().reversed()
+        } else {
+            Style::new()
+        };
+        Line::from(vec![
+            Span::raw(format!("{}{} ", "  ".repeat(row.depth), marker)),
+            Span::styled(visibility.to_string(), Style::new().fg(color)),
+            Span::styled(format!(" {}", name), style),
+        ])
+    }
+}
+
+impl<S: RayCast + ValidShape> Widget for TreePanel<'_, S> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Clear.render(area, buf);
+        let rows = self.view.rows(self.scene);
+        let height = area.height.saturating_sub(2) as usize;
+        let first = (self.view.cursor + 1).saturating_sub(height);
+        let mut lines: Vec<Line> = rows
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .map(|(i, row)| self.line(row, i == self.view.cursor))
+            .collect();
+        if rows.is_empty() {
+            lines.push(Line::from("No structures."));
+        }
+        let block = Block::new()
+            .title("Structure")
+            .title_style(Style::new().bold())
+            .borders(Borders::ALL)
+            .border_style(Style::new().red());
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read::AtomInfo, scene::PALETTE};
+    use nalgebra::{Isometry3, Point3};
+    use parry3d::shape::{Ball, Compound, SharedShape};
+    use std::sync::Arc;
+
+    /// Scene with two chains of two residues each, with two atoms in every residue
+    fn scene() -> Scene<Compound> {
+        let mut scene = Scene::<Compound>::default();
+        for chain in ["A", "B"] {
+            let atoms: Vec<AtomInfo> = (0..4)
+                .map(|i| {
+                    AtomInfo::test(
+                        chain,
+                        ("GLY", i as isize / 2 + 1),
+                        if i % 2 == 0 { "N" } else { "CA" },
+                        Point3::new(2.0 * i as f32, 0.0, 0.0),
+                    )
+                })
+                .collect();
+            let balls = atoms
+                .iter()
+                .map(|atom| {
+                    (
+                        Isometry3::from(atom.position.coords),
+                        SharedShape(Arc::new(Ball::new(1.0))),
+                    )
+                })
+                .collect();
+            scene.add_pdb_shapes("data/test.pdb", vec![(1, Compound::new(balls), atoms)]);
+        }
+        scene.recolor();
+        scene
+    }
+
+    fn names(view: &TreeView, scene: &Scene<Compound>) -> Vec<TreeNode> {
+        view.rows(scene).into_iter().map(|row| row.node).collect()
+    }
+
+    #[test]
+    fn test_rows() {
+        let scene = scene();
+        let mut view = TreeView::new(&scene);
+        let file = TreeNode::File("data/test.pdb".to_string());
+        let model = TreeNode::Model {
+            file: "data/test.pdb".to_string(),
+            model: 1,
+        };
+        assert_eq!(
+            names(&view, &scene),
+            vec![
+                file.clone(),
+                model.clone(),
+                TreeNode::Chain(0),
+                TreeNode::Chain(1)
+            ]
+        );
+
+        view.move_cursor(2, &scene);
+        view.expand(&scene);
+        assert_eq!(view.rows(&scene).len(), 6);
+        view.move_cursor(1, &scene);
+        assert_eq!(
+            view.current(&scene).unwrap().node,
+            TreeNode::Residue {
+                chain: 0,
+                residue: 0
+            }
+        );
+        // Collapsing a residue moves up to its chain, then collapses the chain
+        view.collapse(&scene);
+        view.collapse(&scene);
+        assert_eq!(view.rows(&scene).len(), 4);
+
+        let id = ObjectId {
+            shape: 1,
+            sub_shape: Some(3),
+        };
+        view.reveal(id, &scene);
+        assert_eq!(
+            view.current(&scene).unwrap().node,
+            TreeNode::Residue {
+                chain: 1,
+                residue: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_visibility_and_color() {
+        let mut scene = scene();
+        let mut view = TreeView::new(&scene);
+        let atom = |shape, sub_shape| ObjectId {
+            shape,
+            sub_shape: Some(sub_shape),
+        };
+
+        // Hiding the model hides both chains
+        view.move_cursor(1, &scene);
+        view.toggle_visibility(&mut scene);
+        assert!(!scene.visibility().is_visible(atom(0, 0)));
+        assert!(!scene.visibility().is_visible(atom(1, 3)));
+        view.toggle_visibility(&mut scene);
+        assert!(scene.visibility().is_visible(atom(1, 3)));
+
+        // Hiding a residue only hides its atoms
+        view.move_cursor(1, &scene);
+        view.expand(&scene);
+        view.move_cursor(2, &scene);
+        view.toggle_visibility(&mut scene);
+        assert!(scene.visibility().is_visible(atom(0, 1)));
+        assert!(!scene.visibility().is_visible(atom(0, 2)));
+        assert!(!scene.visibility().is_visible(atom(0, 3)));
+
+        view.cycle_color(&mut scene);
+        assert_eq!(scene.color_of(atom(0, 2)), PALETTE[1]);
+        assert_eq!(scene.color_of(atom(0, 1)), PALETTE[0]);
+        // Colouring the chain replaces the colour of the residue
+        view.move_cursor(-2, &scene);
+        view.cycle_color(&mut scene);
+        assert_eq!(scene.color_of(atom(0, 1)), PALETTE[1]);
+        assert_eq!(scene.color_of(atom(0, 2)), PALETTE[1]);
+    }
+}
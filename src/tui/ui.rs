@@ -4,23 +4,36 @@
 #[allow(unused_imports)]
 use crate::{
     basic_rasterizer::BasicAsciiRasterizer,
+    lighting::{Light, LightingEdit},
+    measurement::{self, Measurement, MeasurementKind},
     rasterizer::{ColoredChar, Rasterizer},
     render::Canvas,
-    scene::Scene,
+    scene::{LoadShapes, ObjectId, Scene},
+    selection::Selection,
     surface::ValidShape,
     tui::{
+        command::{CommandLine, CommandPrompt},
+        file_picker::{FilePicker, FilePickerPopup, PickerFocus},
+        graphics::{GraphicsBackend, GraphicsRasterizer, OutputMode},
+        keymap::{key_name, KeyAction, Keymap},
+        mouse::MouseDrag,
+        notification::{Notifications, Toast},
         popup::Popup,
-        state::{App, BenchmarkState, HelpState, RenderState},
+        sequence_view::{SequenceCursor, SequenceDrag, SequencePanel, SequenceSelection},
+        state::{
+            App, BenchmarkState, BrowsingState, CalibrationState, FilesState, HelpState,
+            LightingState, LogState, MeasuringState, RenderState,
+        },
+        tree::{TreePanel, TreeView},
     },
 };
-use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+use nalgebra::{Isometry3, Rotation3, Translation3, UnitQuaternion, Vector3};
 
 use chrono::{DateTime, Local};
 use crossterm::{
-    event::{self, KeyCode, KeyEvent, KeyEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, KeyEvent, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
 };
 #[allow(unused_imports)]
 use parry3d::{
@@ -29,141 +42,376 @@ use parry3d::{
 };
 // TODO Consider just importing everything from `prelude` and `widgets`
 use ratatui::{
-    prelude::{CrosstermBackend, Frame, Rect, Style, Stylize, Terminal},
+    layout::Position,
+    prelude::{Buffer, CrosstermBackend, Frame, Rect, Style, Stylize, Terminal},
     text::{Line, Span, Text},
     widgets::{Paragraph, Widget},
 };
 use std::io::{stdout, Result};
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// The possible things that will happen after an action
 pub enum NextAction {
-    Translate { x: f32, y: f32, z: f32 },
-    Rotate { axis: Vector3<f32>, angle: f32 },
+    Translate {
+        x: f32,
+        y: f32,
+        z: f32,
+    },
+    Rotate {
+        axis: Vector3<f32>,
+        angle: f32,
+    },
+    /// Select whatever is shown in a cell of the canvas
+    Pick {
+        column: usize,
+        row: usize,
+    },
     Quit,
     Save,
+    /// Save a screenshot to a chosen path rather than a timestamped one
+    SaveAs {
+        path: String,
+    },
+    /// Add the shapes in a file to the scene
+    Load {
+        path: String,
+    },
     Nothing,
     Help,
     Back,
     Benchmark,
+    CycleDithering,
+    CycleSupersampling,
+    CycleSamplePattern,
+    ToggleAmbientOcclusion,
+    ToggleDepthCueing,
+    CycleShadows,
+    ToggleToon,
+    ToggleOutlines,
+    Calibrate,
+    AdjustCellAspect {
+        delta: f32,
+    },
+    EditLighting,
+    SelectNext,
+    /// Change the light being edited or how surfaces respond to light
+    Light(LightingEdit),
+    /// Remove the light being edited, or the last measurement
+    Remove,
+    /// Cycle the colour of the light being edited, or of the node under the cursor in the structure tree
+    CycleColor,
+    Measure,
+    SetMeasurementKind {
+        kind: MeasurementKind,
+    },
+    ExportMeasurements,
+    ToggleSequence,
+    /// Select residues of a chain in the sequence strip, where `start` and `end` may be in either order
+    SelectResidues {
+        chain: usize,
+        start: usize,
+        end: usize,
+    },
+    ScrollSequence {
+        residues: isize,
+        chains: isize,
+    },
+    BrowseStructure,
+    ToggleVisibility,
+    ToggleExpanded,
+    OpenFiles,
+    ShowLog,
+    Color {
+        selection: Selection,
+        color: ratatui::style::Color,
+    },
+    SetVisible {
+        selection: Selection,
+        visible: bool,
+    },
+    /// Move the camera to fit the selected atoms in view
+    ZoomTo {
+        selection: Selection,
+    },
+    SetFieldOfView {
+        degrees: f32,
+    },
 }
 
-/// Return the next action depending on the latest `KeyEvent`
-fn next_action_from_key(key: KeyEvent) -> NextAction {
-    let minor_rotation = std::f32::consts::FRAC_PI_8 / 2.0;
-    let minor_translation = 5.0f32;
-    if key.kind == KeyEventKind::Press {
-        match key.code {
-            KeyCode::Char('q') => NextAction::Quit,
-            KeyCode::Char('l') | KeyCode::Right => NextAction::Translate {
-                x: minor_translation,
-                y: 0.0,
-                z: 0.0,
-            },
-            KeyCode::Char('h') | KeyCode::Left => NextAction::Translate {
-                x: -minor_translation,
-                y: 0.0,
-                z: 0.0,
-            },
-            KeyCode::Char('k') | KeyCode::Up => NextAction::Translate {
-                x: 0.0,
-                y: minor_translation,
-                z: 0.0,
-            },
-            KeyCode::Char('j') | KeyCode::Down => NextAction::Translate {
-                x: 0.0,
-                y: -minor_translation,
-                z: 0.0,
-            },
-            KeyCode::Char('u') => NextAction::Translate {
-                x: 0.0,
-                y: 0.0,
-                z: minor_translation,
-            },
-            KeyCode::Char('d') => NextAction::Translate {
-                x: 0.0,
-                y: 0.0,
-                z: -minor_translation,
-            },
-            KeyCode::Char('H') => NextAction::Rotate {
-                axis: Vector3::y(),
-                angle: -minor_rotation,
-            },
-            KeyCode::Char('L') => NextAction::Rotate {
-                axis: Vector3::y(),
-                angle: minor_rotation,
-            },
-            KeyCode::Char('K') => NextAction::Rotate {
-                axis: Vector3::x(),
-                angle: -minor_rotation,
+/// How far each key press moves or turns the scene, or changes the cell aspect ratio
+const MINOR_ROTATION: f32 = std::f32::consts::FRAC_PI_8 / 2.0;
+const MINOR_TRANSLATION: f32 = 5.0;
+const MINOR_ASPECT_CHANGE: f32 = 0.05;
+
+impl From<KeyAction> for NextAction {
+    fn from(action: KeyAction) -> Self {
+        let translate = |x, y, z| NextAction::Translate { x, y, z };
+        let rotate = |axis, angle| NextAction::Rotate { axis, angle };
+        let measure = |kind| NextAction::SetMeasurementKind { kind };
+        match action {
+            KeyAction::Quit => NextAction::Quit,
+            KeyAction::Help => NextAction::Help,
+            KeyAction::Benchmark => NextAction::Benchmark,
+            KeyAction::Save => NextAction::Save,
+            KeyAction::CycleDithering => NextAction::CycleDithering,
+            KeyAction::CycleSupersampling => NextAction::CycleSupersampling,
+            KeyAction::CycleSamplePattern => NextAction::CycleSamplePattern,
+            KeyAction::ToggleAmbientOcclusion => NextAction::ToggleAmbientOcclusion,
+            KeyAction::ToggleDepthCueing => NextAction::ToggleDepthCueing,
+            KeyAction::CycleShadows => NextAction::CycleShadows,
+            KeyAction::ToggleToon => NextAction::ToggleToon,
+            KeyAction::ToggleOutlines => NextAction::ToggleOutlines,
+            KeyAction::Calibrate => NextAction::Calibrate,
+            KeyAction::EditLighting => NextAction::EditLighting,
+            KeyAction::Measure => NextAction::Measure,
+            KeyAction::ToggleSequence => NextAction::ToggleSequence,
+            KeyAction::BrowseStructure => NextAction::BrowseStructure,
+            KeyAction::OpenFiles => NextAction::OpenFiles,
+            KeyAction::ShowLog => NextAction::ShowLog,
+            KeyAction::Back => NextAction::Back,
+            KeyAction::ZoomOut => translate(0.0, 0.0, -MINOR_TRANSLATION),
+            KeyAction::ZoomIn => translate(0.0, 0.0, MINOR_TRANSLATION),
+            KeyAction::MoveLeft => translate(-MINOR_TRANSLATION, 0.0, 0.0),
+            KeyAction::MoveRight => translate(MINOR_TRANSLATION, 0.0, 0.0),
+            KeyAction::MoveUp => translate(0.0, MINOR_TRANSLATION, 0.0),
+            KeyAction::MoveDown => translate(0.0, -MINOR_TRANSLATION, 0.0),
+            KeyAction::RotateLeft => rotate(Vector3::y(), -MINOR_ROTATION),
+            KeyAction::RotateRight => rotate(Vector3::y(), MINOR_ROTATION),
+            KeyAction::RotateUp => rotate(Vector3::x(), -MINOR_ROTATION),
+            KeyAction::RotateDown => rotate(Vector3::x(), MINOR_ROTATION),
+            KeyAction::IncreaseCellAspect => NextAction::AdjustCellAspect {
+                delta: MINOR_ASPECT_CHANGE,
             },
-            KeyCode::Char('J') => NextAction::Rotate {
-                axis: Vector3::x(),
-                angle: minor_rotation,
+            KeyAction::DecreaseCellAspect => NextAction::AdjustCellAspect {
+                delta: -MINOR_ASPECT_CHANGE,
             },
-            KeyCode::Char('s') => NextAction::Save,
-            KeyCode::Char('?') => NextAction::Help,
-            KeyCode::Char('b') => NextAction::Benchmark,
-            KeyCode::Esc => NextAction::Back,
-            _ => NextAction::Nothing,
+            KeyAction::SelectNext => NextAction::SelectNext,
+            KeyAction::AddLight
+            | KeyAction::CycleLightKind
+            | KeyAction::IncreaseLightIntensity
+            | KeyAction::DecreaseLightIntensity
+            | KeyAction::IncreaseAmbient
+            | KeyAction::DecreaseAmbient
+            | KeyAction::IncreaseSpecular
+            | KeyAction::DecreaseSpecular
+            | KeyAction::IncreaseShininess
+            | KeyAction::DecreaseShininess => action
+                .lighting_edit()
+                .map_or(NextAction::Nothing, NextAction::Light),
+            KeyAction::Remove => NextAction::Remove,
+            KeyAction::CycleColor => NextAction::CycleColor,
+            KeyAction::MeasureDistance => measure(MeasurementKind::Distance),
+            KeyAction::MeasureAngle => measure(MeasurementKind::Angle),
+            KeyAction::MeasureDihedral => measure(MeasurementKind::Dihedral),
+            KeyAction::ExportMeasurements => NextAction::ExportMeasurements,
+            KeyAction::ToggleVisibility => NextAction::ToggleVisibility,
+            KeyAction::ToggleExpanded => NextAction::ToggleExpanded,
         }
-    } else {
-        NextAction::Nothing
     }
 }
 
+/// Return the next action depending on the latest `KeyEvent`
+fn next_action_from_key(keymap: &Keymap, key: KeyEvent) -> NextAction {
+    match keymap.action(key.code) {
+        Some(action) if key.kind == KeyEventKind::Press => action.into(),
+        _ => NextAction::Nothing,
+    }
+}
+
+/// How long input has to stop for before a preview gets refined
+const REFINE_DELAY: Duration = Duration::from_millis(50);
+
+/// Number of frames averaged over for each thread count when benchmarking
+const BENCHMARK_REPEATS: usize = 3;
+
+/// Where screenshots are saved unless a path is given, which is made if it doesn't exist
+const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Smallest allowed ratio of height to width of characters
+const MIN_CELL_ASPECT_RATIO: f32 = 0.25;
+
+/// Width of the panel describing the selected object, including its border
+const INFO_PANEL_WIDTH: u16 = 30;
+
+/// Width of the structure tree, including its border
+const TREE_PANEL_WIDTH: u16 = 32;
+
 pub enum StateWrapper {
     Rendering(App<RenderState>),
     Helping(App<HelpState>),
-    Benchmarking(App<BenchmarkState>),
+    /// Holds the time taken to render a frame for each number of threads
+    Benchmarking(App<BenchmarkState>, Vec<(usize, Duration)>),
+    Calibrating(App<CalibrationState>),
+    /// Holds the index of the light being edited
+    Lighting(App<LightingState>, usize),
+    /// Holds what is being measured and the atoms picked so far
+    Measuring(App<MeasuringState>, MeasurementKind, Vec<ObjectId>),
+    /// Holds which nodes of the structure tree are expanded and where its cursor is
+    Browsing(App<BrowsingState>, TreeView),
+    /// Holds where the file picker is in the filesystem and in the list of loaded files
+    Files(App<FilesState>, FilePicker),
+    /// Holds how many messages the log is scrolled up from the latest
+    Log(App<LogState>, usize),
 }
 
 // Unhappy with how this requires matching every state arm
 impl StateWrapper {
-    pub fn update<R: Rasterizer, S: RayCast + ValidShape>(
+    pub fn update<R: Rasterizer, S: LoadShapes + Sync>(
         mut self,
         canvas: &mut Canvas<R>,
         scene: &mut Scene<S>,
+        notifications: &mut Notifications,
         next_action: NextAction,
     ) -> Self {
+        // Typed commands do the same whatever the state, so they're handled before anything else
+        let Some(next_action) = run_command(next_action, canvas, scene, notifications) else {
+            return self;
+        };
         match self {
-            Self::Rendering(ref mut app) => {
-                match next_action {
-                    NextAction::Rotate { axis, angle } => {
-                        let rotation = UnitQuaternion::from_scaled_axis(axis * angle);
-                        let transform = Isometry3::from_parts(Translation3::identity(), rotation);
-                        scene.transform_shapes(&transform);
-                        canvas.draw_scene_to_canvas(scene);
-                        self
+            Self::Rendering(ref mut app) => match next_action {
+                NextAction::Rotate { axis, angle } => {
+                    let rotation = UnitQuaternion::from_scaled_axis(axis * angle);
+                    let transform = Isometry3::from_parts(Translation3::identity(), rotation);
+                    scene.transform_shapes(&transform);
+                    canvas.draw_scene_preview(scene);
+                    self
+                }
+                NextAction::Translate { x, y, z } => {
+                    let transform = Isometry3::translation(x, y, z);
+                    scene.transform_view(&transform);
+                    canvas.draw_scene_preview(scene);
+                    self
+                }
+                NextAction::Pick { column, row } => {
+                    app.selected = canvas.object_at_cell(column, row);
+                    if let Some(id) = app.selected {
+                        app.sequence.scroll_to(id, scene.sequences());
                     }
-                    NextAction::Translate { x, y, z } => {
-                        let transform = Isometry3::translation(x, y, z);
-                        scene.transform_view(&transform);
+                    self
+                }
+                // Also clears any messages from the bottom row, which stay in the log
+                NextAction::Back => {
+                    app.selected = None;
+                    notifications.dismiss();
+                    if app.sequence.selection.take().is_some() {
+                        scene.set_highlighted([]);
                         canvas.draw_scene_to_canvas(scene);
-                        self
                     }
-                    NextAction::Save => {
-                        let now: DateTime<Local> = Local::now();
-                        let path = format!(
-                            "screenshots/canvas_screenshot_{}.png",
-                            now.format("%Y%m%d_%H%M%S")
-                        );
-                        // TODO Bubble this up to an error popup if something goes wrong
-                        let _ = canvas.save_image(path);
-                        self
+                    self
+                }
+                NextAction::ToggleSequence => {
+                    app.sequence.hidden = !app.sequence.hidden;
+                    self
+                }
+                NextAction::SelectResidues { chain, start, end } => {
+                    let selection = SequenceSelection::new(chain, start, end);
+                    let Some(sequence) = scene.sequences().get(chain) else {
+                        return self;
+                    };
+                    if app.sequence.selection != Some(selection) {
+                        app.sequence.selection = Some(selection);
+                        let ids = sequence.object_ids(selection.start..=selection.end);
+                        scene.set_highlighted(ids);
+                        canvas.draw_scene_preview(scene);
                     }
-                    NextAction::Quit => {
-                        app.should_quit = true;
-                        self
+                    self
+                }
+                NextAction::ScrollSequence { residues, chains } => {
+                    app.sequence.scroll(residues, chains, scene.sequences());
+                    self
+                }
+                NextAction::Save => {
+                    let now: DateTime<Local> = Local::now();
+                    let path = format!(
+                        "{}/canvas_screenshot_{}.png",
+                        SCREENSHOT_DIR,
+                        now.format("%Y%m%d_%H%M%S")
+                    );
+                    match std::fs::create_dir_all(SCREENSHOT_DIR) {
+                        Ok(()) => save_screenshot(canvas, &path, notifications),
+                        Err(error) => notifications
+                            .error(format!("Could not create {}: {}", SCREENSHOT_DIR, error)),
                     }
-                    NextAction::Help => StateWrapper::Helping(App::<HelpState>::from(*app)),
-                    NextAction::Benchmark => {
-                        StateWrapper::Benchmarking(App::<BenchmarkState>::from(*app))
+                    self
+                }
+                NextAction::CycleDithering => {
+                    let dithering = canvas.rasterizer.dithering().next();
+                    canvas.rasterizer.set_dithering(dithering);
+                    canvas.update_frame();
+                    self
+                }
+                NextAction::CycleSupersampling => {
+                    canvas.supersampling = canvas.supersampling.next_samples();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::CycleSamplePattern => {
+                    canvas.supersampling = canvas.supersampling.next_pattern();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::ToggleAmbientOcclusion => {
+                    canvas.shading.toggle_ambient_occlusion();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::ToggleDepthCueing => {
+                    canvas.shading.toggle_depth_cueing();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::CycleShadows => {
+                    canvas.shading.cycle_shadows();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::ToggleToon => {
+                    canvas.shading.toggle_toon();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::ToggleOutlines => {
+                    canvas.shading.toggle_outlines();
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::Quit => {
+                    app.should_quit = true;
+                    self
+                }
+                NextAction::Help => StateWrapper::Helping(App::<HelpState>::from(*app)),
+                NextAction::Benchmark => {
+                    let results = canvas.benchmark_threads(scene, BENCHMARK_REPEATS);
+                    StateWrapper::Benchmarking(App::<BenchmarkState>::from(*app), results)
+                }
+                NextAction::Calibrate => {
+                    StateWrapper::Calibrating(App::<CalibrationState>::from(*app))
+                }
+                NextAction::EditLighting => {
+                    StateWrapper::Lighting(App::<LightingState>::from(*app), 0)
+                }
+                NextAction::Measure => StateWrapper::Measuring(
+                    App::<MeasuringState>::from(*app),
+                    MeasurementKind::default(),
+                    vec![],
+                ),
+                NextAction::BrowseStructure => {
+                    let mut view = TreeView::new(scene);
+                    if let Some(id) = app.selected {
+                        view.reveal(id, scene);
                     }
-                    _ => self,
+                    StateWrapper::Browsing(App::<BrowsingState>::from(*app), view)
                 }
-            }
+                NextAction::ShowLog => StateWrapper::Log(App::<LogState>::from(*app), 0),
+                NextAction::OpenFiles => {
+                    let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                    let mut picker = FilePicker::new(dir, S::can_load);
+                    picker.update_preview();
+                    StateWrapper::Files(App::<FilesState>::from(*app), picker)
+                }
+                _ => self,
+            },
             Self::Helping(ref mut app) => match next_action {
                 NextAction::Quit => {
                     app.should_quit = true;
@@ -172,7 +420,11 @@ impl StateWrapper {
                 NextAction::Back => StateWrapper::Rendering(App::<RenderState>::from(*app)),
                 _ => self,
             },
-            Self::Benchmarking(ref mut app) => match next_action {
+            Self::Benchmarking(ref mut app, ref mut results) => match next_action {
+                NextAction::Benchmark => {
+                    *results = canvas.benchmark_threads(scene, BENCHMARK_REPEATS);
+                    self
+                }
                 NextAction::Quit => {
                     app.should_quit = true;
                     self
@@ -180,6 +432,234 @@ impl StateWrapper {
                 NextAction::Back => StateWrapper::Rendering(App::<RenderState>::from(*app)),
                 _ => self,
             },
+            Self::Calibrating(ref mut app) => match next_action {
+                NextAction::AdjustCellAspect { delta } => {
+                    let cell_aspect_ratio = (scene.scene_projection.cell_aspect_ratio() + delta)
+                        .max(MIN_CELL_ASPECT_RATIO);
+                    scene.set_cell_aspect_ratio(cell_aspect_ratio);
+                    canvas.draw_scene_to_canvas(scene);
+                    self
+                }
+                NextAction::Quit => {
+                    app.should_quit = true;
+                    self
+                }
+                NextAction::Back => StateWrapper::Rendering(App::<RenderState>::from(*app)),
+                _ => self,
+            },
+            Self::Lighting(ref mut app, ref mut selected) => {
+                let edit = match next_action {
+                    // Rotating moves the selected light rather than the shapes
+                    NextAction::Rotate { axis, angle } => {
+                        LightingEdit::Rotate(Rotation3::from_scaled_axis(axis * angle))
+                    }
+                    NextAction::SelectNext => LightingEdit::SelectNext,
+                    NextAction::Light(edit) => edit,
+                    NextAction::Remove => LightingEdit::Remove,
+                    NextAction::CycleColor => LightingEdit::CycleColor,
+                    NextAction::Quit => {
+                        app.should_quit = true;
+                        return self;
+                    }
+                    NextAction::Back => {
+                        return StateWrapper::Rendering(App::<RenderState>::from(*app))
+                    }
+                    _ => return self,
+                };
+                // Point lights start just outside the scene
+                let projection = &scene.scene_projection.perspective;
+                let distance = projection.zfar() - projection.znear();
+                scene.lighting.edit(selected, edit, distance);
+                if edit != LightingEdit::SelectNext {
+                    canvas.draw_scene_to_canvas(scene);
+                }
+                self
+            }
+
+            Self::Measuring(ref mut app, ref mut kind, ref mut picked) => match next_action {
+                NextAction::Rotate { axis, angle } => {
+                    let rotation = UnitQuaternion::from_scaled_axis(axis * angle);
+                    let transform = Isometry3::from_parts(Translation3::identity(), rotation);
+                    scene.transform_shapes(&transform);
+                    canvas.draw_scene_preview(scene);
+                    self
+                }
+                NextAction::Translate { x, y, z } => {
+                    let transform = Isometry3::translation(x, y, z);
+                    scene.transform_view(&transform);
+                    canvas.draw_scene_preview(scene);
+                    self
+                }
+                NextAction::Pick { column, row } => {
+                    // Only atoms can be measured between
+                    let Some(id) = canvas
+                        .object_at_cell(column, row)
+                        .filter(|id| scene.atom(*id).is_some())
+                    else {
+                        return self;
+                    };
+                    app.selected = Some(id);
+                    app.sequence.scroll_to(id, scene.sequences());
+                    if !picked.contains(&id) {
+                        picked.push(id);
+                    }
+                    if picked.len() == kind.atom_count() {
+                        let atoms: Vec<_> = picked
+                            .drain(..)
+                            .filter_map(|id| Some((id, scene.atom(id)?)))
+                            .collect();
+                        if let Some(measurement) = Measurement::new(*kind, &atoms) {
+                            scene.measurements.push(measurement);
+                        }
+                    }
+                    self
+                }
+                NextAction::SetMeasurementKind { kind: new_kind } => {
+                    *kind = new_kind;
+                    picked.clear();
+                    self
+                }
+                // Undo the last pick before removing whole measurements
+                NextAction::Remove => {
+                    if picked.pop().is_none() {
+                        scene.measurements.pop();
+                    }
+                    self
+                }
+                NextAction::ExportMeasurements => {
+                    match measurement::export_csv(&scene.measurements) {
+                        Ok(path) => {
+                            notifications.info(format!("Exported measurements to {}.", path))
+                        }
+                        Err(error) => {
+                            notifications.error(format!("Could not export measurements: {}", error))
+                        }
+                    }
+                    self
+                }
+                NextAction::ToggleSequence => {
+                    app.sequence.hidden = !app.sequence.hidden;
+                    self
+                }
+                NextAction::ScrollSequence { residues, chains } => {
+                    app.sequence.scroll(residues, chains, scene.sequences());
+                    self
+                }
+                NextAction::Quit => {
+                    app.should_quit = true;
+                    self
+                }
+                NextAction::Back => StateWrapper::Rendering(App::<RenderState>::from(*app)),
+                _ => self,
+            },
+            Self::Browsing(ref mut app, ref mut view) => {
+                match next_action {
+                    NextAction::Rotate { axis, angle } => {
+                        let rotation = UnitQuaternion::from_scaled_axis(axis * angle);
+                        let transform = Isometry3::from_parts(Translation3::identity(), rotation);
+                        scene.transform_shapes(&transform);
+                        canvas.draw_scene_preview(scene);
+                    }
+                    // Moving steps through the tree rather than the view, but zooming still works
+                    NextAction::Translate { z, .. } if z != 0.0 => {
+                        scene.transform_view(&Isometry3::translation(0.0, 0.0, z));
+                        canvas.draw_scene_preview(scene);
+                    }
+                    NextAction::Translate { x, y, .. } => {
+                        if y > 0.0 {
+                            view.move_cursor(-1, scene);
+                        } else if y < 0.0 {
+                            view.move_cursor(1, scene);
+                        } else if x > 0.0 {
+                            view.expand(scene);
+                        } else if x < 0.0 {
+                            view.collapse(scene);
+                        }
+                    }
+                    NextAction::ToggleExpanded => view.toggle_expanded(scene),
+                    NextAction::Pick { column, row } => {
+                        app.selected = canvas.object_at_cell(column, row);
+                        if let Some(id) = app.selected {
+                            app.sequence.scroll_to(id, scene.sequences());
+                            view.reveal(id, scene);
+                        }
+                    }
+                    NextAction::ToggleVisibility => {
+                        view.toggle_visibility(scene);
+                        canvas.draw_scene_to_canvas(scene);
+                    }
+                    NextAction::CycleColor => {
+                        view.cycle_color(scene);
+                        canvas.draw_scene_to_canvas(scene);
+                    }
+                    NextAction::ToggleSequence => app.sequence.hidden = !app.sequence.hidden,
+                    NextAction::ScrollSequence { residues, chains } => {
+                        app.sequence.scroll(residues, chains, scene.sequences());
+                    }
+                    NextAction::Quit => app.should_quit = true,
+                    NextAction::Back => {
+                        return StateWrapper::Rendering(App::<RenderState>::from(*app))
+                    }
+                    _ => {}
+                }
+                self
+            }
+            Self::Files(ref mut app, ref mut picker) => {
+                match next_action {
+                    NextAction::Translate { y, .. } if y != 0.0 => {
+                        picker.move_cursor(if y > 0.0 { -1 } else { 1 }, scene.files().len());
+                    }
+                    NextAction::Translate { x, .. }
+                        if x < 0.0 && picker.focus == PickerFocus::Browse =>
+                    {
+                        picker.parent()
+                    }
+                    NextAction::Translate { x, .. } if x > 0.0 => {
+                        open_picked(picker, canvas, scene, notifications)
+                    }
+                    NextAction::ToggleExpanded => open_picked(picker, canvas, scene, notifications),
+                    NextAction::SelectNext => picker.toggle_focus(),
+                    NextAction::Remove if picker.focus == PickerFocus::Loaded => {
+                        let Some((index, file)) = picker
+                            .loaded_file(&scene.files())
+                            .map(|(index, file)| (index, file.to_string()))
+                        else {
+                            return self;
+                        };
+                        scene.remove_file(index);
+                        // Anything picked may have been removed, and the rest have moved
+                        app.selected = None;
+                        app.sequence.selection = None;
+                        app.sequence.scroll(0, 0, scene.sequences());
+                        scene.set_highlighted([]);
+                        canvas.draw_scene_to_canvas(scene);
+                        notifications.info(format!("Removed {}.", file));
+                    }
+                    NextAction::Quit => app.should_quit = true,
+                    NextAction::Back => {
+                        return StateWrapper::Rendering(App::<RenderState>::from(*app))
+                    }
+                    _ => {}
+                }
+                picker.update_preview();
+                self
+            }
+            Self::Log(ref mut app, ref mut scroll) => {
+                match next_action {
+                    NextAction::Translate { y, .. } if y > 0.0 => {
+                        *scroll = (*scroll + 1).min(notifications.log().len().saturating_sub(1));
+                    }
+                    NextAction::Translate { y, .. } if y < 0.0 => {
+                        *scroll = scroll.saturating_sub(1);
+                    }
+                    NextAction::Quit => app.should_quit = true,
+                    NextAction::Back => {
+                        return StateWrapper::Rendering(App::<RenderState>::from(*app))
+                    }
+                    _ => {}
+                }
+                self
+            }
         }
     }
 
@@ -187,24 +667,66 @@ impl StateWrapper {
         match self {
             Self::Rendering(app) => app.should_quit,
             Self::Helping(app) => app.should_quit,
-            Self::Benchmarking(app) => app.should_quit,
+            Self::Benchmarking(app, _) => app.should_quit,
+            Self::Calibrating(app) => app.should_quit,
+            Self::Lighting(app, _) => app.should_quit,
+            Self::Measuring(app, ..) => app.should_quit,
+            Self::Browsing(app, _) => app.should_quit,
+            Self::Files(app, _) => app.should_quit,
+            Self::Log(app, _) => app.should_quit,
+        }
+    }
+
+    pub fn selected(&self) -> Option<ObjectId> {
+        match self {
+            Self::Rendering(app) => app.selected,
+            Self::Helping(app) => app.selected,
+            Self::Benchmarking(app, _) => app.selected,
+            Self::Calibrating(app) => app.selected,
+            Self::Lighting(app, _) => app.selected,
+            Self::Measuring(app, ..) => app.selected,
+            Self::Browsing(app, _) => app.selected,
+            Self::Files(app, _) => app.selected,
+            Self::Log(app, _) => app.selected,
+        }
+    }
+
+    pub fn sequence(&self) -> SequenceCursor {
+        match self {
+            Self::Rendering(app) => app.sequence,
+            Self::Helping(app) => app.sequence,
+            Self::Benchmarking(app, _) => app.sequence,
+            Self::Calibrating(app) => app.sequence,
+            Self::Lighting(app, _) => app.sequence,
+            Self::Measuring(app, ..) => app.sequence,
+            Self::Browsing(app, _) => app.sequence,
+            Self::Files(app, _) => app.sequence,
+            Self::Log(app, _) => app.sequence,
         }
     }
 
-    pub fn ui<R: Rasterizer, S: RayCast + ValidShape>(
+    /// Area of the terminal that the scene is rendered to, leaving room for the sequence strip, structure tree and
+    /// selection panel
+    pub fn render_area<S: RayCast + ValidShape>(&self, area: Rect, scene: &Scene<S>) -> Rect {
+        render_area(
+            area,
+            self.selected().is_some(),
+            self.sequence().height(scene.sequences()),
+            matches!(self, Self::Browsing(..)),
+        )
+    }
+
+    pub fn ui<R: Rasterizer, S: RayCast + ValidShape + Sync>(
         &self,
         canvas: &mut Canvas<R>,
         scene: &mut Scene<S>,
+        frame_cache: &mut FrameCache,
+        keymap: &Keymap,
+        notifications: &Notifications,
         frame: &mut Frame,
     ) {
-        // TODO Once line colour issue is fixed, change this back to be the whole screen
         let area = frame.size();
-        let render_area = Rect {
-            x: area.x,
-            y: area.y,
-            width: area.width,
-            height: area.height - 1,
-        };
+        let render_area = self.render_area(area, scene);
 
         let area_changed = (render_area.width as usize != canvas.render_width())
             || (render_area.height as usize != canvas.render_height());
@@ -214,9 +736,51 @@ impl StateWrapper {
             canvas.draw_scene_to_canvas(scene);
         }
 
-        // FIXME This is currently rendering the entirety even if it's not changing, so probably using a lot of CPU
-        let widget = widget_from_frame_buffer(&canvas.frame_buffer);
-        frame.render_widget(widget, render_area);
+        frame_cache.render(canvas, render_area, frame.buffer_mut());
+        let picked = match self {
+            Self::Measuring(_, _, picked) => &picked[..],
+            _ => &[],
+        };
+        draw_measurements(scene, picked, render_area, frame.buffer_mut());
+
+        let sequence_area = Rect {
+            height: render_area.y - area.y,
+            ..area
+        };
+        if !sequence_area.is_empty() {
+            let panel = SequencePanel {
+                sequences: scene.sequences(),
+                cursor: self.sequence(),
+                picked: self.selected(),
+            };
+            frame.render_widget(panel, sequence_area);
+        }
+
+        if let Self::Browsing(_, view) = self {
+            let tree_area = Rect {
+                x: area.x,
+                y: render_area.y,
+                width: render_area.x - area.x,
+                height: render_area.height,
+            };
+            frame.render_widget(TreePanel { view, scene }, tree_area);
+        }
+
+        if let Some(selected) = self.selected() {
+            let panel_area = Rect {
+                x: render_area.right(),
+                y: render_area.y,
+                width: area.right().saturating_sub(render_area.right()),
+                height: render_area.height,
+            };
+            let popup = Popup::default()
+                .content(info_lines(scene, selected, keymap))
+                .style(Style::new().black())
+                .title("Selection")
+                .title_style(Style::new().bold())
+                .border_style(Style::new().red());
+            frame.render_widget(popup, panel_area);
+        }
 
         match self {
             // TODO Move these functions into implementations of App
@@ -228,26 +792,32 @@ impl StateWrapper {
                     height: area.height / 2,
                 };
 
-                // TODO Move this to constant in another module
-                let help_text = vec![
-                    Line::from("q:      Quit the application."),
-                    Line::from("b:      Benchmark rendering."),
-                    Line::from("s:      Save screenshot."),
-                    Line::from("<Esc>:  Back."),
+                let mut help_text = keymap.help_lines();
+                help_text.extend([
                     Line::from(""),
-                    Line::from("d:      Zoom out."),
-                    Line::from("u:      Zoom in."),
+                    Line::from(":       Command line."),
                     Line::from(""),
-                    Line::from("h:      Move left."),
-                    Line::from("l:      Move right."),
-                    Line::from("k:      Move up."),
-                    Line::from("j:      Move down."),
+                    Line::from("Drag:         Rotate."),
+                    Line::from("Shift-drag:   Move."),
+                    Line::from("Middle-drag:  Move."),
+                    Line::from("Scroll:       Zoom."),
+                    Line::from("Click:        Select atom."),
                     Line::from(""),
-                    Line::from("H:      Rotate left."),
-                    Line::from("L:      Rotate right."),
-                    Line::from("K:      Rotate up."),
-                    Line::from("J:      Rotate down."),
-                ];
+                    Line::from("Drag sequence:         Select residues."),
+                    Line::from("Shift-click sequence:  Extend selection."),
+                    Line::from("Scroll sequence:       Scroll along chains."),
+                    Line::from("Shift-scroll sequence: Scroll through chains."),
+                    Line::from(""),
+                    Line::from(":load <path>            Add a PDB or OBJ file."),
+                    Line::from(":save [path]            Save screenshot."),
+                    Line::from(":color <sel> <colour>   Colour atoms."),
+                    Line::from(":hide <sel>, :show <sel>"),
+                    Line::from(":zoom [sel]             Fit atoms in view."),
+                    Line::from(":set fov <degrees>      Field of view."),
+                    Line::from(":q                      Quit."),
+                    Line::from("<sel>: all, chain A, resn HOH, resi 10-20, name CA, elem FE"),
+                    Line::from("<Tab>: Complete.  <Up>/<Down>: History."),
+                ]);
 
                 // TODO Work out how to properly align key and description
                 // TODO Work out how to colour keys differently to description
@@ -268,30 +838,295 @@ impl StateWrapper {
                 }
                 .clamp(area);
                 // TODO Work out how to avoid whole line being coloured the same
-                let text = Text::raw("Press ? for help.")
-                    .style(Style::new().red())
-                    .alignment(ratatui::layout::Alignment::Right);
+                let text = Text::raw(format!(
+                    "Press {} for help.",
+                    keymap.keys_text(&[KeyAction::Help])
+                ))
+                .style(Style::new().red())
+                .alignment(ratatui::layout::Alignment::Right);
                 frame.render_widget(text, bottom);
             }
-            Self::Benchmarking(_) => {
-                // TODO Make this not spam numbers
-                let now = Instant::now();
-                canvas.draw_scene_to_canvas(scene);
-                let new_now = Instant::now();
-                let frame_time = new_now.duration_since(now);
+            Self::Benchmarking(_, results) => {
+                let single_thread_time = results.first().map(|(_, t)| *t);
+                let mut lines = vec![Line::from(format!(
+                    "Rendering {} * {} scene:",
+                    render_area.width, render_area.height
+                ))];
+                lines.extend(results.iter().map(|(threads, frame_time)| {
+                    let speedup = single_thread_time
+                        .map_or(1.0, |t| t.as_secs_f32() / frame_time.as_secs_f32());
+                    Line::from(format!(
+                        "{:>3} threads: {:>10.2?} (x{:.1})",
+                        threads, frame_time, speedup
+                    ))
+                }));
+                lines.push(Line::from(format!(
+                    "Press {} to rerun.",
+                    keymap.keys_text(&[KeyAction::Benchmark])
+                )));
                 let popup_area = Rect {
                     x: area.width / 4,
                     y: area.height / 4,
                     width: area.width / 2,
-                    height: 3,
+                    height: lines.len() as u16 + 2,
+                }
+                .clamp(area);
+                let popup = Popup::default()
+                    .content(lines)
+                    .style(Style::new().black())
+                    .title("Benchmark")
+                    .title_style(Style::new().bold())
+                    .border_style(Style::new().red());
+                frame.render_widget(popup, popup_area);
+            }
+            Self::Calibrating(_) => {
+                let popup_area = Rect {
+                    x: area.width / 4,
+                    y: area.height / 8,
+                    width: area.width / 2,
+                    height: area.height * 3 / 4,
                 };
+                let cell_aspect_ratio = scene.scene_projection.cell_aspect_ratio();
                 let popup = Popup::default()
-                    .content(format!(
-                        "Rendering {} * {} scene took {:?}",
-                        area.width, area.height, frame_time
+                    .content(vec![
+                        Line::from(format!("Character aspect ratio: {:.2}", cell_aspect_ratio)),
+                        Line::from(format!(
+                            "Press {} until the circle is round, then {}.",
+                            keymap.keys_text(&[
+                                KeyAction::IncreaseCellAspect,
+                                KeyAction::DecreaseCellAspect
+                            ]),
+                            keymap.keys_text(&[KeyAction::Back])
+                        )),
+                    ])
+                    .style(Style::new().black())
+                    .title("Calibration")
+                    .title_style(Style::new().bold())
+                    .border_style(Style::new().red());
+                frame.render_widget(popup, popup_area);
+
+                // Drawn separately, since the popup trims leading whitespace
+                let circle_area = Rect {
+                    x: popup_area.x + 1,
+                    y: popup_area.y + 3,
+                    width: popup_area.width.saturating_sub(2),
+                    height: popup_area.height.saturating_sub(4),
+                };
+                let circle =
+                    calibration_circle(circle_area.width, circle_area.height, cell_aspect_ratio);
+                frame.render_widget(Paragraph::new(circle).red(), circle_area);
+            }
+            Self::Lighting(_, selected) => {
+                let lighting = &scene.lighting;
+                let mut lines: Vec<Line> = lighting
+                    .lights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, light)| {
+                        let marker = if i == *selected { ">" } else { "-" };
+                        Line::from(format!(
+                            "{} {:<11} {:<5} {:.2}",
+                            marker,
+                            light.kind_name(),
+                            light.color_name(),
+                            light.intensity()
+                        ))
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    lines.push(Line::from("No lights."));
+                }
+                lines.extend([
+                    Line::from(""),
+                    Line::from(format!(
+                        "Ambient {:.2}  Specular {:.2}  Shininess {:.0}",
+                        lighting.ambient, lighting.specular, lighting.shininess
+                    )),
+                    Line::from(""),
+                    keymap.help_line(&[KeyAction::SelectNext], "Select next light."),
+                    keymap.help_line(&[KeyAction::AddLight], "Add light."),
+                    keymap.help_line(&[KeyAction::Remove], "Remove light."),
+                    keymap.help_line(&[KeyAction::CycleLightKind], "Cycle light type."),
+                    keymap.help_line(&[KeyAction::CycleColor], "Cycle light colour."),
+                    keymap.help_line(
+                        &[
+                            KeyAction::DecreaseLightIntensity,
+                            KeyAction::IncreaseLightIntensity,
+                        ],
+                        "Light intensity.",
+                    ),
+                    keymap.help_line(
+                        &[KeyAction::DecreaseAmbient, KeyAction::IncreaseAmbient],
+                        "Ambient.",
+                    ),
+                    keymap.help_line(
+                        &[KeyAction::DecreaseSpecular, KeyAction::IncreaseSpecular],
+                        "Specular strength.",
+                    ),
+                    keymap.help_line(
+                        &[KeyAction::DecreaseShininess, KeyAction::IncreaseShininess],
+                        "Shininess.",
+                    ),
+                    keymap.help_line(
+                        &[
+                            KeyAction::RotateLeft,
+                            KeyAction::RotateDown,
+                            KeyAction::RotateUp,
+                            KeyAction::RotateRight,
+                        ],
+                        "Move light.",
+                    ),
+                    keymap.help_line(&[KeyAction::Back], "Back."),
+                ]);
+                let popup_area = Rect {
+                    x: 0,
+                    y: 0,
+                    width: (area.width / 3).max(48),
+                    height: lines.len() as u16 + 2,
+                }
+                .clamp(area);
+                let popup = Popup::default()
+                    .content(lines)
+                    .style(Style::new().black())
+                    .title("Lighting")
+                    .title_style(Style::new().bold())
+                    .border_style(Style::new().red());
+                frame.render_widget(popup, popup_area);
+            }
+            Self::Measuring(_, kind, picked) => {
+                let mut lines = vec![
+                    Line::from(format!(
+                        "Measuring {}: picked {} of {} atoms.",
+                        kind.name(),
+                        picked.len(),
+                        kind.atom_count()
+                    )),
+                    Line::from(""),
+                ];
+                lines.extend(scene.measurements.iter().enumerate().map(|(i, m)| {
+                    Line::from(format!(
+                        "{:>2} {:<8} {:>9}  {}",
+                        i + 1,
+                        m.kind.name(),
+                        m.value_text(),
+                        m.labels.join(" ")
                     ))
+                }));
+                if scene.measurements.is_empty() {
+                    lines.push(Line::from("No measurements."));
+                }
+                lines.extend([
+                    Line::from(""),
+                    Line::from("Click:  Pick atom."),
+                    keymap.help_line(
+                        &[
+                            KeyAction::MeasureDistance,
+                            KeyAction::MeasureAngle,
+                            KeyAction::MeasureDihedral,
+                        ],
+                        "Distance, angle, dihedral.",
+                    ),
+                    keymap.help_line(&[KeyAction::Remove], "Remove last pick or measurement."),
+                    keymap.help_line(&[KeyAction::ExportMeasurements], "Export as CSV."),
+                    keymap.help_line(&[KeyAction::Back], "Back."),
+                ]);
+                let popup_area = Rect {
+                    x: 0,
+                    y: 0,
+                    width: (area.width / 2).max(60),
+                    height: lines.len() as u16 + 2,
+                }
+                .clamp(area);
+                let popup = Popup::default()
+                    .content(lines)
                     .style(Style::new().black())
-                    .title("Benchmark")
+                    .title("Measurements")
+                    .title_style(Style::new().bold())
+                    .border_style(Style::new().red());
+                frame.render_widget(popup, popup_area);
+            }
+            Self::Browsing(..) => {
+                let bottom = Rect {
+                    x: 0,
+                    y: area.height - 1,
+                    width: area.width,
+                    height: 1,
+                }
+                .clamp(area);
+                // Only the first key of each action, to keep the hint on one row
+                let keys = |actions: &[KeyAction]| {
+                    let keys: Vec<String> = actions
+                        .iter()
+                        .filter_map(|action| keymap.keys(*action).first().copied())
+                        .map(key_name)
+                        .collect();
+                    keys.join("/")
+                };
+                let text = Text::raw(format!(
+                    "{}: Move  {}: Collapse/expand  {}: Show/hide  {}: Colour  {}: Back",
+                    keys(&[KeyAction::MoveDown, KeyAction::MoveUp]),
+                    keys(&[KeyAction::MoveLeft, KeyAction::MoveRight]),
+                    keys(&[KeyAction::ToggleVisibility]),
+                    keys(&[KeyAction::CycleColor]),
+                    keys(&[KeyAction::Back]),
+                ))
+                .style(Style::new().red())
+                .alignment(ratatui::layout::Alignment::Right);
+                frame.render_widget(text, bottom);
+            }
+            Self::Files(_, picker) => {
+                let popup_area = Rect {
+                    x: area.width / 8,
+                    y: area.height / 8,
+                    width: area.width * 3 / 4,
+                    height: area.height * 3 / 4,
+                };
+                let hints = vec![
+                    keymap.help_line(&[KeyAction::MoveUp, KeyAction::MoveDown], "Move."),
+                    keymap.help_line(
+                        &[KeyAction::MoveRight, KeyAction::ToggleExpanded],
+                        "Open or load.",
+                    ),
+                    keymap.help_line(&[KeyAction::MoveLeft], "Parent directory."),
+                    keymap.help_line(&[KeyAction::SelectNext], "Switch list."),
+                    keymap.help_line(&[KeyAction::Remove], "Remove loaded file."),
+                    keymap.help_line(&[KeyAction::Back], "Back."),
+                ];
+                let popup = FilePickerPopup {
+                    picker,
+                    files: scene.files(),
+                    hints,
+                };
+                frame.render_widget(popup, popup_area);
+            }
+            Self::Log(_, scroll) => {
+                let popup_area = Rect {
+                    x: area.width / 8,
+                    y: area.height / 8,
+                    width: area.width * 3 / 4,
+                    height: area.height * 3 / 4,
+                };
+                // The latest messages are at the bottom, above the keys
+                let height = popup_area.height.saturating_sub(4) as usize;
+                let log = notifications.log();
+                let end = log.len().saturating_sub(*scroll);
+                let mut lines: Vec<Line> = log[end.saturating_sub(height)..end]
+                    .iter()
+                    .map(|notification| notification.log_line())
+                    .collect();
+                if log.is_empty() {
+                    lines.push(Line::from("No messages."));
+                }
+                lines.resize(height, Line::from(""));
+                lines.push(Line::from(format!(
+                    "{}: Scroll  {}: Back",
+                    keymap.keys_text(&[KeyAction::MoveUp, KeyAction::MoveDown]),
+                    keymap.keys_text(&[KeyAction::Back])
+                )));
+                let popup = Popup::default()
+                    .content(lines)
+                    .style(Style::new().black())
+                    .title("Messages")
                     .title_style(Style::new().bold())
                     .border_style(Style::new().red());
                 frame.render_widget(popup, popup_area);
@@ -300,6 +1135,294 @@ impl StateWrapper {
     }
 }
 
+/// Load the file under the cursor of the file picker, or enter the directory under it
+fn open_picked<R: Rasterizer, S: LoadShapes + Sync>(
+    picker: &mut FilePicker,
+    canvas: &mut Canvas<R>,
+    scene: &mut Scene<S>,
+    notifications: &mut Notifications,
+) {
+    if picker.focus != PickerFocus::Browse {
+        return;
+    }
+    if let Some(path) = picker.open() {
+        load_file(&path, canvas, scene, notifications);
+    }
+}
+
+/// Carry out an action typed into the command line, handing back any other action for the current state
+fn run_command<R: Rasterizer, S: LoadShapes + Sync>(
+    next_action: NextAction,
+    canvas: &mut Canvas<R>,
+    scene: &mut Scene<S>,
+    notifications: &mut Notifications,
+) -> Option<NextAction> {
+    match next_action {
+        NextAction::SaveAs { path } => save_screenshot(canvas, &path, notifications),
+        NextAction::Load { path } => load_file(Path::new(&path), canvas, scene, notifications),
+        NextAction::Color { selection, color } => {
+            for id in scene.select(&selection) {
+                scene.set_color(id, color);
+            }
+            canvas.draw_scene_to_canvas(scene);
+        }
+        NextAction::SetVisible { selection, visible } => {
+            for id in scene.select(&selection) {
+                scene.set_visible(id, visible);
+                // Atoms can't be shown while the rest of their chain is hidden
+                if visible {
+                    let shape = ObjectId {
+                        shape: id.shape,
+                        sub_shape: None,
+                    };
+                    scene.set_visible(shape, true);
+                }
+            }
+            canvas.draw_scene_to_canvas(scene);
+        }
+        NextAction::ZoomTo { selection } => {
+            scene.zoom_to(&scene.select(&selection));
+            canvas.draw_scene_to_canvas(scene);
+        }
+        NextAction::SetFieldOfView { degrees } => {
+            scene.set_field_of_view(degrees.to_radians());
+            canvas.draw_scene_to_canvas(scene);
+        }
+        _ => return Some(next_action),
+    }
+    None
+}
+
+/// Add a file to the scene, saying whether it worked along with any warnings about the file
+fn load_file<R: Rasterizer, S: LoadShapes + Sync>(
+    path: &Path,
+    canvas: &mut Canvas<R>,
+    scene: &mut Scene<S>,
+    notifications: &mut Notifications,
+) {
+    match scene.load_file(path) {
+        Ok(warnings) => {
+            canvas.draw_scene_to_canvas(scene);
+            notifications.info(format!("Loaded {}.", path.display()));
+            for warning in warnings {
+                notifications.warn(format!("{}: {}", path.display(), warning));
+            }
+        }
+        Err(error) => notifications.error(error.to_string()),
+    }
+}
+
+fn save_screenshot<R: Rasterizer>(
+    canvas: &Canvas<R>,
+    path: &str,
+    notifications: &mut Notifications,
+) {
+    match canvas.save_image(path) {
+        Ok(()) => notifications.info(format!("Saved screenshot to {}.", path)),
+        Err(error) => notifications.error(format!("Could not save {}: {}", path, error)),
+    }
+}
+
+/// Description of a picked object, giving the details of the atom if it was read from a PDB file
+fn info_lines<S: RayCast + ValidShape>(
+    scene: &Scene<S>,
+    id: ObjectId,
+    keymap: &Keymap,
+) -> Vec<Line<'static>> {
+    let mut lines = match scene.atom(id) {
+        Some(atom) => vec![
+            Line::from(format!("Atom:      {} ({})", atom.name, atom.serial_number)),
+            Line::from(format!("Element:   {}", atom.element)),
+            Line::from(format!(
+                "Residue:   {} {}",
+                atom.residue_name, atom.residue_number
+            )),
+            Line::from(format!("Chain:     {}", atom.chain)),
+            Line::from(format!("B-factor:  {:.2}", atom.b_factor)),
+            Line::from(format!("Occupancy: {:.2}", atom.occupancy)),
+        ],
+        None => {
+            let mut lines = vec![Line::from(format!("Shape:     {}", id.shape))];
+            if let Some(sub_shape) = id.sub_shape {
+                lines.push(Line::from(format!("Part:      {}", sub_shape)));
+            }
+            lines
+        }
+    };
+    lines.extend([
+        Line::from(""),
+        keymap.help_line(&[KeyAction::Back], "Clear."),
+    ]);
+    lines
+}
+
+/// Draw dashed lines between the atoms of each measurement over the canvas, labelled with the value
+/// Atoms picked for a measurement that isn't finished yet are marked with a cross
+fn draw_measurements<S: RayCast + ValidShape>(
+    scene: &Scene<S>,
+    picked: &[ObjectId],
+    render_area: Rect,
+    buf: &mut Buffer,
+) {
+    let style = Style::new().yellow().bold();
+    let to_cell = |id: &ObjectId| {
+        let clip = scene.project_point(&scene.atom_position(*id)?)?;
+        Some(clip_to_cell(clip.x, clip.y, render_area))
+    };
+    for measurement in scene.measurements.iter() {
+        // Skip measurements with any atom behind the camera
+        let Some(cells) = measurement
+            .atoms
+            .iter()
+            .map(to_cell)
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        for pair in cells.windows(2) {
+            draw_dashed_line(pair[0], pair[1], style, render_area, buf);
+        }
+        // Centred between the atoms, which is inside the angle for angles
+        let count = cells.len() as i32;
+        let label = measurement.value_text();
+        let column = cells.iter().map(|c| c.0).sum::<i32>() / count - label.len() as i32 / 2;
+        let row = cells.iter().map(|c| c.1).sum::<i32>() / count;
+        if row >= render_area.top() as i32 && row < render_area.bottom() as i32 {
+            let column = column.clamp(render_area.left() as i32, render_area.right() as i32 - 1);
+            let width = render_area.right() as usize - column as usize;
+            buf.set_stringn(column as u16, row as u16, label, width, style);
+        }
+    }
+    for cell in picked.iter().filter_map(to_cell) {
+        set_overlay_cell(cell, '+', style, render_area, buf);
+    }
+}
+
+/// Terminal cell containing a point in the 2D projection of clip space, which may be outside of the area
+fn clip_to_cell(x_clip: f32, y_clip: f32, render_area: Rect) -> (i32, i32) {
+    let column = (x_clip + 1.0) / 2.0 * render_area.width as f32;
+    let row = (1.0 - y_clip) / 2.0 * render_area.height as f32;
+    (
+        render_area.x as i32 + column.floor() as i32,
+        render_area.y as i32 + row.floor() as i32,
+    )
+}
+
+/// Draw every other pair of cells along a line, leaving the cells at either end free for the atoms
+fn draw_dashed_line(
+    from: (i32, i32),
+    to: (i32, i32),
+    style: Style,
+    render_area: Rect,
+    buf: &mut Buffer,
+) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs());
+    // Lines from atoms far outside the area would take too long to step along
+    if steps > 4 * (render_area.width as i32 + render_area.height as i32) {
+        return;
+    }
+    // Rows increase down the screen
+    let symbol = if dx.abs() > 2 * dy.abs() {
+        '-'
+    } else if dy.abs() > 2 * dx.abs() {
+        '|'
+    } else if (dx > 0) == (dy > 0) {
+        '\\'
+    } else {
+        '/'
+    };
+    for step in (1..steps).filter(|step| step % 4 < 2) {
+        let t = step as f32 / steps as f32;
+        let cell = (
+            from.0 + (t * dx as f32).round() as i32,
+            from.1 + (t * dy as f32).round() as i32,
+        );
+        set_overlay_cell(cell, symbol, style, render_area, buf);
+    }
+}
+
+fn set_overlay_cell(
+    cell: (i32, i32),
+    symbol: char,
+    style: Style,
+    render_area: Rect,
+    buf: &mut Buffer,
+) {
+    let (Ok(x), Ok(y)) = (u16::try_from(cell.0), u16::try_from(cell.1)) else {
+        return;
+    };
+    if render_area.contains(Position { x, y }) {
+        buf.get_mut(x, y).set_char(symbol).set_style(style);
+    }
+}
+
+/// Lines of text making up a filled circle, which only looks round if the character aspect ratio is correct
+fn calibration_circle(width: u16, height: u16, cell_aspect_ratio: f32) -> Vec<Line<'static>> {
+    // Distances are measured in character widths
+    let radius = (width as f32).min(height as f32 * cell_aspect_ratio) / 2.0;
+    (0..height)
+        .map(|row| {
+            let dy = (row as f32 + 0.5 - height as f32 / 2.0) * cell_aspect_ratio;
+            (0..width)
+                .map(|column| {
+                    let dx = column as f32 + 0.5 - width as f32 / 2.0;
+                    if dx * dx + dy * dy <= radius * radius {
+                        '█'
+                    } else {
+                        ' '
+                    }
+                })
+                .collect::<String>()
+                .into()
+        })
+        .collect()
+}
+
+/// Area of the terminal that the scene is rendered to
+/// Leaves room on the right for the panel describing the selected object, if there is one, on the left for the
+/// structure tree, if it is shown, and above for the sequence strip, which takes `sequence_height` rows
+fn render_area(area: Rect, info_panel: bool, sequence_height: u16, tree_panel: bool) -> Rect {
+    let panel_width = if info_panel {
+        INFO_PANEL_WIDTH.min(area.width / 2)
+    } else {
+        0
+    };
+    let tree_width = if tree_panel {
+        TREE_PANEL_WIDTH.min(area.width / 3)
+    } else {
+        0
+    };
+    // TODO Once line colour issue is fixed, change this back to be the whole screen
+    // Always leave some of the canvas showing
+    let sequence_height = sequence_height.min(area.height / 2);
+    Rect {
+        x: area.x + tree_width,
+        y: area.y + sequence_height,
+        width: area.width - panel_width - tree_width,
+        height: area.height.saturating_sub(1 + sequence_height),
+    }
+}
+
+/// The frame buffer already turned into terminal cells, so that the widget is only rebuilt when the canvas changes
+#[derive(Debug, Default)]
+pub struct FrameCache {
+    generation: Option<u64>,
+    buffer: Buffer,
+}
+
+impl FrameCache {
+    /// Copy the canvas into the buffer, rebuilding the cells first if the canvas or area changed
+    pub fn render<R: Rasterizer>(&mut self, canvas: &Canvas<R>, area: Rect, buf: &mut Buffer) {
+        if self.generation != Some(canvas.generation()) || self.buffer.area != area {
+            self.buffer = Buffer::empty(area);
+            widget_from_frame_buffer(&canvas.frame_buffer).render(area, &mut self.buffer);
+            self.generation = Some(canvas.generation());
+        }
+        buf.merge(&self.buffer);
+    }
+}
+
 /// Returns a widget which correctly colours each pixel individually
 pub fn widget_from_frame_buffer(frame_buffer: &[ColoredChar]) -> impl Widget {
     let lines: Vec<Line> = frame_buffer
@@ -316,7 +1439,7 @@ pub fn widget_from_frame_buffer(frame_buffer: &[ColoredChar]) -> impl Widget {
 
 /// Perform shutdown of terminal
 pub fn shutdown() -> Result<()> {
-    stdout().execute(LeaveAlternateScreen)?;
+    execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen)?;
     disable_raw_mode()?;
     Ok(())
 }
@@ -324,47 +1447,223 @@ pub fn shutdown() -> Result<()> {
 /// Start the terminal
 pub fn startup() -> Result<()> {
     enable_raw_mode()?;
-    execute!(std::io::stderr(), EnterAlternateScreen)?;
+    execute!(std::io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
     Ok(())
 }
 
-pub fn run<Q: AsRef<str> + AsRef<Path>>(pdb_files: Vec<Q>) -> Result<()> {
-    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-    terminal.clear()?;
-
-    let mut app = StateWrapper::Rendering(App::<RenderState>::default());
-    // let mut canvas = Canvas::<FancyAsciiRasterizer>::default();
-    let mut canvas = Canvas::<BasicAsciiRasterizer>::default();
+/// Run the TUI, where `cell_aspect_ratio` is the ratio of height to width of characters
+/// Starts on the calibration screen if `calibrate` is set
+pub fn run<Q: AsRef<str> + AsRef<Path>>(
+    pdb_files: Vec<Q>,
+    output_mode: OutputMode,
+    cell_aspect_ratio: f32,
+    calibrate: bool,
+    keymap: Keymap,
+) -> Result<()> {
+    let app = if calibrate {
+        StateWrapper::Calibrating(App::<CalibrationState>::default())
+    } else {
+        StateWrapper::Rendering(App::<RenderState>::default())
+    };
 
-    // TODO Decide on format depending on filetype
+    // PDB files are drawn atom by atom, so that atoms can be picked, and anything else is read as meshes
+    let is_pdb = |path: &Q| {
+        AsRef::<Path>::as_ref(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pdb"))
+    };
+    // Problems with the files are shown once the viewer starts, rather than stopping it
+    let mut notifications = Notifications::default();
+    if !pdb_files.is_empty() && pdb_files.iter().all(is_pdb) {
+        let mut scene = Scene::<Compound>::default();
+        for path in pdb_files.iter() {
+            let file = AsRef::<str>::as_ref(path);
+            match scene.load_shapes_from_pdb(path) {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        notifications.warn(format!("{}: {}", file, warning));
+                    }
+                }
+                Err(error) => notifications.error(error.to_string()),
+            }
+        }
+        run_with_scene(
+            app,
+            scene,
+            output_mode,
+            cell_aspect_ratio,
+            keymap,
+            notifications,
+        )
+    } else {
+        let mut scene = Scene::<TriMesh>::default();
+        for path in pdb_files.iter() {
+            if let Err(error) = scene.load_meshes_from_path(path) {
+                notifications.error(error.to_string());
+            }
+        }
+        run_with_scene(
+            app,
+            scene,
+            output_mode,
+            cell_aspect_ratio,
+            keymap,
+            notifications,
+        )
+    }
+}
 
-    let mut scene = Scene::<TriMesh>::default();
-    // scene.load_meshes_from_path(test_obj);
+/// Prepare the scene, then run the event loop with a canvas suiting the output mode
+fn run_with_scene<S: LoadShapes + Sync>(
+    app: StateWrapper,
+    mut scene: Scene<S>,
+    output_mode: OutputMode,
+    cell_aspect_ratio: f32,
+    keymap: Keymap,
+    notifications: Notifications,
+) -> Result<()> {
+    scene.recolor();
+    scene.shapes_to_center();
+    scene.set_cell_aspect_ratio(cell_aspect_ratio);
 
-    // let mut scene = Scene::<Compound>::default();
-    for path in pdb_files.iter() {
-        // scene.load_shapes_from_pdb(path)
-        // scene.load_meshes_from_path("./data/surface.obj");
-        scene.load_meshes_from_path(path);
+    match output_mode.protocol() {
+        Some(protocol) => {
+            let canvas = Canvas::<GraphicsRasterizer>::default();
+            run_with_canvas(
+                app,
+                canvas,
+                scene,
+                Some(GraphicsBackend::new(protocol)),
+                keymap,
+                notifications,
+            )
+        }
+        None => {
+            // let canvas = Canvas::<FancyAsciiRasterizer>::default();
+            let canvas = Canvas::<BasicAsciiRasterizer>::default();
+            run_with_canvas(app, canvas, scene, None, keymap, notifications)
+        }
     }
-    scene.recolor();
+}
+
+/// Event loop, drawing the scene using real pixels on top of the blank frame if there is a graphics backend
+fn run_with_canvas<R: Rasterizer, S: LoadShapes + Sync>(
+    mut app: StateWrapper,
+    mut canvas: Canvas<R>,
+    mut scene: Scene<S>,
+    mut graphics: Option<GraphicsBackend>,
+    keymap: Keymap,
+    mut notifications: Notifications,
+) -> Result<()> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    terminal.clear()?;
 
-    scene.shapes_to_center();
     canvas.draw_scene_to_canvas(&scene);
+    let mut frame_cache = FrameCache::default();
+    let mut mouse_drag = MouseDrag::default();
+    let mut sequence_drag = SequenceDrag::default();
+    let mut command_line = CommandLine::default();
+    // Only redraw when something has changed, so that an idle viewer doesn't use any CPU
+    let mut dirty = true;
 
     // TODO Make all of this async
     loop {
-        terminal.draw(|frame| app.ui(&mut canvas, &mut scene, frame))?;
+        if dirty {
+            // Sixel images are cleared by drawing blank cells over them, so this has to come before any popups
+            if let Some(graphics) = graphics.as_mut() {
+                if !matches!(app, StateWrapper::Rendering(_)) {
+                    graphics.clear(terminal.backend_mut())?;
+                }
+            }
+            let area = terminal
+                .draw(|frame| {
+                    app.ui(
+                        &mut canvas,
+                        &mut scene,
+                        &mut frame_cache,
+                        &keymap,
+                        &notifications,
+                        frame,
+                    );
+                    let area = frame.size();
+                    let bottom = Rect {
+                        y: area.bottom().saturating_sub(1),
+                        height: area.height.min(1),
+                        ..area
+                    };
+                    frame.render_widget(Toast(&notifications), bottom);
+                    frame.render_widget(CommandPrompt(&command_line), bottom);
+                })?
+                .area;
+
+            // Popups can't be drawn on top of images, so the image is only shown while there are none
+            if let Some(graphics) = graphics.as_mut() {
+                if let StateWrapper::Rendering(_) = app {
+                    graphics.draw(
+                        terminal.backend_mut(),
+                        canvas.to_rgba_image(),
+                        app.render_area(area, &scene),
+                    )?;
+                }
+            }
+            dirty = false;
+        }
+
+        // Refine the preview once input stops, giving up as soon as there is more input
+        if canvas.needs_refinement() && !event::poll(REFINE_DELAY)? {
+            let input_waiting = || event::poll(Duration::ZERO).unwrap_or(true);
+            dirty = canvas.refine_scene_on_canvas(&scene, &input_waiting);
+            continue;
+        }
+
+        // Clear messages from the bottom row once they have been shown for long enough
+        if let Some(time_left) = notifications.time_left() {
+            if !event::poll(time_left)? {
+                dirty = true;
+                continue;
+            }
+        }
 
-        if event::poll(std::time::Duration::from_millis(3))? {
-            if let event::Event::Key(key) = event::read()? {
-                let next_action = next_action_from_key(key);
-                app = app.update(&mut canvas, &mut scene, next_action);
+        // Blocks until the next event, since nothing changes in between
+        match event::read()? {
+            event::Event::Key(key) => {
+                let next_action = if command_line.wants(&key) {
+                    // The command being typed is shown as it changes
+                    dirty = true;
+                    command_line.next_action(key, &scene)
+                } else {
+                    let dismissed = command_line.dismiss_error();
+                    let next_action = next_action_from_key(&keymap, key);
+                    dirty = dismissed || !matches!(next_action, NextAction::Nothing);
+                    next_action
+                };
+                app = app.update(&mut canvas, &mut scene, &mut notifications, next_action);
                 if app.should_quit() {
                     break;
                 }
             }
+            event::Event::Mouse(mouse) => {
+                let size = terminal.size()?;
+                let area = app.render_area(size, &scene);
+                let sequence_area = Rect {
+                    height: area.y - size.y,
+                    ..size
+                };
+                let next_action = if sequence_drag.wants(&mouse, sequence_area) {
+                    let cursor = app.sequence();
+                    sequence_drag.next_action(mouse, sequence_area, &cursor, scene.sequences())
+                } else {
+                    mouse_drag.next_action(mouse, area, &scene)
+                };
+                dirty = !matches!(next_action, NextAction::Nothing);
+                app = app.update(&mut canvas, &mut scene, &mut notifications, next_action);
+            }
+            event::Event::Resize(_, _) => dirty = true,
+            _ => {}
         }
     }
+    if let Some(graphics) = graphics.as_mut() {
+        graphics.clear(terminal.backend_mut())?;
+    }
     Ok(())
 }